@@ -0,0 +1,70 @@
+//! Compares cloning and dropping `speedy_refs::BiasedArc` with `speedy_refs::Arc`,
+//! `std::sync::Arc` and, as the floor for the owner thread, `speedy_refs::Rc`.
+//!
+//! Run with `cargo +nightly bench --bench arc`.
+#![feature(test)]
+extern crate test;
+
+use test::{black_box, Bencher};
+
+const N: usize = 1000;
+
+macro_rules! clone_drop {
+    ($name:ident, $new:expr) => {
+        #[bench]
+        fn $name(b: &mut Bencher) {
+            let ptr = $new(1u64);
+            b.iter(|| {
+                for _ in 0..N {
+                    drop(black_box(black_box(&ptr).clone()));
+                }
+            })
+        }
+    };
+}
+
+clone_drop!(rc_clone_drop, speedy_refs::Rc::new);
+clone_drop!(std_arc_clone_drop, std::sync::Arc::new);
+clone_drop!(speedy_arc_clone_drop, speedy_refs::Arc::new);
+clone_drop!(biased_arc_clone_drop, speedy_refs::BiasedArc::new);
+
+macro_rules! many_clones {
+    ($name:ident, $new:expr) => {
+        #[bench]
+        fn $name(b: &mut Bencher) {
+            let ptr = $new(1u64);
+            b.iter(|| {
+                let clones: Vec<_> = (0..N).map(|_| black_box(&ptr).clone()).collect();
+                clones.iter().map(|clone| **clone).sum::<u64>()
+            })
+        }
+    };
+}
+
+many_clones!(rc_many_clones, speedy_refs::Rc::new);
+many_clones!(std_arc_many_clones, std::sync::Arc::new);
+many_clones!(speedy_arc_many_clones, speedy_refs::Arc::new);
+many_clones!(biased_arc_many_clones, speedy_refs::BiasedArc::new);
+
+macro_rules! clone_drop_elsewhere {
+    ($name:ident, $new:expr) => {
+        /// Clones and drops on a thread that did not create the value.
+        #[bench]
+        fn $name(b: &mut Bencher) {
+            let ptr = $new(1u64);
+            std::thread::scope(|scope| {
+                scope.spawn(|| {
+                    b.iter(|| {
+                        for _ in 0..N {
+                            drop(black_box(black_box(&ptr).clone()));
+                        }
+                    })
+                });
+            });
+        }
+    };
+}
+
+clone_drop_elsewhere!(std_arc_clone_drop_elsewhere, std::sync::Arc::new);
+clone_drop_elsewhere!(speedy_arc_clone_drop_elsewhere, speedy_refs::Arc::new);
+clone_drop_elsewhere!(biased_arc_clone_drop_elsewhere, speedy_refs::BiasedArc::new);
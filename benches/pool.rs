@@ -0,0 +1,118 @@
+//! Compares creating and dropping `Rc`s and `Arc`s through `speedy_refs::RcPool` and
+//! `speedy_refs::ArcPool` with plain `speedy_refs::Rc::new`, `speedy_refs::Arc::new` and their
+//! `std` counterparts.
+//!
+//! `ArcPool` pays off when many pointers are alive at once, as in the `batch` benchmarks. When a
+//! single block is freed and allocated again, as in the `churn` benchmarks, the fast path of the
+//! global allocator is about as cheap, and most of the time goes to the atomic counts of the `Arc`.
+//!
+//! Run with `cargo +nightly bench --bench pool`.
+#![feature(test)]
+extern crate test;
+
+use speedy_refs::{ArcPool, PooledRc, RcPool};
+use test::{black_box, Bencher};
+
+const N: usize = 1000;
+
+macro_rules! churn {
+    ($name:ident, $setup:expr, $new:expr) => {
+        /// Creates and drops one pointer at a time.
+        #[bench]
+        fn $name(b: &mut Bencher) {
+            #[allow(unused_variables)]
+            let pool = $setup;
+            b.iter(|| {
+                for i in 0..N {
+                    drop(black_box($new(&pool, [i as u64; 4])));
+                }
+            })
+        }
+    };
+}
+
+churn!(std_rc_churn, (), |_, val| std::rc::Rc::new(val));
+churn!(rc_churn, (), |_, val| speedy_refs::Rc::new(val));
+churn!(rc_pool_churn, RcPool::new(), RcPool::alloc);
+churn!(std_arc_churn, (), |_, val| std::sync::Arc::new(val));
+churn!(arc_churn, (), |_, val| speedy_refs::Arc::new(val));
+churn!(arc_pool_churn, ArcPool::new(), ArcPool::alloc);
+
+macro_rules! batch {
+    ($name:ident, $setup:expr, $new:expr) => {
+        /// Creates `N` pointers that are all alive at once, then drops them.
+        #[bench]
+        fn $name(b: &mut Bencher) {
+            #[allow(unused_variables)]
+            let pool = $setup;
+            b.iter(|| {
+                let batch: Vec<_> = (0..N).map(|i| $new(&pool, [i as u64; 4])).collect();
+                black_box(batch)
+            })
+        }
+    };
+}
+
+batch!(std_rc_batch, (), |_, val| std::rc::Rc::new(val));
+batch!(rc_batch, (), |_, val| speedy_refs::Rc::new(val));
+batch!(rc_pool_batch, RcPool::new(), RcPool::alloc);
+batch!(std_arc_batch, (), |_, val| std::sync::Arc::new(val));
+batch!(arc_batch, (), |_, val| speedy_refs::Arc::new(val));
+batch!(arc_pool_batch, ArcPool::new(), ArcPool::alloc);
+
+/// A syntax tree node, the kind of value a parser churns through.
+enum Node<P> {
+    Leaf(u64),
+    Branch(P, P),
+}
+
+fn sum<P: std::ops::Deref<Target = Node<P>>>(node: &Node<P>) -> u64 {
+    match node {
+        Node::Leaf(val) => *val,
+        Node::Branch(lhs, rhs) => sum(lhs) + sum(rhs),
+    }
+}
+
+fn build<P>(depth: u32, new: &mut impl FnMut(Node<P>) -> P) -> P {
+    if depth == 0 {
+        new(Node::Leaf(1))
+    } else {
+        let lhs = build(depth - 1, new);
+        let rhs = build(depth - 1, new);
+        new(Node::Branch(lhs, rhs))
+    }
+}
+
+/// The depth of the trees, for `2^10 - 1` nodes each.
+const DEPTH: u32 = 9;
+
+struct Plain(speedy_refs::Rc<Node<Plain>>);
+
+impl std::ops::Deref for Plain {
+    type Target = Node<Plain>;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+struct Pooled<'p>(PooledRc<'p, Node<Pooled<'p>>>);
+
+impl<'p> std::ops::Deref for Pooled<'p> {
+    type Target = Node<Pooled<'p>>;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Builds, walks and drops a whole tree.
+#[bench]
+fn rc_tree(b: &mut Bencher) {
+    b.iter(|| sum(&build(DEPTH, &mut |node| Plain(speedy_refs::Rc::new(node)))))
+}
+
+/// Builds, walks and drops a whole tree.
+#[bench]
+fn rc_pool_tree(b: &mut Bencher) {
+    let pool = RcPool::new();
+    b.iter(|| sum(&build(DEPTH, &mut |node| Pooled(pool.alloc(node)))))
+}
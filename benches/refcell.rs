@@ -0,0 +1,135 @@
+//! Compares `speedy_refs::RefCell` with `std::cell::RefCell`.
+//!
+//! In release builds the short lived borrows of both cells compile to the same instructions, a
+//! check of the flag and an access to the value, since the increment of the flag and its
+//! decrement on drop cancel out. They tie. The unchecked borrows skip the check and its load of
+//! the flag, and are the ones that beat std.
+//!
+//! Every iteration accesses another element of an array, so that the loops are bound by
+//! throughput rather than by one long chain of stores and loads of the same address, whose
+//! timing mostly depends on where the compiler happened to spill `black_box`.
+//! Each measured loop is also a function of its own aligned to a cache line. Identical loops
+//! placed at different offsets can otherwise differ by 2x on some CPUs, e.g. when a jump
+//! straddles a 32 byte boundary.
+//!
+//! Run with `cargo +nightly bench --bench refcell`.
+#![feature(test)]
+#![feature(fn_align)]
+extern crate test;
+
+use test::{black_box, Bencher};
+
+const N: usize = 1000;
+const LEN: usize = 64;
+
+#[inline(never)]
+#[rustc_align(64)]
+fn std_borrow_loop(cell: &std::cell::RefCell<[u64; LEN]>) -> u64 {
+    let mut sum = 0;
+    for i in 0..N {
+        sum += black_box(cell).borrow()[i % LEN];
+    }
+    sum
+}
+
+#[bench]
+fn std_borrow(b: &mut Bencher) {
+    let cell = Box::new(std::cell::RefCell::new([1u64; LEN]));
+    b.iter(|| std_borrow_loop(&cell))
+}
+
+#[inline(never)]
+#[rustc_align(64)]
+fn speedy_borrow_loop(cell: &speedy_refs::RefCell<[u64; LEN]>) -> u64 {
+    let mut sum = 0;
+    for i in 0..N {
+        sum += black_box(cell).borrow()[i % LEN];
+    }
+    sum
+}
+
+#[bench]
+fn speedy_borrow(b: &mut Bencher) {
+    let cell = Box::new(speedy_refs::RefCell::new([1u64; LEN]));
+    b.iter(|| speedy_borrow_loop(&cell))
+}
+
+#[inline(never)]
+#[rustc_align(64)]
+fn speedy_borrow_unchecked_loop(cell: &speedy_refs::RefCell<[u64; LEN]>) -> u64 {
+    let mut sum = 0;
+    for i in 0..N {
+        sum += unsafe { black_box(cell).borrow_unchecked() }[i % LEN];
+    }
+    sum
+}
+
+#[bench]
+fn speedy_borrow_unchecked(b: &mut Bencher) {
+    let cell = Box::new(speedy_refs::RefCell::new([1u64; LEN]));
+    b.iter(|| speedy_borrow_unchecked_loop(&cell))
+}
+
+#[inline(never)]
+#[rustc_align(64)]
+fn std_borrow_mut_loop(cell: &std::cell::RefCell<[u64; LEN]>) {
+    for i in 0..N {
+        black_box(cell).borrow_mut()[i % LEN] = i as u64;
+    }
+}
+
+#[bench]
+fn std_borrow_mut(b: &mut Bencher) {
+    let cell = Box::new(std::cell::RefCell::new([1u64; LEN]));
+    b.iter(|| std_borrow_mut_loop(&cell))
+}
+
+#[inline(never)]
+#[rustc_align(64)]
+fn speedy_borrow_mut_loop(cell: &speedy_refs::RefCell<[u64; LEN]>) {
+    for i in 0..N {
+        black_box(cell).borrow_mut()[i % LEN] = i as u64;
+    }
+}
+
+#[bench]
+fn speedy_borrow_mut(b: &mut Bencher) {
+    let cell = Box::new(speedy_refs::RefCell::new([1u64; LEN]));
+    b.iter(|| speedy_borrow_mut_loop(&cell))
+}
+
+#[inline(never)]
+#[rustc_align(64)]
+fn speedy_borrow_mut_unchecked_loop(cell: &speedy_refs::RefCell<[u64; LEN]>) {
+    for i in 0..N {
+        unsafe { black_box(cell).borrow_mut_unchecked()[i % LEN] = i as u64 };
+    }
+}
+
+#[bench]
+fn speedy_borrow_mut_unchecked(b: &mut Bencher) {
+    let cell = Box::new(speedy_refs::RefCell::new([1u64; LEN]));
+    b.iter(|| speedy_borrow_mut_unchecked_loop(&cell))
+}
+
+/// Keeps `N` readers alive at once, so that the flag is really written.
+/// Most of the time goes to the `Vec` holding the readers.
+#[bench]
+fn std_many_readers(b: &mut Bencher) {
+    let cell = std::cell::RefCell::new(1u64);
+    b.iter(|| {
+        let cell = black_box(&cell);
+        let readers: Vec<_> = (0..N).map(|_| cell.borrow()).collect();
+        readers.iter().map(|r| **r).sum::<u64>()
+    })
+}
+
+#[bench]
+fn speedy_many_readers(b: &mut Bencher) {
+    let cell = speedy_refs::RefCell::new(1u64);
+    b.iter(|| {
+        let cell = black_box(&cell);
+        let readers: Vec<_> = (0..N).map(|_| cell.borrow()).collect();
+        readers.iter().map(|r| **r).sum::<u64>()
+    })
+}
@@ -1,15 +1,93 @@
-pub struct Arc<T> {
-    inner: *mut Inner<T>,
+use std::alloc::{AllocError, Allocator, Global};
+use std::marker::PhantomData;
+use std::mem::MaybeUninit;
+use std::ptr::NonNull;
+
+use crate::finalizer::{self, Finalizer, HAS_FINALIZER};
+use crate::sync::atomic::{self, AtomicUsize, Ordering};
+
+/// A soft limit on the amount of references that may be made to an `Arc`.
+///
+/// Going above this limit will abort the program, as `std::sync::Arc` does.
+const MAX_REFCOUNT: usize = isize::MAX as usize;
+
+/// A soft limit on the amount of `Weak` pointers to an `Arc`, far enough below `HAS_FINALIZER`
+/// that the weak count never carries into it.
+const MAX_WEAK: usize = HAS_FINALIZER / 2;
+
+/// The value the weak count is set to while `Arc::is_unique` holds it locked.
+const WEAK_LOCKED: usize = usize::MAX;
+
+/// The strong count of a value that is never dropped, like the one in a [`StaticArc`].
+///
+/// It is far above `MAX_REFCOUNT`, so clones and drops count it like any other value without
+/// checking for it first. Both then find a count above `MAX_REFCOUNT` and undo their change in a
+/// cold branch, so the count only strays from `IMMORTAL` by the clones and drops in flight.
+const IMMORTAL: usize = usize::MAX / 4 * 3;
+
+/// The weak count of an immortal value, which `Weak` pointers to it never touch.
+/// Unlike `IMMORTAL` it leaves `HAS_FINALIZER` clear.
+const IMMORTAL_WEAK: usize = MAX_WEAK;
+
+/// Strong counts above this belong to immortal values. Those between `MAX_REFCOUNT` and this
+/// are counts that overflowed.
+const IMMORTAL_MIN: usize = MAX_REFCOUNT + (IMMORTAL - MAX_REFCOUNT) / 2;
+
+/// # speedy_refs::Arc
+/// `Arc<T>` is a thread-safe reference-counted pointer type that allows multiple shared references
+/// to a value of type `T` from different threads.
+///
+/// # Implementation
+/// The `Arc<T>` type is implemented as a thin wrapper around a raw pointer to an `Inner<T>` struct,
+/// which contains the value of type `T`, an atomic strong count and an atomic weak count.
+/// - The value is dropped when the last `Arc` is dropped.
+/// - The memory of `Inner` is deallocated when the last `Arc` and the last [`Weak`] are both dropped.
+///
+/// # Allocators
+/// `Arc::new` allocates from the global allocator, while `Arc::new_in` and `Arc::try_new_in`
+/// take any [`Allocator`]. The allocator is stored in the `Arc` and its `Weak`s, and whichever
+/// of them is dropped last uses it to free the allocation.
+///
+/// # Weak References
+/// `Arc::downgrade` creates a [`Weak<T>`] that does not keep the value alive and can be atomically
+/// upgraded back to an `Arc` with `Weak::upgrade` as long as the value has not been dropped.
+///
+/// # Examples
+///
+/// ```
+/// use speedy_refs::Arc;
+/// use std::thread;
+///
+/// let value = Arc::new(42);
+///
+/// let handles: Vec<_> = (0..4)
+///     .map(|_| {
+///         let value = Arc::clone(&value);
+///         thread::spawn(move || assert_eq!(*value, 42))
+///     })
+///     .collect();
+///
+/// for handle in handles {
+///     handle.join().unwrap();
+/// }
+///
+/// assert_eq!(Arc::strong_count(&value), 1);
+/// ```
+pub struct Arc<T: ?Sized, A: Allocator = Global> {
+    inner: NonNull<Inner<T>>,
+    // Tells the drop check that an `Arc` owns, and may drop, an `Inner<T>`
+    _marker: PhantomData<Inner<T>>,
+    alloc: A,
 }
 
-impl<T> Clone for Arc<T> {
+impl<T: ?Sized, A: Allocator + Clone> Clone for Arc<T, A> {
     #[inline(always)]
     fn clone(&self) -> Self {
-        unsafe {
-            let inner = self.inner.clone();
-            inner.as_mut().unwrap().increment_count();
-
-            Self { inner }
+        self.inner().increment_count();
+        Self {
+            inner: self.inner,
+            _marker: PhantomData,
+            alloc: self.alloc.clone(),
         }
     }
 }
@@ -17,52 +95,1460 @@ impl<T> Clone for Arc<T> {
 impl<T> Arc<T> {
     pub fn new(data: T) -> Self {
         let res = Inner::new(data).into_ptr();
-        Self { inner: res }
+        Self {
+            inner: res,
+            _marker: PhantomData,
+            alloc: Global,
+        }
+    }
+
+    /// Creates a new `Arc`, or returns an error instead of aborting if the allocation fails.
+    /// The value is dropped in that case.
+    pub fn try_new(data: T) -> Result<Self, AllocError> {
+        Arc::try_new_in(data, Global)
+    }
+
+    /// Creates a new `Arc` whose value is left uninitialised, to be written in place before
+    /// `Arc::assume_init` is called and the `Arc` is shared.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::Arc;
+    ///
+    /// let mut arc = Arc::<[u32; 1024]>::new_uninit();
+    /// let counts = Arc::get_mut(&mut arc).unwrap();
+    /// unsafe { counts.as_mut_ptr().cast::<u32>().write_bytes(0xff, 1024) };
+    /// let counts = unsafe { arc.assume_init() };
+    ///
+    /// let handle = std::thread::spawn(move || counts[1023]);
+    /// assert_eq!(handle.join().unwrap(), u32::MAX);
+    /// ```
+    pub fn new_uninit() -> Arc<MaybeUninit<T>> {
+        Arc::try_new_uninit().unwrap_or_else(|_| Inner::<MaybeUninit<T>>::alloc_error())
+    }
+
+    /// Creates a new `Arc` whose value is left uninitialised, or returns an error if the
+    /// allocation fails.
+    pub fn try_new_uninit() -> Result<Arc<MaybeUninit<T>>, AllocError> {
+        Ok(Arc {
+            inner: Inner::try_alloc_uninit(&Global, false)?,
+            _marker: PhantomData,
+            alloc: Global,
+        })
+    }
+
+    /// Creates a new `Arc` whose value is filled with `0` bytes, which may or may not be a valid `T`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::Arc;
+    /// use std::sync::atomic::{AtomicU64, Ordering};
+    ///
+    /// // All zeroes is a valid value for atomics
+    /// let histogram = unsafe { Arc::<[AtomicU64; 4096]>::new_zeroed().assume_init() };
+    /// histogram[42].fetch_add(1, Ordering::Relaxed);
+    /// assert_eq!(histogram.iter().map(|bucket| bucket.load(Ordering::Relaxed)).sum::<u64>(), 1);
+    /// ```
+    pub fn new_zeroed() -> Arc<MaybeUninit<T>> {
+        Arc::try_new_zeroed().unwrap_or_else(|_| Inner::<MaybeUninit<T>>::alloc_error())
+    }
+
+    /// Creates a new `Arc` whose value is filled with `0` bytes, or returns an error if the
+    /// allocation fails.
+    pub fn try_new_zeroed() -> Result<Arc<MaybeUninit<T>>, AllocError> {
+        Ok(Arc {
+            inner: Inner::try_alloc_uninit(&Global, true)?,
+            _marker: PhantomData,
+            alloc: Global,
+        })
+    }
+
+    /// Creates a new `Arc` that calls `finalizer` with the value when the last `Arc` to it is dropped,
+    /// on whichever thread that happens, right before the value itself is dropped.
+    ///
+    /// The finalizer is not called if the value is taken out with `Arc::try_unwrap` or
+    /// `Arc::into_inner`. `Arc::make_mut` keeps it with the value when it moves it, but not
+    /// when it clones it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::Arc;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// static RELEASED_BYTES: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// let frame = Arc::new_with_finalizer(vec![0u8; 1024], |frame| {
+    ///     RELEASED_BYTES.fetch_add(frame.len(), Ordering::Relaxed);
+    /// });
+    /// let sender = frame.clone();
+    /// drop(frame);
+    /// std::thread::spawn(move || drop(sender)).join().unwrap();
+    /// assert_eq!(RELEASED_BYTES.load(Ordering::Relaxed), 1024);
+    /// ```
+    pub fn new_with_finalizer(data: T, finalizer: impl FnOnce(&mut T) + Send + 'static) -> Self
+    where
+        T: 'static,
+    {
+        let finalizer = Finalizer::new(finalizer);
+        Self {
+            inner: Inner::new(data).into_ptr_with_finalizer_in(finalizer, &Global),
+            _marker: PhantomData,
+            alloc: Global,
+        }
     }
 }
 
-impl<T> std::ops::Deref for Arc<T> {
+impl<T, A: Allocator> Arc<MaybeUninit<T>, A> {
+    /// Turns an `Arc` from `Arc::new_uninit` or `Arc::new_zeroed` into an `Arc` to the value
+    /// it has been initialised with.
+    ///
+    /// # Safety
+    /// The value must have been initialised to a valid `T`.
+    pub unsafe fn assume_init(self) -> Arc<T, A> {
+        let this = std::mem::ManuallyDrop::new(self);
+        Arc {
+            inner: this.inner.cast(),
+            _marker: PhantomData,
+            alloc: std::ptr::read(&this.alloc),
+        }
+    }
+}
+
+impl<T, A: Allocator> Arc<T, A> {
+    /// Creates a new `Arc` in memory from `alloc`.
+    ///
+    /// # Panics
+    /// Calls `std::alloc::handle_alloc_error` if `alloc` fails to allocate.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// #![feature(allocator_api)]
+    /// use speedy_refs::Arc;
+    /// use std::alloc::System;
+    ///
+    /// let arc = Arc::new_in(String::from("request"), System);
+    /// let clone = Arc::clone(&arc);
+    /// std::thread::spawn(move || assert_eq!(*clone, "request")).join().unwrap();
+    /// ```
+    #[inline]
+    pub fn new_in(data: T, alloc: A) -> Self {
+        Self {
+            inner: Inner::new(data).into_ptr_in(&alloc),
+            _marker: PhantomData,
+            alloc,
+        }
+    }
+
+    /// Creates a new `Arc` in memory from `alloc`, or returns an error if `alloc` fails to allocate.
+    /// The value is dropped in that case.
+    pub fn try_new_in(data: T, alloc: A) -> Result<Self, AllocError> {
+        Ok(Self {
+            inner: Inner::new(data).try_into_ptr_in(&alloc)?,
+            _marker: PhantomData,
+            alloc,
+        })
+    }
+
+    /// Returns a mutable reference to the value, cloning it into a new allocation first
+    /// if other `Arc` pointers share it.
+    ///
+    /// If only `Weak` pointers share the allocation, the value is moved into a new allocation
+    /// instead and those `Weak` pointers are disassociated from it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::Arc;
+    ///
+    /// let mut data = Arc::new(5);
+    /// *Arc::make_mut(&mut data) += 1;
+    ///
+    /// let other = Arc::clone(&data);
+    /// *Arc::make_mut(&mut data) += 1; // clones the value
+    ///
+    /// assert_eq!(*data, 7);
+    /// assert_eq!(*other, 6);
+    /// ```
+    pub fn make_mut(this: &mut Self) -> &mut T
+    where
+        T: Clone,
+        A: Clone,
+    {
+        let inner = this.inner();
+        // Acquire so that we observe all writes made before other strong pointers were released.
+        if inner
+            .counts
+            .strong
+            .compare_exchange(1, 0, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            // Other strong pointers exist, so we must clone the value
+            *this = Arc::new_in(T::clone(this), this.alloc.clone());
+        } else if inner.counts.weak.load(Ordering::Relaxed) & !HAS_FINALIZER != 1 {
+            // We were the only strong pointer but weak pointers remain. The strong count is now 0,
+            // so they can no longer upgrade and we can move the value out to a fresh allocation.
+            let old = Weak {
+                inner: this.inner,
+                alloc: &this.alloc,
+            };
+            let data = unsafe { std::ptr::read(&(*this.inner.as_ptr()).ptr) };
+            // The value keeps its finalizer in its new allocation
+            this.inner = match unsafe { Inner::take_finalizer(old.inner.as_ptr()) } {
+                Some(finalizer) => {
+                    Inner::new(data).into_ptr_with_finalizer_in(finalizer, &this.alloc)
+                }
+                None => Inner::new(data).into_ptr_in(&this.alloc),
+            };
+            // Releases the implicit weak reference of the old allocation
+            drop(old);
+        } else {
+            // We were the sole reference of either kind; bump the strong count back up.
+            inner.counts.strong.store(1, Ordering::Release);
+        }
+
+        unsafe { &mut (*this.inner.as_ptr()).ptr }
+    }
+
+    /// Returns the inner value if the `Arc` has exactly one strong reference.
+    ///
+    /// Otherwise the same `Arc` is returned in the `Err` variant.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::Arc;
+    ///
+    /// let x = Arc::new(3);
+    /// assert_eq!(Arc::try_unwrap(x).ok(), Some(3));
+    ///
+    /// let x = Arc::new(4);
+    /// let _y = Arc::clone(&x);
+    /// assert_eq!(*Arc::try_unwrap(x).err().unwrap(), 4);
+    /// ```
+    pub fn try_unwrap(this: Self) -> Result<T, Self> {
+        if this
+            .inner()
+            .counts
+            .strong
+            .compare_exchange(1, 0, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+        {
+            return Err(this);
+        }
+
+        atomic::fence(Ordering::Acquire);
+
+        let this = std::mem::ManuallyDrop::new(this);
+        let data = unsafe { std::ptr::read(&(*this.inner.as_ptr()).ptr) };
+        // Releases the implicit weak reference held by the strong pointers
+        drop(Weak {
+            inner: this.inner,
+            alloc: unsafe { std::ptr::read(&this.alloc) },
+        });
+        Ok(data)
+    }
+
+    /// Returns the inner value if this is the last `Arc` to it, otherwise drops this `Arc`
+    /// and returns `None`.
+    ///
+    /// Unlike `Arc::try_unwrap(this).ok()`, when several threads call `into_inner` on clones of
+    /// the same `Arc` exactly one of them is guaranteed to get the value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::Arc;
+    ///
+    /// let x = Arc::new(3);
+    /// let y = Arc::clone(&x);
+    ///
+    /// assert_eq!(Arc::into_inner(x), None);
+    /// assert_eq!(Arc::into_inner(y), Some(3));
+    /// ```
+    pub fn into_inner(this: Self) -> Option<T> {
+        let this = std::mem::ManuallyDrop::new(this);
+
+        if unsafe { Inner::decrement_count(this.inner) } != 1 {
+            return None;
+        }
+
+        atomic::fence(Ordering::Acquire);
+
+        let data = unsafe { std::ptr::read(&(*this.inner.as_ptr()).ptr) };
+        drop(Weak {
+            inner: this.inner,
+            alloc: unsafe { std::ptr::read(&this.alloc) },
+        });
+        Some(data)
+    }
+}
+
+impl<T: ?Sized, A: Allocator> Arc<T, A> {
+    /// Creates a new [`Weak`] pointer to the value of this `Arc`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::Arc;
+    ///
+    /// let five = Arc::new(5);
+    /// let weak_five = Arc::downgrade(&five);
+    ///
+    /// assert_eq!(*weak_five.upgrade().unwrap(), 5);
+    /// ```
+    pub fn downgrade(this: &Self) -> Weak<T, A>
+    where
+        A: Clone,
+    {
+        let inner = this.inner();
+        if inner.counts.is_immortal() {
+            return Weak {
+                inner: this.inner,
+                alloc: this.alloc.clone(),
+            };
+        }
+
+        let mut cur = inner.counts.weak.load(Ordering::Relaxed);
+        loop {
+            // Spin while `is_unique` holds the weak count locked
+            if cur == WEAK_LOCKED {
+                crate::sync::hint::spin_loop();
+                cur = inner.counts.weak.load(Ordering::Relaxed);
+                continue;
+            }
+
+            if cur & !HAS_FINALIZER > MAX_WEAK {
+                std::process::abort();
+            }
+
+            // Acquire synchronises with the Release write in `is_unique`
+            match inner.counts.weak.compare_exchange_weak(
+                cur,
+                cur + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    return Weak {
+                        inner: this.inner,
+                        alloc: this.alloc.clone(),
+                    }
+                }
+                Err(old) => cur = old,
+            }
+        }
+    }
+
+    /// Returns the number of `Arc` pointers to this value.
+    ///
+    /// The count may change as soon as it is read if other threads hold clones of this `Arc`.
+    pub fn strong_count(this: &Self) -> usize {
+        this.inner().counts.strong_count()
+    }
+
+    /// Returns the number of [`Weak`] pointers to this value, or `0` for a value in a
+    /// [`StaticArc`], whose `Weak` pointers are not counted.
+    ///
+    /// The count may change as soon as it is read if other threads hold clones of this `Arc`.
+    pub fn weak_count(this: &Self) -> usize {
+        let inner = this.inner();
+        if inner.counts.is_immortal() {
+            return 0;
+        }
+        match inner.counts.weak.load(Ordering::Relaxed) {
+            // The weak count is locked, so it was 1 before the lock
+            WEAK_LOCKED => 0,
+            // The strong pointers collectively hold one implicit weak reference
+            cnt => (cnt & !HAS_FINALIZER) - 1,
+        }
+    }
+
+    /// Returns `true` if both `Arc`s point to the same allocation.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        std::ptr::addr_eq(this.inner.as_ptr(), other.inner.as_ptr())
+    }
+
+    /// Returns a reference to the allocator the value was allocated from.
+    pub fn allocator(this: &Self) -> &A {
+        &this.alloc
+    }
+
+    /// Returns a mutable reference to the value if there are no other `Arc` or `Weak` pointers to it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::Arc;
+    ///
+    /// let mut x = Arc::new(3);
+    /// *Arc::get_mut(&mut x).unwrap() = 4;
+    /// assert_eq!(*x, 4);
+    ///
+    /// let _y = Arc::clone(&x);
+    /// assert!(Arc::get_mut(&mut x).is_none());
+    /// ```
+    pub fn get_mut(this: &mut Self) -> Option<&mut T> {
+        if this.is_unique() {
+            // We are the only pointer to the allocation, so nobody else can observe the value.
+            Some(unsafe { &mut (*this.inner.as_ptr()).ptr })
+        } else {
+            None
+        }
+    }
+
+    #[inline(always)]
+    fn inner(&self) -> &Inner<T> {
+        // The allocation stays valid while this `Arc` is alive
+        unsafe { self.inner.as_ref() }
+    }
+
+    /// Checks that there are no other `Arc` or `Weak` pointers to the allocation.
+    fn is_unique(&mut self) -> bool {
+        // Lock the weak count so that no new `Weak` can be created through `downgrade`
+        // while we read the strong count.
+        let counts = &self.inner().counts;
+        let unlocked = counts.weak.load(Ordering::Relaxed) & HAS_FINALIZER | 1;
+        if counts
+            .weak
+            .compare_exchange(unlocked, WEAK_LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            let unique = counts.strong.load(Ordering::Acquire) == 1;
+            // Release synchronises with the Acquire in `downgrade`
+            counts.weak.store(unlocked, Ordering::Release);
+            unique
+        } else {
+            false
+        }
+    }
+}
+
+impl<T: ?Sized> Arc<T> {
+    /// Turns the `Arc` into an [`Rc`](crate::Rc) to the same allocation without copying the value,
+    /// if there are no other `Arc` or `Weak` pointers to it. Otherwise the `Arc` is returned back.
+    ///
+    /// Not available with the `loom` feature, whose atomics do not share the layout of `Rc`'s counts.
+    #[cfg(not(feature = "loom"))]
+    pub fn try_into_rc(mut this: Self) -> Result<crate::Rc<T>, Self> {
+        // The Acquire in `is_unique` makes every access from the threads that released their
+        // pointers happen before the value is handed to the `Rc`.
+        if !this.is_unique() {
+            return Err(this);
+        }
+
+        // Both counts are 1, and `rc::Inner` stores them as plain `usize`s laid out like our atomics,
+        // flagging a finalizer in front of it the same way
+        let ptr = Arc::into_inner_ptr(this) as *mut crate::rc::Inner<T>;
+        Ok(unsafe { crate::Rc::from_inner_ptr(ptr) })
+    }
+}
+
+impl<T: ?Sized, A: Allocator> std::ops::Deref for Arc<T, A> {
     type Target = T;
     #[inline(always)]
     fn deref(&self) -> &Self::Target {
-        unsafe { self.inner.as_ref().unwrap().value() }
+        self.inner().value()
     }
 }
 
-impl<T> AsRef<T> for Arc<T> {
+impl<T: ?Sized, A: Allocator> AsRef<T> for Arc<T, A> {
     fn as_ref(&self) -> &T {
         std::ops::Deref::deref(self)
     }
 }
 
-impl<T> Drop for Arc<T> {
+unsafe impl<#[may_dangle] T: ?Sized, A: Allocator> Drop for Arc<T, A> {
+    #[inline]
     fn drop(&mut self) {
-        let inner = unsafe { self.inner.as_mut().unwrap() };
-
-        let old_count = inner.decrement_count();
+        let old_count = unsafe { Inner::decrement_count(self.inner) };
 
         if old_count == 1 {
-            let _ = unsafe { Box::from_raw(self.inner) };
+            // Synchronise with the Release decrements of the other strong pointers
+            // so that all their uses of the value happen before it is dropped.
+            atomic::fence(Ordering::Acquire);
+
+            unsafe { Inner::drop_value(self.inner.as_ptr()) };
+            // Release the implicit weak reference held by the strong pointers
+            drop(Weak {
+                inner: self.inner,
+                alloc: &self.alloc,
+            });
+        }
+    }
+}
+
+/// Moves a boxed value, which may be unsized, into a new `Arc`.
+///
+/// # Examples
+///
+/// ```
+/// use speedy_refs::Arc;
+///
+/// let boxed: Box<dyn Fn() -> i32 + Send + Sync> = Box::new(|| 42);
+/// let arc: Arc<dyn Fn() -> i32 + Send + Sync> = Arc::from(boxed);
+/// assert_eq!(arc(), 42);
+/// ```
+impl<T: ?Sized> From<Box<T>> for Arc<T> {
+    fn from(boxed: Box<T>) -> Self {
+        Arc {
+            inner: Inner::from_box(boxed),
+            _marker: PhantomData,
+            alloc: Global,
+        }
+    }
+}
+
+/// Moves the elements of a `Vec` into a new `Arc<[T]>`.
+///
+/// # Examples
+///
+/// ```
+/// use speedy_refs::Arc;
+///
+/// let arc: Arc<[String]> = Arc::from(vec![String::from("a"), String::from("b")]);
+/// assert_eq!(arc.len(), 2);
+/// ```
+impl<T> From<Vec<T>> for Arc<[T]> {
+    fn from(mut vec: Vec<T>) -> Self {
+        let inner = unsafe { Inner::copy_from(vec.as_ptr(), vec.len()) };
+        // The elements have been moved into the `Arc`, only the buffer is left for the `Vec` to free
+        unsafe { vec.set_len(0) };
+        Arc {
+            inner,
+            _marker: PhantomData,
+            alloc: Global,
+        }
+    }
+}
+
+/// Copies a string slice into a new `Arc<str>`.
+///
+/// # Examples
+///
+/// ```
+/// use speedy_refs::Arc;
+///
+/// let arc: Arc<str> = Arc::from("speedy");
+/// let clone = Arc::clone(&arc);
+/// assert_eq!(&*clone, "speedy");
+/// ```
+impl From<&str> for Arc<str> {
+    fn from(s: &str) -> Self {
+        let inner = unsafe { Inner::copy_from(s.as_ptr(), s.len()) };
+        // `str` has the same layout as `[u8]`
+        Arc {
+            inner: unsafe { NonNull::new_unchecked(inner.as_ptr() as *mut Inner<str>) },
+            _marker: PhantomData,
+            alloc: Global,
+        }
+    }
+}
+
+/// Copies the contents of a `String` into a new `Arc<str>`.
+impl From<String> for Arc<str> {
+    fn from(s: String) -> Self {
+        Arc::from(s.as_str())
+    }
+}
+
+/// Collects the items of an iterator into a new `Arc<[T]>`.
+///
+/// # Examples
+///
+/// ```
+/// use speedy_refs::Arc;
+///
+/// let squares: Arc<[u32]> = (1..=4).map(|x| x * x).collect();
+/// assert_eq!(&*squares, [1, 4, 9, 16]);
+/// ```
+impl<T> FromIterator<T> for Arc<[T]> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Arc::from(iter.into_iter().collect::<Vec<T>>())
+    }
+}
+
+/// Allows `Arc<T>` to be coerced to `Arc<U>` when `T` unsizes to `U`, e.g `Arc<[i32; 3]>` to `Arc<[i32]>`
+/// or `Arc<String>` to `Arc<dyn Display + Send + Sync>`.
+impl<T: ?Sized + std::marker::Unsize<U>, U: ?Sized, A: Allocator> std::ops::CoerceUnsized<Arc<U, A>>
+    for Arc<T, A>
+{
+}
+
+/// # speedy_refs::arc::Weak
+/// `Weak<T>` is a non-owning version of [`Arc<T>`]. It is created through `Arc::downgrade`
+/// and can be atomically upgraded back to an `Arc` with `Weak::upgrade`.
+///
+/// A `Weak` keeps the allocation alive but not the value, so `upgrade` returns `None`
+/// once the last `Arc` to the value has been dropped.
+///
+/// # Examples
+///
+/// ```
+/// use speedy_refs::Arc;
+/// use std::thread;
+///
+/// let strong = Arc::new(String::from("config"));
+/// let weak = Arc::downgrade(&strong);
+///
+/// thread::spawn(move || {
+///     if let Some(config) = weak.upgrade() {
+///         assert_eq!(*config, "config");
+///     }
+/// })
+/// .join()
+/// .unwrap();
+///
+/// assert_eq!(Arc::weak_count(&strong), 0);
+/// ```
+pub struct Weak<T: ?Sized, A: Allocator = Global> {
+    inner: NonNull<Inner<T>>,
+    alloc: A,
+}
+
+impl<T> Weak<T> {
+    /// Creates a new `Weak` that points to no value. Calling `upgrade` on it always returns `None`.
+    pub const fn new() -> Self {
+        Self {
+            inner: unsafe { NonNull::new_unchecked(std::ptr::without_provenance_mut(usize::MAX)) },
+            alloc: Global,
+        }
+    }
+}
+
+impl<T: ?Sized, A: Allocator> Weak<T, A> {
+    /// Attempts to upgrade the `Weak` pointer to an `Arc`.
+    ///
+    /// Returns `None` if the value has already been dropped.
+    pub fn upgrade(&self) -> Option<Arc<T, A>>
+    where
+        A: Clone,
+    {
+        let counts = self.counts()?;
+        let mut cur = counts.strong.load(Ordering::Relaxed);
+        loop {
+            // A strong count of zero means the value has been, or is being, dropped
+            if cur == 0 {
+                return None;
+            }
+
+            if cur > MAX_REFCOUNT {
+                if !is_immortal(cur) {
+                    std::process::abort();
+                }
+                // Not counted, like the `Arc`s made by `Arc::from_static`
+                return Some(Arc {
+                    inner: self.inner,
+                    _marker: PhantomData,
+                    alloc: self.alloc.clone(),
+                });
+            }
+
+            // Acquire synchronises with the Release store in `make_mut`
+            match counts.strong.compare_exchange_weak(
+                cur,
+                cur + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    return Some(Arc {
+                        inner: self.inner,
+                        _marker: PhantomData,
+                        alloc: self.alloc.clone(),
+                    })
+                }
+                Err(old) => cur = old,
+            }
+        }
+    }
+
+    /// Returns the number of `Arc` pointers to the value this `Weak` points to.
+    pub fn strong_count(&self) -> usize {
+        self.counts().map_or(0, Counts::strong_count)
+    }
+
+    /// Returns the number of `Weak` pointers to the value this `Weak` points to,
+    /// or `0` if there are no remaining `Arc` pointers or the value is in a [`StaticArc`].
+    ///
+    /// The count is only approximate when other threads are concurrently manipulating pointers
+    /// to the same allocation.
+    pub fn weak_count(&self) -> usize {
+        match self.counts() {
+            Some(counts) => {
+                let weak = counts.weak.load(Ordering::Acquire);
+                let strong = counts.strong.load(Ordering::Relaxed);
+                if strong == 0 || is_immortal(strong) {
+                    0
+                } else {
+                    // Exclude the implicit weak reference held by the strong pointers
+                    (weak & !HAS_FINALIZER) - 1
+                }
+            }
+            None => 0,
+        }
+    }
+
+    /// Returns `None` for a `Weak` created through `Weak::new`.
+    #[inline]
+    fn counts(&self) -> Option<&Counts> {
+        if self.inner.as_ptr().addr() == usize::MAX {
+            None
+        } else {
+            // The allocation stays valid as long as a `Weak` exists, and starts with the counts
+            Some(unsafe { self.inner.cast::<Counts>().as_ref() })
+        }
+    }
+}
+
+impl<T: ?Sized + std::marker::Unsize<U>, U: ?Sized, A: Allocator>
+    std::ops::CoerceUnsized<Weak<U, A>> for Weak<T, A>
+{
+}
+
+impl<T> Default for Weak<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: ?Sized, A: Allocator + Clone> Clone for Weak<T, A> {
+    fn clone(&self) -> Self {
+        if let Some(counts) = self.counts().filter(|counts| !counts.is_immortal()) {
+            // `is_unique` can not be holding the lock here since it requires
+            // the weak count to be 1 and we are a `Weak` in addition to the implicit one.
+            if counts.weak.fetch_add(1, Ordering::Relaxed) & !HAS_FINALIZER > MAX_WEAK {
+                std::process::abort();
+            }
+        }
+        Self {
+            inner: self.inner,
+            alloc: self.alloc.clone(),
+        }
+    }
+}
+
+unsafe impl<#[may_dangle] T: ?Sized, A: Allocator> Drop for Weak<T, A> {
+    #[inline]
+    fn drop(&mut self) {
+        let Some(counts) = self.counts().filter(|counts| !counts.is_immortal()) else {
+            return;
+        };
+
+        if counts.weak.fetch_sub(1, Ordering::Release) & !HAS_FINALIZER == 1 {
+            atomic::fence(Ordering::Acquire);
+            unsafe { Inner::dealloc(self.inner.as_ptr(), &self.alloc) };
+        }
+    }
+}
+
+/// # StaticArc
+/// Storage for a value that [`Arc`]s can point to without ever counting, for example a default
+/// built at compile time.
+///
+/// `StaticArc::new` is `const`, so it can initialise a `static`. `Arc::from_static` then turns a
+/// reference to that `static` into an ordinary `Arc`, in `const` context too. Cloning and dropping
+/// such an `Arc` counts it like any other, but its count starts too high to ever drop to zero, so the
+/// value is never dropped. `Weak` pointers to it are not counted.
+/// `Arc::strong_count` returns `usize::MAX` for it, and `Arc::weak_count` returns `0`.
+///
+/// Not available with the `loom` feature, whose atomics can not be created in `const` context.
+///
+/// # Examples
+///
+/// ```
+/// use speedy_refs::{Arc, StaticArc};
+///
+/// struct Config {
+///     retries: u32,
+/// }
+///
+/// static DEFAULT: StaticArc<Config> = StaticArc::new(Config { retries: 3 });
+/// const DEFAULT_ARC: Arc<Config> = Arc::from_static(&DEFAULT);
+///
+/// fn config(retries: Option<u32>) -> Arc<Config> {
+///     match retries {
+///         Some(retries) => Arc::new(Config { retries }),
+///         None => DEFAULT_ARC,
+///     }
+/// }
+///
+/// assert_eq!(config(None).retries, 3);
+/// assert_eq!(config(Some(5)).retries, 5);
+/// ```
+#[cfg(not(feature = "loom"))]
+pub struct StaticArc<T>(Inner<T>);
+
+#[cfg(not(feature = "loom"))]
+impl<T> StaticArc<T> {
+    /// Creates the storage for an immortal `val`.
+    pub const fn new(val: T) -> Self {
+        Self(Inner {
+            counts: Counts {
+                strong: AtomicUsize::new(IMMORTAL),
+                weak: AtomicUsize::new(IMMORTAL_WEAK),
+            },
+            ptr: val,
+        })
+    }
+}
+
+#[cfg(not(feature = "loom"))]
+impl<T> Arc<T> {
+    /// Creates an `Arc` to the value in a `static` [`StaticArc`], without incrementing its count.
+    pub const fn from_static(value: &'static StaticArc<T>) -> Self {
+        Self {
+            // Not counted, the strong count of an immortal value can not drop to zero
+            inner: unsafe { NonNull::new_unchecked(std::ptr::from_ref(&value.0).cast_mut()) },
+            _marker: PhantomData,
+            alloc: Global,
+        }
+    }
+}
+
+/// # UniqueArc
+/// `UniqueArc<T>` is an [`Arc<T>`] under construction. It is the only strong pointer to its value,
+/// so it gives out mutable access through `DerefMut`, and is turned into an `Arc` for free with
+/// `UniqueArc::into_shared`.
+///
+/// It lives in the same allocation an `Arc` does. [`Weak`] pointers may be created with
+/// `UniqueArc::downgrade` and sent to other threads before sharing, but they can only be upgraded
+/// once the `UniqueArc` has become an `Arc`.
+///
+/// # Examples
+///
+/// ```
+/// use speedy_refs::arc::{UniqueArc, Weak};
+///
+/// let mut config = UniqueArc::new(Vec::new());
+/// let weak: Weak<Vec<&str>> = UniqueArc::downgrade(&config);
+/// config.push("verbose");
+/// assert!(weak.upgrade().is_none());
+///
+/// let config = UniqueArc::into_shared(config);
+/// assert_eq!(*weak.upgrade().unwrap(), ["verbose"]);
+/// # drop(config);
+/// ```
+pub struct UniqueArc<T: ?Sized> {
+    inner: NonNull<Inner<T>>,
+    _marker: PhantomData<Inner<T>>,
+}
+
+impl<T> UniqueArc<T> {
+    /// Creates a new `UniqueArc` holding `data`.
+    pub fn new(data: T) -> Self {
+        let inner = Inner::new(data).into_ptr();
+        // There is no `Arc` until `into_shared`, so `Weak` pointers can not upgrade yet
+        unsafe { inner.as_ref() }
+            .counts
+            .strong
+            .store(0, Ordering::Relaxed);
+        Self {
+            inner,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: ?Sized> UniqueArc<T> {
+    /// Creates a new [`Weak`] pointer to the value. It can only be upgraded once `this` has been
+    /// turned into an `Arc` with `UniqueArc::into_shared`.
+    pub fn downgrade(this: &Self) -> Weak<T> {
+        if this.inner().counts.weak.fetch_add(1, Ordering::Relaxed) & !HAS_FINALIZER > MAX_WEAK {
+            std::process::abort();
+        }
+        Weak {
+            inner: this.inner,
+            alloc: Global,
+        }
+    }
+
+    /// Turns the `UniqueArc` into an `Arc` to the same allocation, without copying the value.
+    pub fn into_shared(this: Self) -> Arc<T> {
+        let this = std::mem::ManuallyDrop::new(this);
+        // Release synchronises with the Acquire in `Weak::upgrade`, so that an upgraded `Arc`
+        // sees every write made through the `UniqueArc`.
+        this.inner().counts.strong.store(1, Ordering::Release);
+        Arc {
+            inner: this.inner,
+            _marker: PhantomData,
+            alloc: Global,
+        }
+    }
+
+    #[inline(always)]
+    fn inner(&self) -> &Inner<T> {
+        // The allocation stays valid while this `UniqueArc` is alive
+        unsafe { self.inner.as_ref() }
+    }
+}
+
+impl<T: ?Sized> std::ops::Deref for UniqueArc<T> {
+    type Target = T;
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        &self.inner().ptr
+    }
+}
+
+impl<T: ?Sized> std::ops::DerefMut for UniqueArc<T> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // No `Arc` exists and `Weak` pointers can not upgrade, so nobody else can observe the value
+        unsafe { &mut (*self.inner.as_ptr()).ptr }
+    }
+}
+
+impl<T: ?Sized> AsRef<T> for UniqueArc<T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T: ?Sized> AsMut<T> for UniqueArc<T> {
+    fn as_mut(&mut self) -> &mut T {
+        self
+    }
+}
+
+unsafe impl<#[may_dangle] T: ?Sized> Drop for UniqueArc<T> {
+    fn drop(&mut self) {
+        unsafe { Inner::drop_value(self.inner.as_ptr()) };
+
+        // Release the implicit weak reference held by the strong pointers
+        drop(Weak {
+            inner: self.inner,
+            alloc: Global,
+        });
+    }
+}
+
+impl<T: ?Sized> From<UniqueArc<T>> for Arc<T> {
+    fn from(unique: UniqueArc<T>) -> Self {
+        UniqueArc::into_shared(unique)
+    }
+}
+
+impl<T: ?Sized + std::marker::Unsize<U>, U: ?Sized> std::ops::CoerceUnsized<UniqueArc<U>>
+    for UniqueArc<T>
+{
+}
+
+/// # AtomicArc
+/// An atomically replaceable [`Arc<T>`], in the spirit of `arc-swap`.
+///
+/// Readers either `load` a cheap [`ArcGuard`] that does not touch the reference count, or
+/// `load_full` a cloned `Arc`. Writers `store`, `swap` or `rcu` new values and never wait for the
+/// readers. The cell keeps a strong reference to each replaced `Arc` in a retire list, and only
+/// releases it once every reader that could still see it has dropped its guard, so no reader ever
+/// sees a freed `Inner`. The retire list is checked by later loads and writes, and emptied when the
+/// `AtomicArc` is dropped.
+///
+/// # Examples
+///
+/// ```
+/// use speedy_refs::{Arc, AtomicArc};
+/// use std::collections::HashMap;
+/// use std::thread;
+///
+/// let routes = Arc::new(AtomicArc::new(HashMap::from([("/", "index")])));
+///
+/// let reader = {
+///     let routes = routes.clone();
+///     thread::spawn(move || {
+///         let table = routes.load();
+///         assert!(table.contains_key("/"));
+///     })
+/// };
+///
+/// routes.rcu(|table| {
+///     let mut table = table.clone();
+///     table.insert("/about", "about");
+///     table
+/// });
+///
+/// reader.join().unwrap();
+/// assert_eq!(routes.load().len(), 2);
+/// ```
+pub struct AtomicArc<T> {
+    ptr: atomic::AtomicPtr<Inner<T>>,
+    epochs: crate::atomic::Epochs<Arc<T>>,
+}
+
+/// A read guard returned by `AtomicArc::load`.
+///
+/// It borrows the value the `AtomicArc` held when it was loaded, without touching the reference count.
+pub struct ArcGuard<'a, T> {
+    cell: &'a AtomicArc<T>,
+    inner: *mut Inner<T>,
+    epoch: usize,
+}
+
+impl<T> AtomicArc<T> {
+    /// Creates a new `AtomicArc` holding a new `Arc` to `data`.
+    pub fn new(data: T) -> Self {
+        Self::from_arc(Arc::new(data))
+    }
+
+    /// Creates a new `AtomicArc` holding `arc`.
+    pub fn from_arc(arc: Arc<T>) -> Self {
+        Self {
+            ptr: atomic::AtomicPtr::new(Arc::into_inner_ptr(arc)),
+            epochs: crate::atomic::Epochs::new(),
+        }
+    }
+
+    /// Returns a guard to the current value. This never blocks and leaves the reference count alone.
+    pub fn load(&self) -> ArcGuard<'_, T> {
+        self.epochs.collect();
+        let epoch = self.epochs.enter();
+        ArcGuard {
+            cell: self,
+            inner: self.ptr.load(Ordering::SeqCst),
+            epoch,
+        }
+    }
+
+    /// Returns a new `Arc` to the current value. This never blocks.
+    pub fn load_full(&self) -> Arc<T> {
+        self.load().to_arc()
+    }
+
+    /// Replaces the current `Arc` with `arc`. The old one is released once no reader can see it anymore.
+    pub fn store(&self, arc: Arc<T>) {
+        let old = self.ptr.swap(Arc::into_inner_ptr(arc), Ordering::SeqCst);
+        // The reference held by the cell is kept until the readers are gone
+        self.epochs.retire(unsafe { Arc::from_inner_ptr(old) });
+    }
+
+    /// Replaces the current `Arc` with `arc` and returns the old one right away.
+    pub fn swap(&self, arc: Arc<T>) -> Arc<T> {
+        let old = self.ptr.swap(Arc::into_inner_ptr(arc), Ordering::SeqCst);
+        // The reference held by the cell is handed over to the caller
+        self.retire_clone(unsafe { Arc::from_inner_ptr(old) })
+    }
+
+    /// Replaces the current `Arc` with `new` if it still points to the same allocation as `current`.
+    ///
+    /// Since `current` keeps its allocation alive, the comparison can not be fooled by a new
+    /// allocation at the same address.
+    ///
+    /// # Returns
+    /// * `Ok(old)` - The `Arc` that was replaced
+    /// * `Err(new)` - `new` is handed back if the current `Arc` is not `current`
+    pub fn compare_exchange(&self, current: &Arc<T>, new: Arc<T>) -> Result<Arc<T>, Arc<T>> {
+        let new = Arc::into_inner_ptr(new);
+        match self.ptr.compare_exchange(
+            current.inner.as_ptr(),
+            new,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        ) {
+            Ok(old) => Ok(self.retire_clone(unsafe { Arc::from_inner_ptr(old) })),
+            // `new` was never published
+            Err(_) => Err(unsafe { Arc::from_inner_ptr(new) }),
+        }
+    }
+
+    /// Keeps a new reference to an `Arc` just unlinked from the cell until its readers are gone,
+    /// and returns the `Arc`.
+    fn retire_clone(&self, old: Arc<T>) -> Arc<T> {
+        self.epochs.retire(old.clone());
+        old
+    }
+
+    /// Read-copy-update: computes a new value from the current one with `f` and installs it,
+    /// retrying with the latest value if another writer got in first.
+    ///
+    /// `f` may be called several times. Returns the `Arc` that was replaced.
+    pub fn rcu<F: FnMut(&T) -> T>(&self, mut f: F) -> Arc<T> {
+        let mut current = self.load_full();
+        loop {
+            let new = Arc::new(f(&current));
+            match self.compare_exchange(&current, new) {
+                Ok(old) => return old,
+                Err(_) => current = self.load_full(),
+            }
+        }
+    }
+
+    /// Consumes the `AtomicArc` and returns the `Arc` it holds.
+    pub fn into_inner(self) -> Arc<T> {
+        let mut this = std::mem::ManuallyDrop::new(self);
+        // No guard is left, so dropping the reader registration releases the retired `Arc`s
+        unsafe { std::ptr::drop_in_place(&mut this.epochs) };
+        unsafe { Arc::from_inner_ptr(this.ptr.load(Ordering::Relaxed)) }
+    }
+}
+
+impl<T: ?Sized> Arc<T> {
+    /// Gives up this `Arc`'s reference, returning the pointer it held without decrementing the count.
+    #[inline(always)]
+    pub(crate) fn into_inner_ptr(this: Self) -> *mut Inner<T> {
+        std::mem::ManuallyDrop::new(this).inner.as_ptr()
+    }
+
+    /// Gives up this `Arc`'s reference like dropping it would. If it was the last one, the value
+    /// is not dropped but its allocation returned, to be finished with `Arc::drop_released`.
+    pub(crate) fn release(this: Self) -> Option<NonNull<Inner<T>>> {
+        let this = std::mem::ManuallyDrop::new(this);
+        if unsafe { Inner::decrement_count(this.inner) } != 1 {
+            return None;
+        }
+
+        // Synchronise with the Release decrements of the other strong pointers
+        atomic::fence(Ordering::Acquire);
+        Some(this.inner)
+    }
+
+    /// Drops the value of an allocation with no strong pointers left, and releases
+    /// the implicit weak reference held by the strong pointers.
+    ///
+    /// # Safety
+    /// `inner` must come from `Arc::release`, or from a drop of the last `Arc`, and this must be
+    /// called once for it.
+    pub(crate) unsafe fn drop_released(inner: NonNull<Inner<T>>) {
+        Inner::drop_value(inner.as_ptr());
+        drop(Weak {
+            inner,
+            alloc: Global,
+        });
+    }
+
+    /// Takes over a strong reference previously given up through `Arc::into_inner_ptr`.
+    ///
+    /// # Safety
+    /// `inner` must carry a strong reference that nothing else will release.
+    #[inline(always)]
+    pub(crate) unsafe fn from_inner_ptr(inner: *mut Inner<T>) -> Self {
+        Self {
+            inner: NonNull::new_unchecked(inner),
+            _marker: PhantomData,
+            alloc: Global,
         }
     }
 }
 
-struct Inner<T> {
+impl<T> Drop for AtomicArc<T> {
+    fn drop(&mut self) {
+        // No guard can outlive the `AtomicArc` it borrows
+        drop(unsafe { Arc::from_inner_ptr(self.ptr.load(Ordering::Relaxed)) });
+    }
+}
+
+impl<T> From<Arc<T>> for AtomicArc<T> {
+    fn from(arc: Arc<T>) -> Self {
+        Self::from_arc(arc)
+    }
+}
+
+impl<T: Default> Default for AtomicArc<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for AtomicArc<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("AtomicArc").field(&*self.load()).finish()
+    }
+}
+
+impl<'a, T> ArcGuard<'a, T> {
+    /// Returns a new `Arc` to the value this guard points to.
+    pub fn to_arc(&self) -> Arc<T> {
+        // The allocation can not be released while the guard is registered
+        unsafe { &*self.inner }.increment_count();
+        unsafe { Arc::from_inner_ptr(self.inner) }
+    }
+}
+
+impl<'a, T> std::ops::Deref for ArcGuard<'a, T> {
+    type Target = T;
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        // The retired `Arc` that keeps the value alive is not released before this guard is dropped
+        unsafe { &*self.inner }.value()
+    }
+}
+
+impl<'a, T> AsRef<T> for ArcGuard<'a, T> {
+    fn as_ref(&self) -> &T {
+        std::ops::Deref::deref(self)
+    }
+}
+
+impl<'a, T> Drop for ArcGuard<'a, T> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        self.cell.epochs.leave(self.epoch);
+    }
+}
+
+/// The allocation shared by `Arc` and `Weak`, holding both counts and the value. The finalizer of
+/// an `Arc` from `Arc::new_with_finalizer` is kept in front of it.
+///
+/// It is `repr(C)` so that the value always comes last and an `Inner` can be allocated by hand
+/// for unsized values like slices, strings and trait objects.
+/// It is laid out like `rc::Inner`, so that a unique `Rc` and `Arc` can be converted in place.
+#[repr(C)]
+pub(crate) struct Inner<T: ?Sized> {
+    counts: Counts,
     ptr: T,
-    count: std::sync::atomic::AtomicUsize,
 }
 
+/// The counts at the start of every `Inner`.
+///
+/// `Weak` pointers only ever borrow the counts, never the whole `Inner`, so that they do not race
+/// with the last `Arc` dropping the value, or with the `&mut` to the value handed out by a
+/// `UniqueArc`.
+#[repr(C)]
+struct Counts {
+    strong: AtomicUsize,
+    /// The number of `Weak` pointers plus one implicit reference held by all the strong pointers,
+    /// and `HAS_FINALIZER` if the `Inner` has a finalizer in front of it.
+    weak: AtomicUsize,
+}
+
+impl Counts {
+    /// Both counts set to 1, for a new `Arc`.
+    fn new() -> Self {
+        Self {
+            strong: AtomicUsize::new(1),
+            weak: AtomicUsize::new(1),
+        }
+    }
+
+    /// Returns `true` for a value that is never dropped. Its weak count is never touched.
+    #[inline(always)]
+    fn is_immortal(&self) -> bool {
+        is_immortal(self.strong.load(Ordering::Relaxed))
+    }
+
+    /// Returns `true` if the `Inner` has a finalizer in front of it.
+    #[inline(always)]
+    fn has_finalizer(&self) -> bool {
+        self.weak.load(Ordering::Relaxed) & HAS_FINALIZER != 0
+    }
+
+    /// Returns the strong count, or `usize::MAX` for an immortal value.
+    fn strong_count(&self) -> usize {
+        match self.strong.load(Ordering::Relaxed) {
+            strong if is_immortal(strong) => usize::MAX,
+            strong => strong,
+        }
+    }
+}
+
+/// Returns `true` if `strong` is the strong count of a value that is never dropped.
+#[inline(always)]
+fn is_immortal(strong: usize) -> bool {
+    strong > IMMORTAL_MIN
+}
+
+// `Arc::try_into_rc` and `Rc::try_into_arc` rely on both counts having the same layout
+#[cfg(not(feature = "loom"))]
+const _: () = assert!(
+    std::mem::size_of::<AtomicUsize>() == std::mem::size_of::<std::cell::UnsafeCell<usize>>()
+        && std::mem::align_of::<AtomicUsize>()
+            == std::mem::align_of::<std::cell::UnsafeCell<usize>>()
+        && std::mem::size_of::<Inner<()>>() == std::mem::size_of::<crate::rc::Inner<()>>()
+);
+
 impl<T> Inner<T> {
     fn new(data: T) -> Self {
         Self {
+            counts: Counts::new(),
             ptr: data,
-            count: std::sync::atomic::AtomicUsize::new(1),
         }
     }
 
     #[inline(always)]
-    fn into_ptr(self) -> *mut Inner<T> {
-        Box::leak(Box::new(self))
+    fn into_ptr(self) -> NonNull<Inner<T>> {
+        NonNull::from(Box::leak(Box::new(self)))
+    }
+
+    /// Moves an `Inner` into memory from `alloc`, calling `std::alloc::handle_alloc_error` if that fails.
+    #[inline]
+    fn into_ptr_in<A: Allocator>(self, alloc: &A) -> NonNull<Self> {
+        self.try_into_ptr_in(alloc)
+            .unwrap_or_else(|_| std::alloc::handle_alloc_error(std::alloc::Layout::new::<Self>()))
+    }
+
+    /// Moves an `Inner` into memory from `alloc`.
+    #[inline]
+    fn try_into_ptr_in<A: Allocator>(self, alloc: &A) -> Result<NonNull<Self>, AllocError> {
+        let ptr = alloc
+            .allocate(std::alloc::Layout::new::<Self>())?
+            .cast::<Self>();
+        unsafe { ptr.as_ptr().write(self) };
+        Ok(ptr)
+    }
+
+    /// Moves an `Inner` into memory from `alloc`, after `finalizer`, calling
+    /// `std::alloc::handle_alloc_error` if that fails.
+    fn into_ptr_with_finalizer_in<A: Allocator>(
+        mut self,
+        finalizer: Finalizer,
+        alloc: &A,
+    ) -> NonNull<Self> {
+        let (layout, offset) = finalizer::layout_with(std::alloc::Layout::new::<Self>());
+        let Ok(mem) = alloc.allocate(layout) else {
+            std::alloc::handle_alloc_error(layout);
+        };
+
+        self.counts.weak = AtomicUsize::new(HAS_FINALIZER | 1);
+        unsafe {
+            let mem = mem.cast::<u8>();
+            mem.cast::<Option<Finalizer>>().write(Some(finalizer));
+            let ptr = mem.add(offset).cast::<Self>();
+            ptr.write(self);
+            ptr
+        }
+    }
+}
+
+impl<T> Inner<MaybeUninit<T>> {
+    /// Allocates an `Inner` from `alloc` with a strong count of 1 and the implicit weak reference,
+    /// without building its value on the stack first. The value is zeroed if `zeroed` is set,
+    /// otherwise it is left uninitialised.
+    fn try_alloc_uninit<A: Allocator>(
+        alloc: &A,
+        zeroed: bool,
+    ) -> Result<NonNull<Self>, AllocError> {
+        let layout = std::alloc::Layout::new::<Self>();
+        let mem = if zeroed {
+            alloc.allocate_zeroed(layout)?
+        } else {
+            alloc.allocate(layout)?
+        };
+
+        let ptr = mem.cast::<Self>().as_ptr();
+        unsafe {
+            std::ptr::addr_of_mut!((*ptr).counts).write(Counts::new());
+        }
+        Ok(mem.cast())
+    }
+
+    fn alloc_error() -> ! {
+        std::alloc::handle_alloc_error(std::alloc::Layout::new::<Self>())
+    }
+}
+
+impl<T> Inner<[T]> {
+    /// Allocates an `Inner` for `len` elements and bitwise copies the elements at `src` into it.
+    ///
+    /// # Safety
+    /// `src` must be valid for reading `len` elements. The caller becomes responsible for not
+    /// dropping the originals unless `T` is `Copy`.
+    unsafe fn copy_from(src: *const T, len: usize) -> NonNull<Self> {
+        let layout = std::alloc::Layout::array::<T>(len).expect("slice is too large");
+        let ptr = Self::alloc_for(layout, |mem| {
+            std::ptr::slice_from_raw_parts_mut(mem.cast::<T>(), len) as *mut Self
+        });
+        std::ptr::copy_nonoverlapping(src, Self::value_ptr(ptr.as_ptr()).cast::<T>(), len);
+        ptr
+    }
+}
+
+impl<T: ?Sized> Inner<T> {
+    /// Returns the layout of an `Inner` whose value has the layout `val_layout`.
+    fn layout_for(val_layout: std::alloc::Layout) -> std::alloc::Layout {
+        std::alloc::Layout::new::<Inner<()>>()
+            .extend(val_layout)
+            .expect("value is too large")
+            .0
+            .pad_to_align()
+    }
+
+    /// Allocates an `Inner` for a value of layout `val_layout`, with a strong count of 1 and
+    /// the implicit weak reference. The value is left uninitialised.
+    ///
+    /// # Safety
+    /// `with_metadata` must turn the address of the allocation into a pointer to an `Inner`
+    /// whose value has the layout `val_layout`.
+    pub(crate) unsafe fn alloc_for(
+        val_layout: std::alloc::Layout,
+        with_metadata: impl FnOnce(*mut u8) -> *mut Self,
+    ) -> NonNull<Self> {
+        let layout = Self::layout_for(val_layout);
+        let Some(mem) = NonNull::new(std::alloc::alloc(layout)) else {
+            std::alloc::handle_alloc_error(layout);
+        };
+
+        let ptr = with_metadata(mem.as_ptr());
+        std::ptr::addr_of_mut!((*ptr).counts).write(Counts::new());
+        NonNull::new_unchecked(ptr)
+    }
+
+    /// Frees an `Inner` from `Inner::alloc_for` whose value was never initialised.
+    ///
+    /// # Safety
+    /// `val_layout` must be the layout `ptr` was allocated for, and `ptr` must not be used afterwards.
+    pub(crate) unsafe fn dealloc_for(ptr: NonNull<Self>, val_layout: std::alloc::Layout) {
+        std::alloc::dealloc(ptr.as_ptr().cast(), Self::layout_for(val_layout));
+    }
+
+    /// Moves the value out of `boxed` into a new `Inner` and frees the box.
+    fn from_box(boxed: Box<T>) -> NonNull<Self> {
+        let raw = Box::into_raw(boxed);
+        unsafe {
+            let val_layout = std::alloc::Layout::for_value(&*raw);
+            let ptr = Self::alloc_for(val_layout, |mem| mem.with_metadata_of(raw as *const Self));
+            std::ptr::copy_nonoverlapping(
+                raw.cast::<u8>(),
+                Self::value_ptr(ptr.as_ptr()).cast::<u8>(),
+                val_layout.size(),
+            );
+            // Frees the box without dropping the value that was moved out of it
+            drop(Box::from_raw(raw as *mut std::mem::ManuallyDrop<T>));
+            ptr
+        }
+    }
+
+    /// Frees the memory of an `Inner` whose value has already been dropped or moved out.
+    ///
+    /// # Safety
+    /// `ptr` must have been allocated from `alloc`, which is `Global` for `Inner::into_ptr` and
+    /// `Inner::alloc_for`. Its value must no longer be in use and it must not be used afterwards.
+    unsafe fn dealloc<A: Allocator>(ptr: *mut Self, alloc: &A) {
+        let layout = std::alloc::Layout::for_value_raw(ptr);
+        if !(*std::ptr::addr_of!((*ptr).counts)).has_finalizer() {
+            alloc.deallocate(NonNull::new_unchecked(ptr.cast()), layout);
+            return;
+        }
+
+        // A finalizer left over by `try_unwrap` and friends is freed without being called
+        let slot = finalizer::slot(ptr.cast(), layout);
+        std::ptr::drop_in_place(slot);
+        let layout = finalizer::layout_with(layout).0;
+        alloc.deallocate(NonNull::new_unchecked(slot.cast()), layout);
+    }
+
+    /// Takes the finalizer out of the allocation of the `Inner` at `ptr`, if it has one.
+    ///
+    /// # Safety
+    /// `ptr` must point to an allocated `Inner`, and no other thread may take its finalizer.
+    unsafe fn take_finalizer(ptr: *mut Self) -> Option<Finalizer> {
+        if !(*ptr).counts.has_finalizer() {
+            return None;
+        }
+        let layout = std::alloc::Layout::for_value(&*ptr);
+        (*finalizer::slot(ptr.cast(), layout)).take()
+    }
+
+    /// Runs the finalizer, if any, then drops the value.
+    ///
+    /// # Safety
+    /// The value must be initialised, no other thread may use it, and it must not be used afterwards.
+    unsafe fn drop_value(ptr: *mut Self) {
+        if let Some(finalizer) = Self::take_finalizer(ptr) {
+            finalizer.call(Self::value_ptr(ptr).cast());
+        }
+        std::ptr::drop_in_place(Self::value_ptr(ptr));
     }
 
     #[inline(always)]
@@ -70,19 +1556,426 @@ impl<T> Inner<T> {
         &self.ptr
     }
 
+    /// Returns a pointer to the value of the `Inner` at `this`, which may not be initialised yet.
+    ///
+    /// # Safety
+    /// `this` must point to an allocated `Inner`.
+    #[inline(always)]
+    pub(crate) unsafe fn value_ptr(this: *mut Self) -> *mut T {
+        std::ptr::addr_of_mut!((*this).ptr)
+    }
+
     #[inline(always)]
-    fn increment_count(&mut self) {
-        self.count
-            .fetch_add(1, std::sync::atomic::Ordering::AcqRel);
+    fn increment_count(&self) {
+        // A new reference can only be formed from an existing one, so no synchronisation is needed.
+        let old = self.counts.strong.fetch_add(1, Ordering::Relaxed);
+        if old > MAX_REFCOUNT {
+            self.count_overflowed(old);
+        }
+    }
+
+    /// Undoes the increment of an immortal count, which only looks like an overflow, or aborts.
+    #[cold]
+    #[inline(never)]
+    fn count_overflowed(&self, old: usize) {
+        if !is_immortal(old) {
+            std::process::abort();
+        }
+        self.counts.strong.fetch_sub(1, Ordering::Relaxed);
     }
 
     /// Decreases reference count by one and returns the old value
+    ///
+    /// Takes a pointer rather than `&self`, since once the count is decremented another thread
+    /// may free the allocation before this function returns.
+    ///
+    /// # Safety
+    /// `this` must point to a live `Inner` that the caller holds a strong reference to.
     #[inline(always)]
-    fn decrement_count(&mut self) -> usize {
-        self.count
-            .fetch_add(1, std::sync::atomic::Ordering::AcqRel)
+    unsafe fn decrement_count(this: NonNull<Self>) -> usize {
+        let old = (*this.as_ptr())
+            .counts
+            .strong
+            .fetch_sub(1, Ordering::Release);
+        if old > MAX_REFCOUNT {
+            // Only an immortal count can be this high, and its value is never freed
+            (*this.as_ptr()).restore_count();
+        }
+        old
+    }
+
+    /// Undoes the decrement of an immortal count.
+    #[cold]
+    #[inline(never)]
+    fn restore_count(&self) {
+        self.counts.strong.fetch_add(1, Ordering::Relaxed);
     }
 }
 
-unsafe impl<T: Sync + Send> Sync for Arc<T> {}
-unsafe impl<T: Sync + Send> Send for Arc<T> {}
\ No newline at end of file
+unsafe impl<T: ?Sized + Sync + Send, A: Allocator + Sync> Sync for Arc<T, A> {}
+unsafe impl<T: ?Sized + Sync + Send, A: Allocator + Send> Send for Arc<T, A> {}
+unsafe impl<T: ?Sized + Sync + Send, A: Allocator + Sync> Sync for Weak<T, A> {}
+unsafe impl<T: ?Sized + Sync + Send, A: Allocator + Send> Send for Weak<T, A> {}
+unsafe impl<T: ?Sized + Sync> Sync for UniqueArc<T> {}
+unsafe impl<T: ?Sized + Send> Send for UniqueArc<T> {}
+unsafe impl<T: Sync + Send> Sync for AtomicArc<T> {}
+unsafe impl<T: Sync + Send> Send for AtomicArc<T> {}
+unsafe impl<'a, T: Sync + Send> Sync for ArcGuard<'a, T> {}
+
+mod test {
+    #[test]
+    fn test_counts() {
+        let arc = super::Arc::new(String::from("value"));
+        let clone = arc.clone();
+        let weak = super::Arc::downgrade(&arc);
+
+        assert_eq!(super::Arc::strong_count(&arc), 2);
+        assert_eq!(super::Arc::weak_count(&arc), 1);
+        assert!(super::Arc::ptr_eq(&arc, &clone));
+
+        drop(clone);
+        assert_eq!(weak.strong_count(), 1);
+        drop(arc);
+        assert!(weak.upgrade().is_none());
+        assert_eq!(weak.weak_count(), 0);
+    }
+
+    #[test]
+    fn test_get_mut_with_weak() {
+        let mut arc = super::Arc::new(1);
+        let weak = super::Arc::downgrade(&arc);
+        assert!(super::Arc::get_mut(&mut arc).is_none());
+        drop(weak);
+        *super::Arc::get_mut(&mut arc).unwrap() += 1;
+        assert_eq!(*arc, 2);
+    }
+
+    #[test]
+    fn test_make_mut_disassociates_weak() {
+        let mut arc = super::Arc::new(vec![1]);
+        let weak = super::Arc::downgrade(&arc);
+        super::Arc::make_mut(&mut arc).push(2);
+        assert!(weak.upgrade().is_none());
+        assert_eq!(*arc, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_drop_runs_once() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct Counted;
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let arc = super::Arc::new(Counted);
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let arc = arc.clone();
+                std::thread::spawn(move || {
+                    let weak = super::Arc::downgrade(&arc);
+                    for _ in 0..100 {
+                        drop(weak.upgrade());
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(DROPS.load(Ordering::SeqCst), 0);
+        drop(arc);
+        assert_eq!(DROPS.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_into_inner_exactly_once() {
+        for _ in 0..20 {
+            let arc = super::Arc::new(7);
+            let handles: Vec<_> = (0..4)
+                .map(|_| {
+                    let arc = arc.clone();
+                    std::thread::spawn(move || super::Arc::into_inner(arc))
+                })
+                .collect();
+            let mut found = super::Arc::into_inner(arc).into_iter().count();
+            for handle in handles {
+                found += handle.join().unwrap().into_iter().count();
+            }
+            assert_eq!(found, 1);
+        }
+    }
+
+    #[test]
+    fn test_atomic_arc_swap() {
+        let cell = super::AtomicArc::new(1);
+        let first = cell.load_full();
+        let old = cell.swap(super::Arc::new(2));
+        assert!(super::Arc::ptr_eq(&first, &old));
+        assert_eq!(*cell.load(), 2);
+        assert_eq!(super::Arc::strong_count(&old), 2);
+
+        let current = cell.load_full();
+        assert!(cell.compare_exchange(&first, super::Arc::new(3)).is_err());
+        assert_eq!(
+            *cell
+                .compare_exchange(&current, super::Arc::new(4))
+                .ok()
+                .unwrap(),
+            2
+        );
+        assert_eq!(*cell.into_inner(), 4);
+    }
+
+    #[test]
+    fn test_atomic_arc_store_while_reading() {
+        let cell = super::AtomicArc::new(String::from("one"));
+        let first = cell.load_full();
+        let guard = cell.load();
+        // Does not wait for the guard of this thread, which keeps the old value counted
+        cell.store(super::Arc::new(String::from("two")));
+        assert_eq!(super::Arc::strong_count(&first), 2);
+        let old = cell.swap(super::Arc::new(String::from("three")));
+        assert_eq!(*old, "two");
+        assert_eq!(super::Arc::strong_count(&old), 2);
+        assert_eq!(*guard, "one");
+
+        // The next load releases the `Arc`s the guard kept retired
+        drop(guard);
+        assert_eq!(*cell.load(), "three");
+        assert_eq!(super::Arc::strong_count(&first), 1);
+        assert_eq!(super::Arc::strong_count(&old), 1);
+    }
+
+    #[test]
+    fn test_atomic_arc_rcu() {
+        let cell = std::sync::Arc::new(super::AtomicArc::new(0usize));
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let cell = cell.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..100 {
+                        cell.rcu(|v| v + 1);
+                        assert!(*cell.load() > 0);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(*cell.load_full(), 400);
+    }
+
+    #[test]
+    fn test_unsized() {
+        use std::fmt::Display;
+
+        let display: super::Arc<dyn Display + Send + Sync> = super::Arc::new(String::from("dyn"));
+        let weak: super::Weak<dyn Display + Send + Sync> = super::Arc::downgrade(&display);
+        let handle = std::thread::spawn(move || weak.upgrade().map(|val| val.to_string()));
+        assert_eq!(handle.join().unwrap().as_deref(), Some("dyn"));
+        assert_eq!(super::Arc::weak_count(&display), 0);
+
+        let slice: super::Arc<[String]> = (0..3).map(|i| i.to_string()).collect();
+        assert_eq!(slice.concat(), "012");
+        assert_eq!(
+            super::Arc::into_inner(super::Arc::new([1, 2])),
+            Some([1, 2])
+        );
+        let empty: super::Arc<[u64]> = super::Arc::from(Vec::new());
+        assert!(empty.is_empty());
+        let boxed: super::Arc<[u8]> = super::Arc::from(Box::from([7u8; 5]));
+        assert_eq!(&*boxed, [7; 5]);
+        assert_eq!(&*super::Arc::<str>::from(String::from("string")), "string");
+    }
+
+    #[test]
+    fn test_unique_arc() {
+        let mut unique = super::UniqueArc::new(vec![1]);
+        let weak = super::UniqueArc::downgrade(&unique);
+        unique.push(2);
+
+        let handle = std::thread::spawn(move || {
+            assert!(weak.upgrade().is_none());
+            weak
+        });
+        let weak = handle.join().unwrap();
+
+        let arc: super::Arc<Vec<i32>> = super::UniqueArc::into_shared(unique);
+        let handle = std::thread::spawn(move || weak.upgrade().map(|val| val.len()));
+        assert_eq!(handle.join().unwrap(), Some(2));
+        assert_eq!(super::Arc::into_inner(arc), Some(vec![1, 2]));
+
+        let unique: super::UniqueArc<[u8]> = super::UniqueArc::new([7u8; 3]);
+        let weak = super::UniqueArc::downgrade(&unique);
+        drop(unique);
+        assert!(weak.upgrade().is_none());
+        assert_eq!(weak.strong_count(), 0);
+    }
+
+    #[test]
+    fn test_static() {
+        static VALUE: super::StaticArc<String> = super::StaticArc::new(String::new());
+
+        let mut arc = super::Arc::from_static(&VALUE);
+        let clones: Vec<_> = (0..4).map(|_| arc.clone()).collect();
+        let weak = super::Arc::downgrade(&arc);
+        let handles: Vec<_> = clones
+            .into_iter()
+            .map(|clone| std::thread::spawn(move || clone.len()))
+            .collect();
+        handles
+            .into_iter()
+            .for_each(|h| assert_eq!(h.join().unwrap(), 0));
+
+        assert_eq!(super::Arc::strong_count(&arc), usize::MAX);
+        assert_eq!(super::Arc::weak_count(&arc), 0);
+        assert_eq!(weak.weak_count(), 0);
+        assert!(super::Arc::ptr_eq(&weak.upgrade().unwrap(), &arc));
+        assert!(super::Arc::get_mut(&mut arc).is_none());
+        assert!(super::Arc::into_inner(arc.clone()).is_none());
+        super::Arc::make_mut(&mut arc).push_str("copy");
+        assert_eq!(*arc, "copy");
+        assert_eq!(*super::Arc::from_static(&VALUE), "");
+        drop(weak);
+        assert_eq!(
+            VALUE.0.counts.strong.load(super::Ordering::Relaxed),
+            super::IMMORTAL
+        );
+        assert_eq!(
+            VALUE.0.counts.weak.load(super::Ordering::Relaxed),
+            super::IMMORTAL_WEAK
+        );
+        assert!(!VALUE.0.counts.has_finalizer());
+    }
+
+    #[test]
+    fn test_static_count_never_moves() {
+        static VALUE: super::StaticArc<u32> = super::StaticArc::new(7);
+        let count = || VALUE.0.counts.strong.load(super::Ordering::Relaxed);
+
+        let arc = super::Arc::from_static(&VALUE);
+        let weak = super::Arc::downgrade(&arc);
+        for _ in 0..1000 {
+            drop(arc.clone());
+            drop(super::Arc::from_static(&VALUE));
+            drop(weak.upgrade());
+            assert_eq!(count(), super::IMMORTAL);
+        }
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let arc = arc.clone();
+                std::thread::spawn(move || (0..1000).for_each(|_| drop(arc.clone())))
+            })
+            .collect();
+        handles.into_iter().for_each(|h| h.join().unwrap());
+        drop((arc, weak));
+        assert_eq!(count(), super::IMMORTAL);
+    }
+
+    #[test]
+    fn test_finalizer() {
+        type Log = std::sync::Arc<std::sync::Mutex<Vec<&'static str>>>;
+        #[derive(Clone)]
+        struct Logged(Log, Vec<u8>);
+        impl Drop for Logged {
+            fn drop(&mut self) {
+                self.0.lock().unwrap().push("drop");
+            }
+        }
+        let new = |log: &Log| {
+            let finalizer_log = log.clone();
+            super::Arc::new_with_finalizer(Logged(log.clone(), vec![1]), move |val| {
+                val.1.push(2);
+                finalizer_log.lock().unwrap().push("finalize")
+            })
+        };
+
+        // Allocations without a finalizer do not make room for one
+        assert_eq!(std::mem::size_of::<super::Inner<u64>>(), 24);
+
+        let log = Log::default();
+        let arc = new(&log);
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let arc = arc.clone();
+                std::thread::spawn(move || drop(arc))
+            })
+            .collect();
+        drop(arc);
+        handles.into_iter().for_each(|h| h.join().unwrap());
+        assert_eq!(*log.lock().unwrap(), ["finalize", "drop"]);
+
+        log.lock().unwrap().clear();
+        let arc = new(&log);
+        let val = super::Arc::into_inner(arc).unwrap();
+        assert_eq!(val.1, [1]);
+        drop(val);
+        assert_eq!(*log.lock().unwrap(), ["drop"]);
+
+        log.lock().unwrap().clear();
+        let mut arc = new(&log);
+        let weak = super::Arc::downgrade(&arc);
+        assert_eq!(super::Arc::weak_count(&arc), 1);
+        assert!(super::Arc::get_mut(&mut arc).is_none());
+        super::Arc::make_mut(&mut arc).1.push(3);
+        assert!(weak.upgrade().is_none());
+        assert!(super::Arc::get_mut(&mut arc).is_some());
+        assert!(log.lock().unwrap().is_empty());
+        let rc = super::Arc::try_into_rc(arc).ok().unwrap();
+        drop(rc);
+        assert_eq!(*log.lock().unwrap(), ["finalize", "drop"]);
+    }
+
+    #[test]
+    fn test_allocator() {
+        use crate::test::Counting;
+
+        let alloc = Counting::default();
+        let arc = super::Arc::new_in(String::from("value"), alloc.clone());
+        let weak = super::Arc::downgrade(&arc);
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let arc = arc.clone();
+                std::thread::spawn(move || arc.len())
+            })
+            .collect();
+        drop(arc);
+        handles
+            .into_iter()
+            .for_each(|h| assert_eq!(h.join().unwrap(), 5));
+        assert!(weak.upgrade().is_none());
+        assert_eq!(alloc.live(), 1);
+        drop(weak);
+        assert_eq!(alloc.live(), 0);
+
+        let mut arc = super::Arc::try_new_in(vec![1], alloc.clone()).unwrap();
+        let weak = super::Arc::downgrade(&arc);
+        super::Arc::make_mut(&mut arc).push(2);
+        assert_eq!(alloc.live(), 2);
+        drop(weak);
+        assert_eq!(alloc.live(), 1);
+        assert_eq!(super::Arc::into_inner(arc).unwrap(), [1, 2]);
+        assert_eq!(alloc.live(), 0);
+    }
+
+    #[test]
+    fn test_uninit() {
+        let mut arc = super::Arc::<Vec<u8>>::try_new_uninit().unwrap();
+        super::Arc::get_mut(&mut arc).unwrap().write(vec![1, 2, 3]);
+        let arc = unsafe { arc.assume_init() };
+        let clone = arc.clone();
+        let handle = std::thread::spawn(move || clone.iter().sum::<u8>());
+        assert_eq!(handle.join().unwrap(), 6);
+        assert_eq!(super::Arc::into_inner(arc).unwrap(), [1, 2, 3]);
+
+        let zeroed = unsafe { super::Arc::<[u64; 256]>::new_zeroed().assume_init() };
+        assert_eq!(*zeroed, [0; 256]);
+        assert_eq!(*super::Arc::try_new(1).unwrap(), 1);
+    }
+}
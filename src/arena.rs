@@ -0,0 +1,272 @@
+use std::cell::{Cell, UnsafeCell};
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+
+/// The number of values the first chunk of an arena has room for, unless a capacity is given.
+const MIN_CHUNK: usize = 8;
+
+/// A value in an arena, together with the number of handles to it.
+struct Slot<T> {
+    count: Cell<usize>,
+    // Dropped by the last handle, never by the arena
+    val: UnsafeCell<ManuallyDrop<T>>,
+}
+
+/// The storage of an arena. Chunks are never reallocated, so slots never move.
+struct Chunks<T> {
+    current: Vec<Slot<T>>,
+    full: Vec<Vec<Slot<T>>>,
+}
+
+/// # speedy_refs::RcArena
+/// An arena handing out reference counted [`ArenaRc`] handles to the values stored in it, for
+/// building graphs, like the IR of a compiler, out of many values of the same type.
+///
+/// `RcArena::alloc` moves a value into the arena's current chunk and returns an `ArenaRc`, which
+/// can be cloned and dereferenced like an [`Rc`](crate::Rc). Cloning and dropping a handle only
+/// touches its count. When the last handle to a value is dropped, the value is dropped in place,
+/// but its memory stays in the arena. All of the storage is freed at once when the arena is dropped.
+///
+/// Handles borrow the arena, so values can point to each other with `ArenaRc`s, cycles included.
+/// Values still kept alive by a cycle when the arena is dropped are not dropped, because they may
+/// see each other while being dropped, but their storage is freed along with the rest of the
+/// arena. Anything they own outside of the arena, like the buffer of a `Vec`, is leaked.
+///
+/// # Examples
+///
+/// ```
+/// use std::cell::RefCell;
+/// use speedy_refs::{ArenaRc, RcArena};
+///
+/// struct Block<'a> {
+///     name: &'static str,
+///     successors: RefCell<Vec<ArenaRc<'a, Block<'a>>>>,
+/// }
+///
+/// let arena = RcArena::new();
+/// let block = |name| {
+///     arena.alloc(Block {
+///         name,
+///         successors: RefCell::new(Vec::new()),
+///     })
+/// };
+/// let entry = block("entry");
+/// let body = block("loop");
+/// let exit = block("exit");
+/// entry.successors.borrow_mut().push(body.clone());
+/// body.successors.borrow_mut().extend([body.clone(), exit.clone()]);
+///
+/// let names: Vec<_> = body.successors.borrow().iter().map(|block| block.name).collect();
+/// assert_eq!(names, ["loop", "exit"]);
+/// assert_eq!(ArenaRc::strong_count(&body), 3);
+/// // The loop keeps `body` alive, the arena frees its storage anyway
+/// ```
+pub struct RcArena<T> {
+    chunks: UnsafeCell<Chunks<T>>,
+}
+
+impl<T> RcArena<T> {
+    /// Creates an empty arena. Its first chunk is allocated with the first value.
+    pub const fn new() -> Self {
+        Self {
+            chunks: UnsafeCell::new(Chunks {
+                current: Vec::new(),
+                full: Vec::new(),
+            }),
+        }
+    }
+
+    /// Creates an arena whose first chunk has room for `capacity` values.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            chunks: UnsafeCell::new(Chunks {
+                current: Vec::with_capacity(capacity),
+                full: Vec::new(),
+            }),
+        }
+    }
+
+    /// Moves `val` into the arena and returns the first handle to it.
+    pub fn alloc(&self, val: T) -> ArenaRc<'_, T> {
+        // The arena is `!Sync` and never calls out while the chunks are borrowed
+        let chunks = unsafe { &mut *self.chunks.get() };
+        if chunks.current.len() == chunks.current.capacity() {
+            // Start a new chunk rather than growing the current one, which would move its slots
+            let capacity = (chunks.current.capacity() * 2).max(MIN_CHUNK);
+            let full = std::mem::replace(&mut chunks.current, Vec::with_capacity(capacity));
+            if full.capacity() > 0 {
+                chunks.full.push(full);
+            }
+        }
+        chunks.current.push(Slot {
+            count: Cell::new(1),
+            val: UnsafeCell::new(ManuallyDrop::new(val)),
+        });
+        let slot: *const Slot<T> = chunks.current.last().unwrap();
+        ArenaRc {
+            // The slot stays put until the arena is dropped, which the borrow of `self` prevents
+            slot: unsafe { &*slot },
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the number of values moved into the arena so far, including dropped ones.
+    pub fn len(&self) -> usize {
+        let chunks = unsafe { &*self.chunks.get() };
+        chunks.current.len() + chunks.full.iter().map(Vec::len).sum::<usize>()
+    }
+
+    /// Returns `true` if no value was ever moved into the arena.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Default for RcArena<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// # speedy_refs::ArenaRc
+/// A reference counted handle to a value in an [`RcArena`].
+///
+/// An `ArenaRc` is a single pointer that borrows the arena. Cloning it increments a count next to
+/// the value and dropping it decrements the count, without ever freeing memory. The last handle to
+/// a value drops the value in place.
+pub struct ArenaRc<'a, T> {
+    slot: &'a Slot<T>,
+    // The last handle drops the `T`
+    _marker: PhantomData<T>,
+}
+
+impl<'a, T> ArenaRc<'a, T> {
+    /// Returns the number of handles to this value.
+    pub fn strong_count(this: &Self) -> usize {
+        this.slot.count.get()
+    }
+
+    /// Returns `true` if both handles point to the same value.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        std::ptr::eq(this.slot, other.slot)
+    }
+
+    /// Returns a mutable reference to the value if there are no other handles to it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::{ArenaRc, RcArena};
+    ///
+    /// let arena = RcArena::new();
+    /// let mut x = arena.alloc(3);
+    /// *ArenaRc::get_mut(&mut x).unwrap() = 4;
+    /// assert_eq!(*x, 4);
+    ///
+    /// let _y = x.clone();
+    /// assert!(ArenaRc::get_mut(&mut x).is_none());
+    /// ```
+    pub fn get_mut(this: &mut Self) -> Option<&mut T> {
+        // We are the only handle to the value, so nobody else can observe it
+        (this.slot.count.get() == 1).then(|| unsafe { &mut **this.slot.val.get() })
+    }
+}
+
+impl<T> Clone for ArenaRc<'_, T> {
+    fn clone(&self) -> Self {
+        let count = self.slot.count.get();
+        // Leaked clones must not wrap the count around and drop the value under the other handles
+        if count == usize::MAX {
+            std::process::abort();
+        }
+        self.slot.count.set(count + 1);
+        Self {
+            slot: self.slot,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> std::ops::Deref for ArenaRc<'_, T> {
+    type Target = T;
+    #[inline]
+    fn deref(&self) -> &T {
+        // The value is only dropped by the last handle
+        unsafe { &*self.slot.val.get() }
+    }
+}
+
+impl<T> AsRef<T> for ArenaRc<'_, T> {
+    fn as_ref(&self) -> &T {
+        std::ops::Deref::deref(self)
+    }
+}
+
+impl<T> Drop for ArenaRc<'_, T> {
+    fn drop(&mut self) {
+        let count = self.slot.count.get() - 1;
+        self.slot.count.set(count);
+        if count == 0 {
+            unsafe { ManuallyDrop::drop(&mut *self.slot.val.get()) }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ArenaRc, RcArena};
+    use std::cell::{Cell, RefCell};
+
+    #[test]
+    fn test_arena_rc() {
+        let dropped = Cell::new(0);
+        struct Value<'d>(&'d Cell<usize>);
+        impl Drop for Value<'_> {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let arena = RcArena::with_capacity(1);
+        let first = arena.alloc(Value(&dropped));
+        let slots: Vec<_> = (0..20).map(|_| arena.alloc(Value(&dropped))).collect();
+        // Growing the arena does not move the values
+        assert!(std::ptr::eq(first.0, &dropped));
+        assert_eq!(arena.len(), 21);
+
+        let second = first.clone();
+        assert!(ArenaRc::ptr_eq(&first, &second));
+        assert!(!ArenaRc::ptr_eq(&first, &slots[0]));
+        assert_eq!(ArenaRc::strong_count(&first), 2);
+        drop(first);
+        assert_eq!(dropped.get(), 0);
+        drop(second);
+        assert_eq!(dropped.get(), 1);
+        drop(slots);
+        assert_eq!(dropped.get(), 21);
+        assert_eq!(arena.len(), 21);
+    }
+
+    #[test]
+    fn test_arena_rc_cycle() {
+        struct Node<'a> {
+            edges: RefCell<Vec<ArenaRc<'a, Node<'a>>>>,
+        }
+
+        let arena = RcArena::new();
+        let a = arena.alloc(Node {
+            edges: RefCell::new(Vec::new()),
+        });
+        let b = arena.alloc(Node {
+            edges: RefCell::new(vec![a.clone()]),
+        });
+        a.edges.borrow_mut().push(b.clone());
+        assert_eq!(ArenaRc::strong_count(&a), 2);
+        assert!(ArenaRc::ptr_eq(&a.edges.borrow()[0].edges.borrow()[0], &a));
+
+        // Breaking the cycle drops both nodes
+        let edges = std::mem::take(&mut *b.edges.borrow_mut());
+        drop((a, edges));
+        assert_eq!(ArenaRc::strong_count(&b), 1);
+    }
+}
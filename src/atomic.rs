@@ -1,35 +1,534 @@
-#[allow(dead_code)]
+use crate::sync::atomic::{self, AtomicBool, AtomicUsize, Ordering};
+
+/// # AtomicPtr
+/// An owned heap value behind an atomic pointer, that can be read and replaced from multiple threads
+/// without locking out the readers.
+///
+/// It is useful for sharing values that are read very often and replaced from time to time,
+/// such as configuration snapshots.
+///
+/// # How it works
+/// - `load` never blocks. It registers the reader in one of two reader counters, reads the pointer
+///   and returns a [`Guard`] that dereferences to the current value.
+/// - `store` and `compare_exchange` atomically install a new boxed value and never block. The old
+///   value is retired, and freed by a later `load`, `store` or `compare_exchange`, or by the drop of
+///   the `AtomicPtr`, once every reader that could still see it has dropped its `Guard`.
+/// - `swap` is the one blocking operation. It hands the old value back, so it waits for every reader
+///   that could still see it to drop its `Guard`. Readers that arrive after the new value was
+///   installed are never waited for.
+///
+/// # Deadlocks
+/// `swap` waits forever if the calling thread holds a `Guard` from the same `AtomicPtr`.
+///
+/// # Examples
+///
+/// ```
+/// use speedy_refs::AtomicPtr;
+/// use std::sync::Arc;
+/// use std::thread;
+///
+/// #[derive(Debug, PartialEq)]
+/// struct Config {
+///     version: u32,
+/// }
+///
+/// let config = Arc::new(AtomicPtr::new(Config { version: 1 }));
+///
+/// let reader = {
+///     let config = config.clone();
+///     thread::spawn(move || {
+///         let snapshot = config.load();
+///         assert!(snapshot.version >= 1);
+///     })
+/// };
+///
+/// let old = config.swap(Box::new(Config { version: 2 }));
+/// assert_eq!(*old, Config { version: 1 });
+///
+/// reader.join().unwrap();
+/// assert_eq!(config.load().version, 2);
+/// ```
 pub struct AtomicPtr<T> {
-    ptr: std::sync::atomic::AtomicPtr<T>,
+    ptr: crate::sync::atomic::AtomicPtr<T>,
+    epochs: Epochs<Retired<T>>,
+}
+
+/// A read guard returned by `AtomicPtr::load`.
+///
+/// The value it dereferences to stays alive until the guard is dropped,
+/// even if the `AtomicPtr` has been given a new value in the meantime.
+pub struct Guard<'a, T> {
+    cell: &'a AtomicPtr<T>,
+    ptr: std::ptr::NonNull<T>,
+    epoch: usize,
 }
 
 impl<T> std::fmt::Pointer for AtomicPtr<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        std::fmt::Pointer::fmt(&self, f)
+        std::fmt::Pointer::fmt(&self.ptr.load(Ordering::Relaxed), f)
+    }
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for AtomicPtr<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("AtomicPtr").field(&*self.load()).finish()
+    }
+}
+
+impl<T: Default> Default for AtomicPtr<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> From<Box<T>> for AtomicPtr<T> {
+    fn from(value: Box<T>) -> Self {
+        Self::from_box(value)
     }
 }
 
-#[allow(unused)]
 impl<T> AtomicPtr<T> {
+    /// Moves `value` onto the heap and creates a new `AtomicPtr` pointing to it.
     pub fn new(value: T) -> Self {
+        Self::from_box(Box::new(value))
+    }
+
+    /// Creates a new `AtomicPtr` that takes ownership of an already boxed value.
+    pub fn from_box(value: Box<T>) -> Self {
         Self {
-            ptr: std::sync::atomic::AtomicPtr::new(Box::leak(Box::new(value)) as *mut T),
+            ptr: crate::sync::atomic::AtomicPtr::new(Box::into_raw(value)),
+            epochs: Epochs::new(),
+        }
+    }
+
+    /// Returns a guard to the current value. This never blocks.
+    pub fn load(&self) -> Guard<'_, T> {
+        self.epochs.collect();
+        let epoch = self.epochs.enter();
+        // The pointer must be read after registering as a reader, so that a writer
+        // either waits for us or we see its new value.
+        let ptr = self.ptr.load(Ordering::SeqCst);
+        Guard {
+            cell: self,
+            // The pointer always comes from `Box::into_raw`
+            ptr: unsafe { std::ptr::NonNull::new_unchecked(ptr) },
+            epoch,
         }
     }
 
-    pub fn load_mut<R, F: Fn(&mut T) -> R>(&self, f: F, ordering: std::sync::atomic::Ordering) -> R {
-        f(self.as_mut(ordering))
+    /// Calls `f` with a reference to the current value and returns its result.
+    pub fn load_ref<R, F: FnOnce(&T) -> R>(&self, f: F) -> R {
+        f(&self.load())
     }
 
-    pub fn load_ref<R, F: Fn(&T) -> R>(&self, f: F, ordering: std::sync::atomic::Ordering) -> R {
-        f(self.as_ref(ordering))
+    /// Replaces the current value with `value`. This never blocks, the old value is dropped
+    /// later, once no reader can see it anymore.
+    pub fn store(&self, value: Box<T>) {
+        let old = self.ptr.swap(Box::into_raw(value), Ordering::SeqCst);
+        self.epochs.retire(unsafe { Retired::new(old) });
+    }
+
+    /// Replaces the current value with `value` and returns the old one
+    /// once no reader can see it anymore.
+    ///
+    /// This is the one operation that blocks: it waits for the readers of the old value to drop
+    /// their guards. Use `store` to never wait.
+    ///
+    /// # Deadlocks
+    /// If the calling thread itself holds a `Guard` from this `AtomicPtr`.
+    pub fn swap(&self, value: Box<T>) -> Box<T> {
+        let old = self.ptr.swap(Box::into_raw(value), Ordering::SeqCst);
+        self.epochs.synchronize();
+        // Every reader of `old` is gone and no new reader can reach it
+        unsafe { Box::from_raw(old) }
+    }
+
+    /// Replaces the current value with `new` if the current value is still the one `current` points to.
+    /// This never blocks, the old value is dropped later, once no reader can see it anymore.
+    ///
+    /// The guard is consumed so that the value it points to can not be freed, and its address reused,
+    /// before the comparison is made.
+    ///
+    /// # Returns
+    /// * `Ok(current)` - The value was replaced, `current` is handed back and still points to the old value
+    /// * `Err(new)` - `new` is handed back if the value had already been replaced
+    ///
+    /// # Panics
+    /// If `current` was not obtained from this `AtomicPtr`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::AtomicPtr;
+    ///
+    /// let cell = AtomicPtr::new(1);
+    ///
+    /// // Increment the value, retrying if another thread replaced it in the meantime
+    /// let old = loop {
+    ///     let current = cell.load();
+    ///     let next = Box::new(*current + 1);
+    ///     if let Ok(old) = cell.compare_exchange(current, next) {
+    ///         break old;
+    ///     }
+    /// };
+    ///
+    /// assert_eq!(*old, 1);
+    /// assert_eq!(*cell.load(), 2);
+    /// ```
+    pub fn compare_exchange<'a>(
+        &'a self,
+        current: Guard<'a, T>,
+        new: Box<T>,
+    ) -> Result<Guard<'a, T>, Box<T>> {
+        assert!(
+            std::ptr::eq(current.cell, self),
+            "the guard does not belong to this AtomicPtr"
+        );
+
+        let new = Box::into_raw(new);
+        match self.ptr.compare_exchange(
+            current.ptr.as_ptr(),
+            new,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        ) {
+            Ok(old) => {
+                // `current` registered before the flip, so it keeps the old value alive
+                self.epochs.retire(unsafe { Retired::new(old) });
+                Ok(current)
+            }
+            // `new` was never published
+            Err(_) => Err(unsafe { Box::from_raw(new) }),
+        }
     }
 
-    pub fn as_mut(&self, ordering: std::sync::atomic::Ordering) -> &mut T {
-        todo!()
+    /// Returns a mutable reference to the current value.
+    ///
+    /// No guard can be alive since this requires exclusive access to the `AtomicPtr`.
+    pub fn get_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.ptr.load(Ordering::Relaxed) }
     }
 
-    pub fn as_ref(&self, ordering: std::sync::atomic::Ordering) -> &T {
-        todo!()
+    /// Consumes the `AtomicPtr` and returns the current value.
+    pub fn into_inner(self) -> T {
+        let mut this = std::mem::ManuallyDrop::new(self);
+        // No guard is left, so dropping the reader registration frees the retired values
+        unsafe { std::ptr::drop_in_place(&mut this.epochs) };
+        *unsafe { Box::from_raw(this.ptr.load(Ordering::Relaxed)) }
     }
-}
\ No newline at end of file
+}
+
+impl<T> Drop for AtomicPtr<T> {
+    fn drop(&mut self) {
+        // No guard can outlive the `AtomicPtr` it borrows, the retired values are freed with `epochs`
+        drop(unsafe { Box::from_raw(self.ptr.load(Ordering::Relaxed)) });
+    }
+}
+
+/// A value unlinked from an `AtomicPtr`, freed when dropped.
+///
+/// Kept as a pointer rather than a `Box`, since readers may still hold references to the value.
+pub(crate) struct Retired<T>(std::ptr::NonNull<T>);
+
+impl<T> Retired<T> {
+    /// # Safety
+    /// `ptr` must come from `Box::into_raw` and be owned by the `Retired`.
+    unsafe fn new(ptr: *mut T) -> Self {
+        Self(std::ptr::NonNull::new_unchecked(ptr))
+    }
+}
+
+impl<T> Drop for Retired<T> {
+    fn drop(&mut self) {
+        drop(unsafe { Box::from_raw(self.0.as_ptr()) });
+    }
+}
+
+impl<'a, T> Guard<'a, T> {
+    /// Returns the address of the value this guard points to.
+    pub fn as_ptr(&self) -> *const T {
+        self.ptr.as_ptr()
+    }
+}
+
+impl<'a, T> std::ops::Deref for Guard<'a, T> {
+    type Target = T;
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        // The value is not freed before this guard is dropped
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<'a, T> AsRef<T> for Guard<'a, T> {
+    fn as_ref(&self) -> &T {
+        std::ops::Deref::deref(self)
+    }
+}
+
+impl<'a, T> Drop for Guard<'a, T> {
+    #[inline]
+    fn drop(&mut self) {
+        self.cell.epochs.leave(self.epoch);
+    }
+}
+
+/// The reader registration behind [`AtomicPtr`] and `AtomicArc`, and the values their writers
+/// unlinked but readers may still be using.
+///
+/// Readers register in one of two counters, chosen by the parity of `epoch`, and read the shared
+/// pointer afterwards. A reader that could still see an unlinked value registered before the writer
+/// that unlinked it flipped the parity, but in either counter, since other writers flip it too.
+/// The value is dropped once each counter has been seen empty after that flip. New readers only
+/// go to one of them, which stops draining, so whoever finds a value waiting on it flips the parity.
+///
+/// A reader checks the parity again once registered, and moves to the other counter if a writer
+/// flipped it in between. Otherwise a reader that read the parity before a flip, but registered
+/// after its counter was seen empty, would sit in a counter nothing looks at for it anymore.
+pub(crate) struct Epochs<R> {
+    /// Selects which of the two `readers` counters new readers register in.
+    epoch: AtomicUsize,
+    /// Number of live readers registered during each epoch parity.
+    readers: [AtomicUsize; 2],
+    /// Values unlinked by writers, with which counters have been seen empty since.
+    retired: crate::sync::Mutex<Vec<([bool; 2], R)>>,
+    /// Set while `retired` is not empty, so that readers only lock it when there is work to do.
+    has_retired: AtomicBool,
+}
+
+impl<R> Epochs<R> {
+    pub(crate) fn new() -> Self {
+        Self {
+            epoch: AtomicUsize::new(0),
+            readers: [AtomicUsize::new(0), AtomicUsize::new(0)],
+            retired: crate::sync::Mutex::new(Vec::new()),
+            has_retired: AtomicBool::new(false),
+        }
+    }
+
+    /// Registers a reader and returns the epoch parity to pass to `leave`.
+    ///
+    /// The shared pointer must be read after this call.
+    #[inline]
+    pub(crate) fn enter(&self) -> usize {
+        let mut epoch = self.epoch.load(Ordering::Relaxed) & 1;
+        loop {
+            self.readers[epoch].fetch_add(1, Ordering::Relaxed);
+            // Pairs with the fence in `flip`: either the writer sees this registration,
+            // or we see its flip, and the pointer it unlinked before flipping is gone for us too.
+            atomic::fence(Ordering::SeqCst);
+            let current = self.epoch.load(Ordering::Acquire) & 1;
+            // Once the parity is seen unchanged after registering, every later flip counts us
+            if current == epoch {
+                return epoch;
+            }
+            self.readers[epoch].fetch_sub(1, Ordering::Release);
+            epoch = current;
+        }
+    }
+
+    /// Unregisters a reader returned by `enter`.
+    #[inline]
+    pub(crate) fn leave(&self, epoch: usize) {
+        // Our reads of the value happen before the thread that sees the counter drain drops it
+        self.readers[epoch].fetch_sub(1, Ordering::Release);
+    }
+
+    /// Makes new readers register with the other parity, and returns the one they used to.
+    ///
+    /// After the old pointer has been unlinked, every reader that could still see it has confirmed
+    /// its registration before the flip, since it reads the pointer only after `enter` returns.
+    /// The counters read after the flip, or after a lock taken after it, count that reader until
+    /// it leaves.
+    fn flip(&self) -> usize {
+        let old = self.epoch.fetch_xor(1, Ordering::AcqRel) & 1;
+        atomic::fence(Ordering::SeqCst);
+        old
+    }
+
+    /// Hands a value unlinked from the shared pointer over, to be dropped once no reader can see it.
+    /// This never waits for the readers.
+    pub(crate) fn retire(&self, value: R) {
+        self.flip();
+        {
+            let mut retired = self
+                .retired
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            retired.push(([false; 2], value));
+            self.has_retired.store(true, Ordering::Relaxed);
+        }
+        self.collect();
+    }
+
+    /// Drops the retired values whose readers have all left. This never waits, it gives up
+    /// if another thread is already collecting.
+    #[inline]
+    pub(crate) fn collect(&self) {
+        if self.has_retired.load(Ordering::Relaxed) {
+            self.collect_slow();
+        }
+    }
+
+    #[cold]
+    fn collect_slow(&self) {
+        let mut retired = match self.retired.try_lock() {
+            Ok(retired) => retired,
+            Err(std::sync::TryLockError::Poisoned(err)) => err.into_inner(),
+            Err(std::sync::TryLockError::WouldBlock) => return,
+        };
+        // Orders the counters read below after the flips of the writers that retired the values
+        atomic::fence(Ordering::SeqCst);
+        let empty = [
+            self.readers[0].load(Ordering::Acquire) == 0,
+            self.readers[1].load(Ordering::Acquire) == 0,
+        ];
+        let mut ready = Vec::new();
+        let mut i = 0;
+        while i < retired.len() {
+            let drained = &mut retired[i].0;
+            drained[0] |= empty[0];
+            drained[1] |= empty[1];
+            if drained[0] && drained[1] {
+                ready.push(retired.swap_remove(i).1);
+            } else {
+                i += 1;
+            }
+        }
+
+        // New readers keep the counter they register in from draining, send them to the other one
+        let current = self.epoch.load(Ordering::Relaxed) & 1;
+        if retired.iter().any(|(drained, _)| !drained[current]) {
+            self.flip();
+        }
+        self.has_retired
+            .store(!retired.is_empty(), Ordering::Relaxed);
+        // The values may use the `AtomicPtr` or `AtomicArc` again when they are dropped
+        drop(retired);
+        drop(ready);
+    }
+
+    /// Waits for a grace period: every reader that was registered before the call has left.
+    ///
+    /// Must be called after the old pointer has been unlinked, see `flip`.
+    pub(crate) fn synchronize(&self) {
+        let mut drained = [false; 2];
+        while !(drained[0] && drained[1]) {
+            // Waits on the counter new readers just moved away from, so that it can only drain
+            let old = self.flip();
+            let mut spins = 0u32;
+            while self.readers[old].load(Ordering::Acquire) != 0 {
+                if spins < 64 {
+                    crate::sync::hint::spin_loop();
+                    spins += 1;
+                } else {
+                    crate::sync::thread::yield_now();
+                }
+            }
+            drained[old] = true;
+        }
+    }
+}
+
+unsafe impl<T: Send> Send for AtomicPtr<T> {}
+unsafe impl<T: Send + Sync> Sync for AtomicPtr<T> {}
+unsafe impl<'a, T: Sync> Sync for Guard<'a, T> {}
+
+mod test {
+    #[test]
+    fn test_swap_and_load() {
+        let cell = super::AtomicPtr::new(String::from("one"));
+        let guard = cell.load();
+        assert_eq!(*guard, "one");
+        drop(guard);
+
+        let old = cell.swap(Box::new(String::from("two")));
+        assert_eq!(*old, "one");
+        assert_eq!(cell.load_ref(|s| s.len()), 3);
+        assert_eq!(cell.into_inner(), "two");
+    }
+
+    #[test]
+    fn test_readers_never_see_freed_values() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let cell = std::sync::Arc::new(super::AtomicPtr::new(vec![0usize; 16]));
+        let done = std::sync::Arc::new(AtomicBool::new(false));
+
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let cell = cell.clone();
+                let done = done.clone();
+                std::thread::spawn(move || {
+                    while !done.load(Ordering::Relaxed) {
+                        let snapshot = cell.load();
+                        // Every snapshot is internally consistent
+                        assert!(snapshot.iter().all(|v| *v == snapshot[0]));
+                    }
+                })
+            })
+            .collect();
+
+        let writers: Vec<_> = (1..3)
+            .map(|w| {
+                let cell = cell.clone();
+                std::thread::spawn(move || {
+                    for i in 0..50 {
+                        cell.store(Box::new(vec![w * 1000 + i; 16]));
+                    }
+                })
+            })
+            .collect();
+
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        done.store(true, Ordering::Relaxed);
+        for reader in readers {
+            reader.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_compare_exchange_counter() {
+        let cell = std::sync::Arc::new(super::AtomicPtr::new(0usize));
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let cell = cell.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..100 {
+                        loop {
+                            let current = cell.load();
+                            let next = Box::new(*current + 1);
+                            if cell.compare_exchange(current, next).is_ok() {
+                                break;
+                            }
+                        }
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(*cell.load(), 400);
+    }
+
+    #[test]
+    fn test_store_while_reading() {
+        let cell = super::AtomicPtr::new(String::from("one"));
+        let guard = cell.load();
+        // Does not wait for the guard of this thread
+        cell.store(Box::new(String::from("two")));
+        let current = cell.load();
+        let old = cell
+            .compare_exchange(current, Box::new(String::from("three")))
+            .unwrap();
+        assert_eq!(*old, "two");
+        assert_eq!(*guard, "one");
+        assert_eq!(*cell.load(), "three");
+        drop(old);
+        drop(guard);
+        assert_eq!(*cell.swap(Box::new(String::from("four"))), "three");
+    }
+}
@@ -0,0 +1,472 @@
+use std::cell::Cell;
+use std::sync::atomic::{self, AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Set in `Inner::shared` once every reference is counted there and `biased` is no longer used.
+const MERGED: usize = 1;
+/// Set in `Inner::shared` while the allocation waits in its owner's queue to be merged.
+const QUEUED: usize = 2;
+/// The shared count is stored above the two flags, as a wrapping signed number.
+const ONE: usize = 4;
+/// A soft limit on both counts, half of what the shared word can hold, so that a count running
+/// away through `mem::forget` is caught long before it wraps around.
+const MAX_REFCOUNT: isize = isize::MAX / ONE as isize / 2;
+
+/// The id of a thread that has never created a `BiasedArc`.
+const UNREGISTERED: usize = 0;
+/// The id of a thread whose `Owner` has been destroyed.
+const EXITED: usize = 1;
+/// The owner of allocations created without an owner thread, which matches no thread id.
+const UNOWNED: usize = usize::MAX;
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(EXITED + 1);
+
+thread_local! {
+    static ID: Cell<usize> = const { Cell::new(UNREGISTERED) };
+    static OWNER: Owner = Owner::register();
+}
+
+/// The allocations created by one thread, together with their queue of pending merges.
+struct Owner {
+    id: usize,
+    queue: std::sync::Arc<Queue>,
+}
+
+impl Owner {
+    fn register() -> Self {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        ID.with(|cell| cell.set(id));
+        Self {
+            id,
+            queue: std::sync::Arc::new(Queue {
+                dirty: AtomicBool::new(false),
+                pending: Mutex::new(Some(Vec::new())),
+            }),
+        }
+    }
+}
+
+impl Drop for Owner {
+    fn drop(&mut self) {
+        // From now on this thread uses the shared count like any other thread
+        ID.with(|cell| cell.set(EXITED));
+        let pending = self.queue.pending.lock().unwrap().take();
+        for merge in pending.into_iter().flatten() {
+            unsafe { (merge.merge)(merge.ptr) };
+        }
+    }
+}
+
+/// Allocations whose shared count went negative, waiting for their owner to merge their counts.
+///
+/// `pending` is `None` once the owner has exited, in which case the thread that would queue an
+/// allocation merges it itself.
+struct Queue {
+    dirty: AtomicBool,
+    pending: Mutex<Option<Vec<Merge>>>,
+}
+
+impl Queue {
+    /// Merges every queued allocation. Must only be called by the owner.
+    fn process(&self) {
+        if !self.dirty.load(Ordering::Relaxed) || !self.dirty.swap(false, Ordering::Acquire) {
+            return;
+        }
+        // Taken out of the lock first, merging may drop values that queue other allocations
+        let pending = self
+            .pending
+            .lock()
+            .unwrap()
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default();
+        for merge in pending {
+            unsafe { (merge.merge)(merge.ptr) };
+        }
+    }
+}
+
+/// A queued allocation, with the function merging it for its type.
+struct Merge {
+    ptr: *const (),
+    merge: unsafe fn(*const ()),
+}
+
+// The allocation is only merged by its owner, or once its owner has exited
+unsafe impl Send for Merge {}
+
+/// Returns the id of the current thread, without registering it.
+#[inline(always)]
+fn current_thread() -> usize {
+    ID.with(Cell::get)
+}
+
+/// Decodes the signed count stored in a `shared` word.
+#[inline(always)]
+fn count(shared: usize) -> isize {
+    (shared as isize) >> 2
+}
+
+struct Inner<T> {
+    owner: usize,
+    /// The references counted by the owner thread. Only the owner reads or writes it.
+    biased: Cell<usize>,
+    /// The owner's copy of the `MERGED` flag, so that its fast path does not touch `shared`.
+    merged: Cell<bool>,
+    /// The references counted by every other thread, and the `MERGED` and `QUEUED` flags.
+    /// The count goes negative when references counted in `biased` are dropped elsewhere.
+    shared: AtomicUsize,
+    queue: Option<std::sync::Arc<Queue>>,
+    val: T,
+}
+
+/// # speedy_refs::BiasedArc
+/// A thread-safe reference-counting pointer that counts without atomics on the thread that created it.
+///
+/// Following biased reference counting, the owner thread clones and drops through a plain counter
+/// and every other thread through an atomic one. The counts are merged when the owner's reaches zero,
+/// or when another thread drops references the owner counted, driving the shared count negative.
+/// In that last case the allocation is queued, and merged by the owner on its next `BiasedArc::new`
+/// or drop, or when it exits.
+///
+/// Where most clones and drops happen on the creating thread it is as cheap as [`Rc`](crate::Rc),
+/// and elsewhere about as cheap as [`Arc`](crate::Arc).
+///
+/// # Examples
+///
+/// ```
+/// use speedy_refs::BiasedArc;
+///
+/// let config = BiasedArc::new(String::from("fast"));
+/// let local = config.clone();
+///
+/// let handle = std::thread::spawn(move || config.len());
+/// assert_eq!(handle.join().unwrap(), 4);
+/// assert_eq!(*local, "fast");
+/// ```
+pub struct BiasedArc<T> {
+    inner: std::ptr::NonNull<Inner<T>>,
+    _marker: std::marker::PhantomData<Inner<T>>,
+}
+
+impl<T> BiasedArc<T> {
+    /// Creates a new `BiasedArc` owned by the current thread.
+    pub fn new(val: T) -> Self {
+        let (owner, queue, biased, shared) = match OWNER.try_with(|owner| {
+            owner.queue.process();
+            (owner.id, owner.queue.clone())
+        }) {
+            Ok((id, queue)) => (id, Some(queue), 1, 0),
+            // The thread is exiting, so every reference is counted in `shared` from the start
+            Err(_) => (UNOWNED, None, 0, ONE | MERGED),
+        };
+
+        let inner = Box::new(Inner {
+            owner,
+            biased: Cell::new(biased),
+            merged: Cell::new(queue.is_none()),
+            shared: AtomicUsize::new(shared),
+            queue,
+            val,
+        });
+        Self {
+            inner: std::ptr::NonNull::from(Box::leak(inner)),
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    /// Returns `true` if both `BiasedArc`s point to the same allocation.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        this.inner == other.inner
+    }
+
+    #[inline(always)]
+    fn inner(&self) -> &Inner<T> {
+        // The allocation stays valid while this `BiasedArc` is alive
+        unsafe { self.inner.as_ref() }
+    }
+
+    #[inline(always)]
+    fn is_owner(&self) -> bool {
+        self.inner().owner == current_thread()
+    }
+
+    /// Moves the owner's count into the shared one. Must only be called by the owner,
+    /// or by any thread once the owner has exited.
+    unsafe fn merge(ptr: *const ()) {
+        let inner = &*ptr.cast::<Inner<T>>();
+        let biased = inner.biased.replace(0);
+        inner.merged.set(true);
+
+        let mut cur = inner.shared.load(Ordering::Relaxed);
+        let new = loop {
+            let new = (cur.wrapping_add(biased.wrapping_mul(ONE)) | MERGED) & !QUEUED;
+            match inner
+                .shared
+                .compare_exchange_weak(cur, new, Ordering::AcqRel, Ordering::Relaxed)
+            {
+                Ok(_) => break new,
+                Err(old) => cur = old,
+            }
+        };
+        if count(new) == 0 {
+            Self::dealloc(ptr.cast_mut().cast());
+        }
+    }
+
+    /// Drops the value and frees the allocation.
+    unsafe fn dealloc(ptr: *mut Inner<T>) {
+        drop(Box::from_raw(ptr));
+    }
+
+    #[inline(never)]
+    fn clone_shared(&self) {
+        let old = self.inner().shared.fetch_add(ONE, Ordering::Relaxed);
+        // The shared count goes negative when other threads drop references the owner counted
+        if !(-MAX_REFCOUNT..=MAX_REFCOUNT).contains(&count(old)) {
+            std::process::abort();
+        }
+    }
+
+    #[inline(never)]
+    fn drop_shared(&mut self) {
+        let inner = self.inner();
+        let new = inner
+            .shared
+            .fetch_sub(ONE, Ordering::Release)
+            .wrapping_sub(ONE);
+        if new & (MERGED | QUEUED) == MERGED && count(new) == 0 {
+            atomic::fence(Ordering::Acquire);
+            unsafe { Self::dealloc(self.inner.as_ptr()) };
+            return;
+        }
+
+        // The owner counted this reference, and may never drop to zero on its own. Whichever
+        // thread leaves the count negative and unflagged queues the allocation.
+        let mut cur = new;
+        let queue = loop {
+            if cur & (MERGED | QUEUED) != 0 || count(cur) >= 0 {
+                break false;
+            }
+            match inner.shared.compare_exchange_weak(
+                cur,
+                cur | QUEUED,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break true,
+                Err(old) => cur = old,
+            }
+        };
+
+        if queue {
+            // Allocations without an owner are merged from the start, so never get queued
+            let owner = inner.queue.as_ref().unwrap();
+            let merge = Merge {
+                ptr: self.inner.as_ptr().cast_const().cast(),
+                merge: Self::merge,
+            };
+            let mut pending = owner.pending.lock().unwrap();
+            match pending.as_mut() {
+                Some(pending) => {
+                    pending.push(merge);
+                    owner.dirty.store(true, Ordering::Release);
+                }
+                None => {
+                    // The owner has exited and its last access to `biased` happens before
+                    // it closed the queue, which happens before this.
+                    drop(pending);
+                    unsafe { Self::merge(merge.ptr) };
+                }
+            }
+        }
+    }
+}
+
+impl<T> Clone for BiasedArc<T> {
+    #[inline]
+    fn clone(&self) -> Self {
+        let inner = self.inner();
+        if self.is_owner() && !inner.merged.get() {
+            let biased = inner.biased.get();
+            if biased > MAX_REFCOUNT as usize {
+                std::process::abort();
+            }
+            inner.biased.set(biased + 1);
+        } else {
+            self.clone_shared();
+        }
+        Self {
+            inner: self.inner,
+            _marker: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<T> Drop for BiasedArc<T> {
+    #[inline]
+    fn drop(&mut self) {
+        if !self.is_owner() {
+            return self.drop_shared();
+        }
+
+        let inner = self.inner();
+        if let Some(queue) = &inner.queue {
+            queue.process();
+        }
+        if inner.merged.get() {
+            return self.drop_shared();
+        }
+
+        let biased = inner.biased.get() - 1;
+        inner.biased.set(biased);
+        if biased == 0 {
+            inner.merged.set(true);
+            let new = inner.shared.fetch_or(MERGED, Ordering::AcqRel) | MERGED;
+            if new & QUEUED == 0 && count(new) == 0 {
+                unsafe { Self::dealloc(self.inner.as_ptr()) };
+            }
+        }
+    }
+}
+
+impl<T> std::ops::Deref for BiasedArc<T> {
+    type Target = T;
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        &self.inner().val
+    }
+}
+
+impl<T> AsRef<T> for BiasedArc<T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T: Default> Default for BiasedArc<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> From<T> for BiasedArc<T> {
+    fn from(val: T) -> Self {
+        Self::new(val)
+    }
+}
+
+// `biased` and `merged` are only touched by the owner, everything else is atomic
+unsafe impl<T: Sync + Send> Send for BiasedArc<T> {}
+unsafe impl<T: Sync + Send> Sync for BiasedArc<T> {}
+
+#[cfg(test)]
+mod test {
+    use super::BiasedArc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct Counted(&'static AtomicUsize);
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_owner_and_shared_counts() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        let arc = BiasedArc::new(Counted(&DROPS));
+        let clones: Vec<_> = (0..4).map(|_| arc.clone()).collect();
+        let handles: Vec<_> = clones
+            .into_iter()
+            .map(|clone| {
+                std::thread::spawn(move || {
+                    let more = clone.clone();
+                    drop(clone);
+                    more
+                })
+            })
+            .collect();
+        // Every thread clones before it drops, so the shared count never goes negative
+        let returned: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(DROPS.load(Ordering::Relaxed), 0);
+
+        drop(returned);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 0);
+        drop(arc);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 1);
+
+        let arc = BiasedArc::new(Counted(&DROPS));
+        let clone = arc.clone();
+        drop(arc);
+        drop(clone);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_owner_exits() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        let (arc, other) = std::thread::spawn(|| {
+            let arc = BiasedArc::new(Counted(&DROPS));
+            (arc.clone(), arc)
+        })
+        .join()
+        .unwrap();
+
+        drop(arc);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 0);
+        let clone = other.clone();
+        drop(other);
+        assert!(std::thread::spawn(move || drop(clone)).join().is_ok());
+        assert_eq!(DROPS.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_queued_while_owner_lives() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        let arc = BiasedArc::new(Counted(&DROPS));
+        let sent = arc.clone();
+        std::thread::spawn(move || drop(sent)).join().unwrap();
+        // Merged on the owner's next drop, which is also the last one
+        drop(arc);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 1);
+
+        let arc = BiasedArc::new(Counted(&DROPS));
+        let sent = arc.clone();
+        std::thread::spawn(move || drop(sent)).join().unwrap();
+        // Merged by `new`, so the next drop already goes through the shared count
+        drop(BiasedArc::new(0));
+        assert!(arc.inner().merged.get());
+        drop(arc);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_concurrent_drops() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        for round in 0..50 {
+            let arc = BiasedArc::new(Counted(&DROPS));
+            let handles: Vec<_> = (0..4)
+                .map(|_| {
+                    let clones: Vec<_> = (0..8).map(|_| arc.clone()).collect();
+                    std::thread::spawn(move || {
+                        for clone in clones {
+                            drop(clone.clone());
+                        }
+                    })
+                })
+                .collect();
+            for _ in 0..8 {
+                drop(arc.clone());
+            }
+            handles.into_iter().for_each(|h| h.join().unwrap());
+            drop(arc);
+            assert_eq!(DROPS.load(Ordering::Relaxed), round + 1);
+        }
+    }
+}
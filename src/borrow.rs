@@ -91,10 +91,19 @@ impl<T> Borrow<T> {
         }
     }
 
+    /// Creates a new `Borrow` instance with the specified initial value, or returns an error
+    /// instead of aborting if the allocation fails. The value is dropped in that case.
+    pub fn try_new(value: T) -> std::result::Result<Borrow<T>, std::alloc::AllocError> {
+        Ok(Self {
+            value: std::rc::Rc::try_new(SharedCell::new(value))?,
+        })
+    }
+
     pub(crate) fn get_ref(&self) -> &T {
         unsafe { self.value.get_ref() }
     }
 
+    #[allow(clippy::mut_from_ref)]
     pub(crate) fn get_mut(&self) -> &mut T {
         unsafe { self.value.get_mut() }
     }
@@ -104,9 +113,6 @@ impl<T: PartialEq> PartialEq for Borrow<T> {
     fn eq(&self, other: &Self) -> bool {
         PartialEq::eq(self.get_ref(), other.get_ref())
     }
-    fn ne(&self, other: &Self) -> bool {
-        PartialEq::ne(self.get_ref(), other.get_ref())
-    }
 }
 
 impl<T: Eq> Eq for Borrow<T> {}
@@ -143,7 +149,7 @@ impl<T: Ord> Ord for Borrow<T> {
 
 pub(crate) use std::hash::{Hash, Hasher};
 impl<T: Hash> Hash for Borrow<T> {
-    fn hash<'a, H: Hasher>(&'a self, state: &mut H) {
+    fn hash<H: Hasher>(&self, state: &mut H) {
         self.get_ref().hash(state)
     }
 }
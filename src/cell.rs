@@ -30,15 +30,35 @@
 ///     cell.drop_n_dealloc();
 /// }
 /// ```
-
-pub struct HeapCell<T> {
-    inner: *mut T,
+///
+/// ## Allocators
+/// `HeapCell::new_in` and `HeapCell::try_new_in` move the data into memory from any `Allocator`.
+/// `dealloc` and `drop_n_dealloc` give the memory back to that same allocator.
+///
+/// ```
+/// #![feature(allocator_api)]
+/// use speedy_refs::HeapCell;
+/// use std::alloc::System;
+///
+/// let cell = HeapCell::new_in([1u8; 64], System);
+/// unsafe {
+///     assert_eq!(cell.as_ref()[63], 1);
+///     cell.drop_n_dealloc();
+/// }
+/// ```
+pub struct HeapCell<T, A: std::alloc::Allocator = std::alloc::Global> {
+    inner: std::ptr::NonNull<T>,
+    // Marks the `T` on the heap as owned by the `HeapCell` for the drop check
+    _marker: std::marker::PhantomData<T>,
+    alloc: A,
 }
 
-impl<T> Clone for HeapCell<T> {
+impl<T, A: std::alloc::Allocator + Clone> Clone for HeapCell<T, A> {
     fn clone(&self) -> Self {
         Self {
-            inner: self.inner.clone(),
+            inner: self.inner,
+            _marker: std::marker::PhantomData,
+            alloc: self.alloc.clone(),
         }
     }
 }
@@ -58,9 +78,130 @@ impl<T> HeapCell<T> {
     /// This function will panic if memory allocation fails.
     pub fn new(val: T) -> Self {
         Self {
-            inner: Box::leak(Box::new(val)) as *mut T,
+            inner: std::ptr::NonNull::from(Box::leak(Box::new(val))),
+            _marker: std::marker::PhantomData,
+            alloc: std::alloc::Global,
+        }
+    }
+
+    /// Creates a new `HeapCell` containing the given value, or returns an error instead of
+    /// aborting if the allocation fails. The value is dropped in that case.
+    pub fn try_new(val: T) -> Result<Self, std::alloc::AllocError> {
+        Self::try_new_in(val, std::alloc::Global)
+    }
+
+    /// Creates a new `HeapCell` whose value is left uninitialised, to be written in place
+    /// through `as_mut` before `assume_init` is called.
+    ///
+    /// # Panics
+    ///
+    /// This function calls `std::alloc::handle_alloc_error` if the allocation fails.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::HeapCell;
+    ///
+    /// let cell = HeapCell::<[u16; 2048]>::new_uninit();
+    /// unsafe {
+    ///     cell.as_mut().write([7; 2048]);
+    ///     let cell = cell.assume_init();
+    ///     assert_eq!(cell.as_ref()[2047], 7);
+    ///     cell.drop_n_dealloc();
+    /// }
+    /// ```
+    pub fn new_uninit() -> HeapCell<std::mem::MaybeUninit<T>> {
+        Self::try_new_uninit()
+            .unwrap_or_else(|_| std::alloc::handle_alloc_error(std::alloc::Layout::new::<T>()))
+    }
+
+    /// Creates a new `HeapCell` whose value is filled with `0` bytes, which may or may not be
+    /// a valid `T`.
+    ///
+    /// # Panics
+    ///
+    /// This function calls `std::alloc::handle_alloc_error` if the allocation fails.
+    pub fn new_zeroed() -> HeapCell<std::mem::MaybeUninit<T>> {
+        Self::try_new_zeroed()
+            .unwrap_or_else(|_| std::alloc::handle_alloc_error(std::alloc::Layout::new::<T>()))
+    }
+
+    /// Creates a new `HeapCell` whose value is left uninitialised, or returns an error if the
+    /// allocation fails.
+    pub fn try_new_uninit() -> Result<HeapCell<std::mem::MaybeUninit<T>>, std::alloc::AllocError> {
+        Self::try_alloc(false)
+    }
+
+    /// Creates a new `HeapCell` whose value is filled with `0` bytes, or returns an error if the
+    /// allocation fails.
+    pub fn try_new_zeroed() -> Result<HeapCell<std::mem::MaybeUninit<T>>, std::alloc::AllocError> {
+        Self::try_alloc(true)
+    }
+
+    /// Allocates memory for a `T` without building one on the stack first.
+    fn try_alloc(
+        zeroed: bool,
+    ) -> Result<HeapCell<std::mem::MaybeUninit<T>>, std::alloc::AllocError> {
+        use std::alloc::Allocator;
+
+        let layout = std::alloc::Layout::new::<T>();
+        let inner = if zeroed {
+            std::alloc::Global.allocate_zeroed(layout)?
+        } else {
+            std::alloc::Global.allocate(layout)?
+        };
+        Ok(HeapCell {
+            inner: inner.cast(),
+            _marker: std::marker::PhantomData,
+            alloc: std::alloc::Global,
+        })
+    }
+}
+
+impl<T, A: std::alloc::Allocator> HeapCell<std::mem::MaybeUninit<T>, A> {
+    /// Turns a `HeapCell` from `HeapCell::new_uninit` or `HeapCell::new_zeroed` into a `HeapCell`
+    /// of the value it has been initialised with.
+    ///
+    /// # Safety
+    /// The value must have been initialised to a valid `T`.
+    pub unsafe fn assume_init(self) -> HeapCell<T, A> {
+        HeapCell {
+            inner: self.inner.cast(),
+            _marker: std::marker::PhantomData,
+            alloc: self.alloc,
         }
     }
+}
+
+impl<T, A: std::alloc::Allocator> HeapCell<T, A> {
+    /// Creates a new `HeapCell` containing the given value, in memory from `alloc`.
+    ///
+    /// # Panics
+    ///
+    /// This function calls `std::alloc::handle_alloc_error` if `alloc` fails to allocate.
+    pub fn new_in(val: T, alloc: A) -> Self {
+        Self::try_new_in(val, alloc)
+            .unwrap_or_else(|_| std::alloc::handle_alloc_error(std::alloc::Layout::new::<T>()))
+    }
+
+    /// Creates a new `HeapCell` containing the given value, in memory from `alloc`.
+    ///
+    /// # Returns
+    /// * `Err(AllocError)` - If `alloc` fails to allocate, in which case the value is dropped
+    pub fn try_new_in(val: T, alloc: A) -> Result<Self, std::alloc::AllocError> {
+        let inner = alloc.allocate(std::alloc::Layout::new::<T>())?.cast::<T>();
+        unsafe { inner.as_ptr().write(val) };
+        Ok(Self {
+            inner,
+            _marker: std::marker::PhantomData,
+            alloc,
+        })
+    }
+
+    /// Returns a reference to the allocator the value was allocated from.
+    pub fn allocator(&self) -> &A {
+        &self.alloc
+    }
 
     /// Creates a mutable reference to T from an immutable reference to self.
     ///
@@ -72,9 +213,9 @@ impl<T> HeapCell<T> {
     /// It is the responsibility of the caller to
     /// ensure that this method is not invoked while there is an outstanding
     /// **mutable** or **immutable** reference this same T, as that would lead to data races.
-    ///
+    #[allow(clippy::mut_from_ref)]
     pub unsafe fn as_mut(&self) -> &mut T {
-        &mut *self.inner
+        &mut *self.inner.as_ptr()
     }
 
     /// Creates an immutable reference to self from an immutable reference to self.
@@ -88,7 +229,7 @@ impl<T> HeapCell<T> {
     /// ensure that this method is not invoked while there is an outstanding
     /// **mutable** reference this same T, as that would lead to data races.
     pub unsafe fn as_ref(&self) -> &T {
-        &*self.inner
+        self.inner.as_ref()
     }
 
     /// Takes ownership of the value stored in the `HeapCell`.
@@ -111,7 +252,7 @@ impl<T> HeapCell<T> {
     pub unsafe fn take(&self) -> T {
         // It is safe to call `from_raw` here, as the `self.inner` was originally created
         // using `Box::into_raw`.
-        std::ptr::read(self.inner)
+        std::ptr::read(self.inner.as_ptr())
     }
 
     /// Drops the content and deallocates its memory.
@@ -123,20 +264,20 @@ impl<T> HeapCell<T> {
     /// The caller must ensure that the `HeapCell` is not used after calling `deallocate` as
     /// `self.inner` will then point to an invalid memory.
     pub unsafe fn drop_n_dealloc(&self) {
-        let ptr = self.inner;
-        std::ptr::drop_in_place(ptr);
-        std::alloc::dealloc(ptr as *mut u8, std::alloc::Layout::new::<T>());
+        self.drop();
+        self.dealloc();
     }
-    /// Creates a new cell wrapping a clone of the inner value.
+    /// Creates a new cell wrapping a clone of the inner value, in memory from the same allocator.
     ///
     /// # Returns
     /// The new cloned value
-    pub fn clone_inner(&self) -> HeapCell<T>
+    pub fn clone_inner(&self) -> HeapCell<T, A>
     where
         T: Clone,
+        A: Clone,
     {
-        let clone = unsafe { self.inner.as_ref().unwrap() }.clone();
-        HeapCell::new(clone)
+        let clone = unsafe { self.inner.as_ref() }.clone();
+        HeapCell::new_in(clone, self.alloc.clone())
     }
 
     /// Replaces the wraped value with `val` and returns the original one
@@ -148,16 +289,24 @@ impl<T> HeapCell<T> {
         val
     }
 
-    // Calls drop on T, if it implements Drop
+    /// Calls drop on T, if it implements Drop
+    ///
+    /// # Safety
+    /// The caller must ensure that T is not accessed after it has been dropped.
     #[inline]
     pub unsafe fn drop(&self) {
-        std::ptr::drop_in_place(self.inner);
+        std::ptr::drop_in_place(self.inner.as_ptr());
     }
 
-    // Deallocates the momory associated with T
+    /// Deallocates the momory associated with T, returning it to the allocator it came from
+    ///
+    /// # Safety
+    /// The caller must ensure that the `HeapCell` is not used after calling `dealloc` as
+    /// `self.inner` will then point to an invalid memory.
     #[inline]
     pub unsafe fn dealloc(&self) {
-        std::alloc::dealloc(self.inner as *mut u8, std::alloc::Layout::new::<T>());
+        self.alloc
+            .deallocate(self.inner.cast(), std::alloc::Layout::new::<T>());
     }
 }
 
@@ -178,6 +327,12 @@ pub struct BorrowFlag {
     inner: std::cell::UnsafeCell<isize>,
 }
 
+impl Default for BorrowFlag {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl BorrowFlag {
     /// Initiallizes a new BorrowFlag with 0 current reads and no current writer
     pub fn new() -> Self {
@@ -194,6 +349,7 @@ impl BorrowFlag {
     /// # Returns
     /// * `true` - If immutable borrow is possible
     /// * `false` - If immutable borrow is impossible
+    #[inline]
     pub fn can_borrow(&self) -> bool {
         unsafe { *self.inner.get() >= 0 }
     }
@@ -206,6 +362,7 @@ impl BorrowFlag {
     /// # Returns
     /// * `true` - If mutable borrow is possible
     /// * `false` - If mutable borrow is impossible
+    #[inline]
     pub fn can_borrow_mut(&self) -> bool {
         unsafe { *self.inner.get() == 0 }
     }
@@ -218,111 +375,435 @@ impl BorrowFlag {
     /// # Returns
     /// * `true` - If taking ownership is possible
     /// * `false` - If taking ownership is impossible
-
+    #[inline]
     pub fn can_own(&self) -> bool {
         self.can_borrow_mut()
     }
     /// Marks a the start of a new read by increasing the count of the internal `readers`
     ///
+    #[inline]
     pub fn borrow(&self) {
         unsafe { std::ptr::write(self.inner.get(), *self.inner.get() + 1) }
     }
     /// Marks the end of an ongoing read by decrementing the count of the internal `readers`
     ///
     ///
+    #[inline]
     pub fn drop_borrow(&self) {
         unsafe { std::ptr::write(self.inner.get(), *self.inner.get() - 1) }
     }
 
     /// Marks the start of a write by setting the internal `write` field to true
     ///
+    #[inline]
     pub fn borrow_mut(&self) {
         unsafe { std::ptr::write(self.inner.get(), -1) }
     }
-    /// Marks the end of a write session by setting the internal `write` field to false
+    /// Marks the end of a write session by releasing one writer.
     ///
+    /// There is only ever one writer unless a `RefMut` has been split with `RefMut::map_split`,
+    /// in which case the value becomes writable again once every part has been dropped.
+    #[inline]
     pub fn drop_borrow_mut(&self) {
-        unsafe { std::ptr::write(self.inner.get(), 0) }
+        unsafe { std::ptr::write(self.inner.get(), *self.inner.get() + 1) }
+    }
+
+    /// Registers one more writer for a write session that is already ongoing.
+    ///
+    /// Used by `RefMut::map_split` for writers that borrow disjoint parts of the value.
+    #[inline]
+    pub(crate) fn split_borrow_mut(&self) {
+        unsafe { std::ptr::write(self.inner.get(), *self.inner.get() - 1) }
     }
 }
 
 /// An immutable borrow of RefCell
-pub struct Ref<'a, T> {
-    val: &'a mut Inner<T>,
+///
+/// Any number of `Ref`s to the same `RefCell` may be alive at once, as long as there is no `RefMut`.
+pub struct Ref<'a, T: ?Sized> {
+    val: std::ptr::NonNull<T>,
+    flag: &'a BorrowFlag,
+    #[cfg(debug_assertions)]
+    tracked: Tracked<'a>,
+    // A `NonNull` instead of a reference, so that the value is not required to stay
+    // valid for a whole function the `Ref` is passed to, after it is dropped in there.
+    _marker: std::marker::PhantomData<&'a T>,
 }
 
 /// Dropping a Ref object
-impl<'a, T> Drop for Ref<'a, T> {
+impl<'a, T: ?Sized> Drop for Ref<'a, T> {
     #[inline]
     fn drop(&mut self) {
-        self.val.flag -= 1;
+        self.flag.drop_borrow();
     }
 }
 
-impl<'a, T> std::ops::Deref for Ref<'a, T> {
+impl<'a, T: ?Sized> std::ops::Deref for Ref<'a, T> {
     type Target = T;
     #[inline]
     fn deref(&self) -> &Self::Target {
-        &self.val.val
+        // The flag guarantees that there is no `RefMut` while this `Ref` is alive
+        unsafe { self.val.as_ref() }
     }
 }
 
-impl<'a, T> AsRef<T> for Ref<'a, T> {
+impl<'a, T: ?Sized> AsRef<T> for Ref<'a, T> {
     fn as_ref(&self) -> &T {
         std::ops::Deref::deref(self)
     }
 }
 
-pub struct RefMut<'a, T> {
-    val: &'a mut Inner<T>,
+impl<'a, T: ?Sized + std::fmt::Debug> std::fmt::Debug for Ref<'a, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + std::fmt::Display> std::fmt::Display for Ref<'a, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized> Ref<'a, T> {
+    /// Creates another `Ref` to the same borrowed data.
+    ///
+    /// This is an associated function that needs to be used as `Ref::clone(...)`,
+    /// so that a `Clone` implementation or a method on `T` is not shadowed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::{Ref, RefCell};
+    ///
+    /// let cell = RefCell::new(5);
+    /// let first = cell.borrow();
+    /// let second = Ref::clone(&first);
+    ///
+    /// drop(first);
+    /// assert!(cell.try_borrow_mut().is_err());
+    /// drop(second);
+    /// assert!(cell.try_borrow_mut().is_ok());
+    /// ```
+    #[allow(clippy::should_implement_trait)]
+    pub fn clone(orig: &Ref<'a, T>) -> Ref<'a, T> {
+        orig.flag.borrow();
+        Ref {
+            val: orig.val,
+            flag: orig.flag,
+            #[cfg(debug_assertions)]
+            tracked: orig.tracked.split(),
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    /// Makes a new `Ref` for a component of the borrowed data.
+    ///
+    /// The `RefCell` stays immutably borrowed until the returned `Ref` is dropped.
+    ///
+    /// This is an associated function that needs to be used as `Ref::map(...)`,
+    /// so that it does not interfere with methods of the same name on `T`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::{Ref, RefCell};
+    ///
+    /// let cell = RefCell::new(vec![1u8, 2, 3, 4]);
+    ///
+    /// // Hand out the bytes without exposing the `Vec`
+    /// let bytes: Ref<[u8]> = Ref::map(cell.borrow(), |v| &v[1..]);
+    /// assert_eq!(*bytes, [2, 3, 4]);
+    /// ```
+    pub fn map<U: ?Sized, F: FnOnce(&T) -> &U>(orig: Ref<'a, T>, f: F) -> Ref<'a, U> {
+        let val = std::ptr::NonNull::from(f(&*orig));
+        // The borrow is handed over to the new `Ref`
+        let orig = std::mem::ManuallyDrop::new(orig);
+        Ref {
+            val,
+            flag: orig.flag,
+            #[cfg(debug_assertions)]
+            tracked: unsafe { std::ptr::read(&orig.tracked) },
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    /// Makes a new `Ref` for an optional component of the borrowed data.
+    ///
+    /// If `f` returns `None`, the original `Ref` is handed back in the `Err` variant.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::{Ref, RefCell};
+    ///
+    /// let cell = RefCell::new(vec![1, 2, 3]);
+    ///
+    /// let second = Ref::filter_map(cell.borrow(), |v| v.get(1));
+    /// assert_eq!(*second.ok().unwrap(), 2);
+    ///
+    /// let missing = Ref::filter_map(cell.borrow(), |v| v.get(10));
+    /// assert_eq!(*missing.err().unwrap(), vec![1, 2, 3]);
+    /// ```
+    pub fn filter_map<U: ?Sized, F: FnOnce(&T) -> Option<&U>>(
+        orig: Ref<'a, T>,
+        f: F,
+    ) -> Result<Ref<'a, U>, Self> {
+        match f(&*orig).map(std::ptr::NonNull::from) {
+            Some(val) => {
+                let orig = std::mem::ManuallyDrop::new(orig);
+                Ok(Ref {
+                    val,
+                    flag: orig.flag,
+                    #[cfg(debug_assertions)]
+                    tracked: unsafe { std::ptr::read(&orig.tracked) },
+                    _marker: std::marker::PhantomData,
+                })
+            }
+            None => Err(orig),
+        }
+    }
+
+    /// Splits a `Ref` into two `Ref`s for different components of the borrowed data.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::{Ref, RefCell};
+    ///
+    /// let cell = RefCell::new([1, 2, 3, 4]);
+    /// let (head, tail) = Ref::map_split(cell.borrow(), |a| a.split_at(1));
+    ///
+    /// assert_eq!(*head, [1]);
+    /// assert_eq!(*tail, [2, 3, 4]);
+    /// ```
+    pub fn map_split<U: ?Sized, V: ?Sized, F: FnOnce(&T) -> (&U, &V)>(
+        orig: Ref<'a, T>,
+        f: F,
+    ) -> (Ref<'a, U>, Ref<'a, V>) {
+        let (a, b) = f(&*orig);
+        let (a, b) = (std::ptr::NonNull::from(a), std::ptr::NonNull::from(b));
+        // `orig`'s borrow goes to the first part, the second part registers its own
+        let orig = std::mem::ManuallyDrop::new(orig);
+        orig.flag.borrow();
+        (
+            Ref {
+                val: a,
+                flag: orig.flag,
+                #[cfg(debug_assertions)]
+                tracked: orig.tracked.split(),
+                _marker: std::marker::PhantomData,
+            },
+            Ref {
+                val: b,
+                flag: orig.flag,
+                #[cfg(debug_assertions)]
+                tracked: unsafe { std::ptr::read(&orig.tracked) },
+                _marker: std::marker::PhantomData,
+            },
+        )
+    }
+}
+
+/// A mutable borrow of RefCell
+///
+/// While a `RefMut` is alive, no other `Ref` or `RefMut` to the same `RefCell` can be created.
+pub struct RefMut<'a, T: ?Sized> {
+    val: std::ptr::NonNull<T>,
+    flag: &'a BorrowFlag,
+    #[cfg(debug_assertions)]
+    tracked: Tracked<'a>,
+    _marker: std::marker::PhantomData<&'a mut T>,
 }
 
 impl<'a, T> RefMut<'a, T> {
     pub fn replace(&mut self, val: T) -> T {
-        std::mem::replace(&mut self.val.val, val)
+        std::mem::replace(&mut **self, val)
+    }
+}
+
+impl<'a, T: ?Sized> RefMut<'a, T> {
+    /// Makes a new `RefMut` for a component of the borrowed data.
+    ///
+    /// The `RefCell` stays mutably borrowed until the returned `RefMut` is dropped.
+    ///
+    /// This is an associated function that needs to be used as `RefMut::map(...)`,
+    /// so that it does not interfere with methods of the same name on `T`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::{RefCell, RefMut};
+    ///
+    /// let cell = RefCell::new((5, 'b'));
+    /// {
+    ///     let mut first: RefMut<u32> = RefMut::map(cell.borrow_mut(), |t| &mut t.0);
+    ///     *first += 1;
+    /// }
+    /// assert_eq!(*cell.borrow(), (6, 'b'));
+    /// ```
+    pub fn map<U: ?Sized, F: FnOnce(&mut T) -> &mut U>(
+        mut orig: RefMut<'a, T>,
+        f: F,
+    ) -> RefMut<'a, U> {
+        let val = std::ptr::NonNull::from(f(&mut *orig));
+        // The borrow is handed over to the new `RefMut`
+        let orig = std::mem::ManuallyDrop::new(orig);
+        RefMut {
+            val,
+            flag: orig.flag,
+            #[cfg(debug_assertions)]
+            tracked: unsafe { std::ptr::read(&orig.tracked) },
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    /// Makes a new `RefMut` for an optional component of the borrowed data.
+    ///
+    /// If `f` returns `None`, the original `RefMut` is handed back in the `Err` variant.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::{RefCell, RefMut};
+    ///
+    /// let cell = RefCell::new(vec![1, 2, 3]);
+    ///
+    /// let last = RefMut::filter_map(cell.borrow_mut(), |v| v.last_mut());
+    /// *last.ok().unwrap() = 4;
+    ///
+    /// assert!(RefMut::filter_map(cell.borrow_mut(), |v| v.get_mut(10)).is_err());
+    /// assert_eq!(*cell.borrow(), vec![1, 2, 4]);
+    /// ```
+    pub fn filter_map<U: ?Sized, F: FnOnce(&mut T) -> Option<&mut U>>(
+        mut orig: RefMut<'a, T>,
+        f: F,
+    ) -> Result<RefMut<'a, U>, Self> {
+        match f(&mut *orig).map(std::ptr::NonNull::from) {
+            Some(val) => {
+                let orig = std::mem::ManuallyDrop::new(orig);
+                Ok(RefMut {
+                    val,
+                    flag: orig.flag,
+                    #[cfg(debug_assertions)]
+                    tracked: unsafe { std::ptr::read(&orig.tracked) },
+                    _marker: std::marker::PhantomData,
+                })
+            }
+            None => Err(orig),
+        }
+    }
+
+    /// Splits a `RefMut` into two `RefMut`s for disjoint components of the borrowed data.
+    ///
+    /// The `RefCell` stays mutably borrowed until both returned `RefMut`s are dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::{RefCell, RefMut};
+    ///
+    /// let cell = RefCell::new([1, 2, 3, 4]);
+    ///
+    /// let (mut left, mut right) = RefMut::map_split(cell.borrow_mut(), |a| a.split_at_mut(2));
+    /// left[0] = 10;
+    /// right[1] = 40;
+    ///
+    /// drop(left);
+    /// assert!(cell.try_borrow().is_err());
+    /// drop(right);
+    /// assert_eq!(*cell.borrow(), [10, 2, 3, 40]);
+    /// ```
+    pub fn map_split<U: ?Sized, V: ?Sized, F: FnOnce(&mut T) -> (&mut U, &mut V)>(
+        mut orig: RefMut<'a, T>,
+        f: F,
+    ) -> (RefMut<'a, U>, RefMut<'a, V>) {
+        let (a, b) = f(&mut *orig);
+        let (a, b) = (std::ptr::NonNull::from(a), std::ptr::NonNull::from(b));
+        // `orig`'s borrow goes to the first part, the second part registers its own
+        let orig = std::mem::ManuallyDrop::new(orig);
+        orig.flag.split_borrow_mut();
+        (
+            RefMut {
+                val: a,
+                flag: orig.flag,
+                #[cfg(debug_assertions)]
+                tracked: orig.tracked.split(),
+                _marker: std::marker::PhantomData,
+            },
+            RefMut {
+                val: b,
+                flag: orig.flag,
+                #[cfg(debug_assertions)]
+                tracked: unsafe { std::ptr::read(&orig.tracked) },
+                _marker: std::marker::PhantomData,
+            },
+        )
     }
 }
 
-impl<'a, T> std::ops::Deref for RefMut<'a, T> {
+impl<'a, T: ?Sized> std::ops::Deref for RefMut<'a, T> {
     type Target = T;
     #[inline]
     fn deref(&self) -> &Self::Target {
-        &self.val.val
+        // The flag guarantees that this `RefMut` is the only borrow
+        unsafe { self.val.as_ref() }
     }
 }
 
-impl<'a, T> AsRef<T> for RefMut<'a, T> {
+impl<'a, T: ?Sized> AsRef<T> for RefMut<'a, T> {
     fn as_ref(&self) -> &T {
         std::ops::Deref::deref(self)
     }
 }
 
-impl<'a, T> std::ops::DerefMut for RefMut<'a, T> {
+impl<'a, T: ?Sized> std::ops::DerefMut for RefMut<'a, T> {
     #[inline]
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.val.val
+        unsafe { self.val.as_mut() }
     }
 }
 
-impl<'a, T> AsMut<T> for RefMut<'a, T> {
+impl<'a, T: ?Sized> AsMut<T> for RefMut<'a, T> {
     fn as_mut(&mut self) -> &mut T {
         std::ops::DerefMut::deref_mut(self)
     }
 }
 
-impl<'a, T> Drop for RefMut<'a, T> {
+impl<'a, T: ?Sized + std::fmt::Debug> std::fmt::Debug for RefMut<'a, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + std::fmt::Display> std::fmt::Display for RefMut<'a, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized> Drop for RefMut<'a, T> {
     #[inline]
     fn drop(&mut self) {
-        self.val.flag = 0;
+        self.flag.drop_borrow_mut();
     }
 }
 
 /// # RefCell
 /// A RefCell is a mutable memory location with dynamically checked borrow rules.
 ///
+/// Like `std::cell::RefCell`, it allows any number of immutable borrows (`Ref`) or exactly one
+/// mutable borrow (`RefMut`) at a time.
 ///
 /// # vs std::cell::RefCell
-/// //TODO
+/// `borrow` and `borrow_mut` compile to the same instructions as the std ones in release builds,
+/// a check of the flag that the optimizer folds with the matching release when the guard is short
+/// lived, and cost the same. In debug builds the cell also records where the outstanding borrow
+/// was made, and reports it when a conflicting borrow fails.
+///
+/// `borrow_unchecked` and `borrow_mut_unchecked` are the fast mode. They hand out plain references
+/// without tracking them and only check the flag in debug builds, so in release builds they skip
+/// the load and check of the flag that every std borrow pays. See `benches/refcell.rs`.
 ///
 /// The `RefCell` stores a value of type `T`, and allows mutable access through the `borrow_mut` method,
 /// which returns a `RefMut<T>` type. Immutable access is granted through the `borrow` method, which
@@ -341,9 +822,10 @@ impl<'a, T> Drop for RefMut<'a, T> {
 /// ```
 /// use speedy_refs::RefCell;
 /// let x = RefCell::new(42);
-/// // borrowing immutably
+/// // borrowing immutably, any number of times
 /// let y = x.borrow();
-/// assert_eq!(*y, 42);
+/// let w = x.borrow();
+/// assert_eq!(*y + *w, 84);
 /// // attempting to borrow mutably while already borrowed immutably
 /// // will panic at runtime ///
 /// // let z = x.borrow_mut();
@@ -351,6 +833,7 @@ impl<'a, T> Drop for RefMut<'a, T> {
 ///
 /// // borrowing mutably
 /// std::mem::drop(y);
+/// std::mem::drop(w);
 /// let mut z = x.borrow_mut();
 /// *z += 1;
 /// assert_eq!(*z, 43);
@@ -363,9 +846,12 @@ impl<'a, T> Drop for RefMut<'a, T> {
 /// let val = x.take();
 /// assert_eq!(val, 43);
 /// ```
-
 pub struct RefCell<T> {
-    inner: std::cell::UnsafeCell<Inner<T>>,
+    flag: BorrowFlag,
+    /// Where the currently outstanding borrows were made, oldest first, for error reporting.
+    #[cfg(debug_assertions)]
+    borrows: Locations,
+    val: std::cell::UnsafeCell<T>,
 }
 
 impl<T> RefCell<T> {
@@ -380,12 +866,17 @@ impl<T> RefCell<T> {
     /// ```
     pub fn new(val: T) -> Self {
         Self {
-            inner: std::cell::UnsafeCell::new(Inner::new(val)),
+            flag: BorrowFlag::new(),
+            #[cfg(debug_assertions)]
+            borrows: Locations::default(),
+            val: std::cell::UnsafeCell::new(val),
         }
     }
 
     /// Borrows the value immutably. Panics if the value is currently borrowed mutably.
     ///
+    /// In debug builds the panic message tells where the conflicting borrow was made.
+    ///
     /// # Examples
     ///
     /// ```
@@ -397,12 +888,15 @@ impl<T> RefCell<T> {
     ///
     /// assert_eq!(*reference, 42);
     /// ```
+    #[cfg_attr(debug_assertions, track_caller)]
     pub fn borrow<'a>(&'a self) -> Ref<'a, T> {
-        self.try_borrow()
-            .expect("T cannot be borrowed immutably while T is borrowed mutably")
+        match self.try_borrow() {
+            Ok(r) => r,
+            Err(err) => panic_borrowed(&err),
+        }
     }
 
-    /// Tries to borrow the value immutably. Returns `None` if the value is currently borrowed mutably.
+    /// Tries to borrow the value immutably. Returns a [`BorrowError`] if the value is currently borrowed mutably.
     ///
     /// # Examples
     ///
@@ -414,24 +908,37 @@ impl<T> RefCell<T> {
     /// let reference1 = cell.try_borrow().unwrap();
     /// assert_eq!(*reference1, 42);
     ///
+    /// // Any number of immutable borrows can coexist
     /// let reference2 = cell.try_borrow();
-    /// assert!(reference2.is_none());
-    /// ```
-    pub fn try_borrow<'a>(&'a self) -> Option<Ref<'a, T>> {
-        unsafe {
-            if (*self.inner.get()).flag == 0 {
-                (&mut *self.inner.get()).flag += 1;
-                Some(Ref {
-                    val: &mut *self.inner.get(),
-                })
-            } else {
-                None
-            }
+    /// assert!(reference2.is_ok());
+    ///
+    /// // But not alongside a mutable one
+    /// assert!(cell.try_borrow_mut().is_err());
+    /// ```
+    #[inline]
+    #[cfg_attr(debug_assertions, track_caller)]
+    pub fn try_borrow<'a>(&'a self) -> Result<Ref<'a, T>, BorrowError> {
+        if self.flag.can_borrow() {
+            self.flag.borrow();
+            Ok(Ref {
+                val: self.as_non_null(),
+                flag: &self.flag,
+                #[cfg(debug_assertions)]
+                tracked: Tracked::new(&self.borrows, std::panic::Location::caller()),
+                _marker: std::marker::PhantomData,
+            })
+        } else {
+            Err(BorrowError {
+                #[cfg(debug_assertions)]
+                location: self.borrows.last(),
+            })
         }
     }
 
     /// Borrows the value mutably. Panics if the value is currently borrowed (either mutably or immutably).
     ///
+    /// In debug builds the panic message tells where the conflicting borrow was made.
+    ///
     /// # Examples
     ///
     /// ```
@@ -445,12 +952,16 @@ impl<T> RefCell<T> {
     ///
     /// assert_eq!(*reference, 13);
     /// ```
+    #[cfg_attr(debug_assertions, track_caller)]
     pub fn borrow_mut<'a>(&'a self) -> RefMut<'a, T> {
-        self.try_borrow_mut()
-            .expect("T cannot be borrowed mutably while T is borrowed mutably or immutably")
+        match self.try_borrow_mut() {
+            Ok(r) => r,
+            Err(err) => panic_borrowed(&err),
+        }
     }
 
-    /// Tries to borrow the value mutably. Returns `None` if the value is currently borrowed (either mutably or immutably).
+    /// Tries to borrow the value mutably. Returns a [`BorrowMutError`] if the value is currently borrowed
+    /// (either mutably or immutably).
     ///
     /// # Examples
     ///
@@ -463,52 +974,294 @@ impl<T> RefCell<T> {
     /// *mut_reference1 = 13;
     ///
     /// let mut_reference2 = cell.try_borrow_mut();
-    /// assert!(mut_reference2.is_none());
-    /// ```
-    pub fn try_borrow_mut<'a>(&'a self) -> Option<RefMut<'a, T>> {
-        unsafe {
-            if (*self.inner.get()).flag == 0 {
-                (&mut *self.inner.get()).flag = -1;
-                Some(RefMut {
-                    val: &mut *self.inner.get(),
-                })
-            } else {
-                None
-            }
+    /// assert!(mut_reference2.is_err());
+    /// ```
+    #[inline]
+    #[cfg_attr(debug_assertions, track_caller)]
+    pub fn try_borrow_mut<'a>(&'a self) -> Result<RefMut<'a, T>, BorrowMutError> {
+        if self.flag.can_borrow_mut() {
+            self.flag.borrow_mut();
+            Ok(RefMut {
+                val: self.as_non_null(),
+                flag: &self.flag,
+                #[cfg(debug_assertions)]
+                tracked: Tracked::new(&self.borrows, std::panic::Location::caller()),
+                _marker: std::marker::PhantomData,
+            })
+        } else {
+            Err(BorrowMutError {
+                #[cfg(debug_assertions)]
+                location: self.borrows.last(),
+            })
         }
     }
 
-    pub fn take(mut self) -> T {
-        if self.inner.get_mut().flag == 0 {
-            let Inner { val, flag: _ } = self.inner.into_inner();
-            val
+    /// Borrows the value immutably without tracking the borrow.
+    ///
+    /// Faster than `borrow` in release builds, where the flag is not read at all.
+    /// In debug builds this panics like `borrow` if the value is currently borrowed mutably.
+    ///
+    /// # Safety
+    /// The caller must ensure that the value is not borrowed mutably, through any of the
+    /// `borrow_mut` methods, while the returned reference is alive.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::RefCell;
+    ///
+    /// let cell = RefCell::new(vec![1, 2, 3]);
+    ///
+    /// // Nothing borrows `cell` mutably in this scope
+    /// let sum: i32 = unsafe { cell.borrow_unchecked() }.iter().sum();
+    /// assert_eq!(sum, 6);
+    /// ```
+    #[inline(always)]
+    pub unsafe fn borrow_unchecked(&self) -> &T {
+        debug_assert!(
+            self.flag.can_borrow(),
+            "T cannot be borrowed immutably while T is borrowed mutably"
+        );
+        &*self.val.get()
+    }
+
+    /// Borrows the value mutably without tracking the borrow.
+    ///
+    /// Faster than `borrow_mut` in release builds, where the flag is not read at all.
+    /// In debug builds this panics like `borrow_mut` if the value is currently borrowed.
+    ///
+    /// # Safety
+    /// The caller must ensure that the value is not borrowed in any other way, through any of
+    /// the borrow methods, while the returned reference is alive.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::RefCell;
+    ///
+    /// let cell = RefCell::new(0);
+    ///
+    /// // Nothing else borrows `cell` in this scope
+    /// *unsafe { cell.borrow_mut_unchecked() } += 1;
+    /// assert_eq!(*cell.borrow(), 1);
+    /// ```
+    #[inline(always)]
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn borrow_mut_unchecked(&self) -> &mut T {
+        debug_assert!(
+            self.flag.can_borrow_mut(),
+            "T cannot be borrowed mutably while T is borrowed mutably or immutably"
+        );
+        &mut *self.val.get()
+    }
+
+    /// Consumes the `RefCell` and returns the wrapped value.
+    ///
+    /// # Panics
+    /// If a borrow of the value was leaked (e.g with `std::mem::forget`) and is therefore still outstanding.
+    #[cfg_attr(debug_assertions, track_caller)]
+    pub fn take(self) -> T {
+        if self.flag.can_own() {
+            self.val.into_inner()
         } else {
-            panic!("T cannot be moved while T is borrowed mutably or immutably")
+            panic_borrowed(&BorrowMutError {
+                #[cfg(debug_assertions)]
+                location: self.borrows.last(),
+            })
         }
     }
     /// Replaces the wrapped value with a new one computed from f, returning the old value, without deinitializing either one.
     ///
     /// # Panics
     ///Panics if the value is currently borrowed.
+    #[cfg_attr(debug_assertions, track_caller)]
     pub fn replace(&self, val: T) -> T {
         self.borrow_mut().replace(val)
     }
+
+    #[inline(always)]
+    fn as_non_null(&self) -> std::ptr::NonNull<T> {
+        // `UnsafeCell::get` never returns null
+        unsafe { std::ptr::NonNull::new_unchecked(self.val.get()) }
+    }
 }
 
-impl<T: Clone> Clone for RefCell<T> {
-    fn clone(&self) -> Self {
-        unsafe { RefCell::new((*self.inner.get()).val.clone()) }
+/// Kept out of line so that the borrow fast paths stay small enough to inline.
+#[cold]
+#[inline(never)]
+#[track_caller]
+fn panic_borrowed(err: &dyn std::fmt::Display) -> ! {
+    panic!("{}", err)
+}
+
+/// Where the outstanding borrows of a `RefCell` were made, in the order they were made.
+#[cfg(debug_assertions)]
+#[derive(Default)]
+struct Locations(std::cell::Cell<Vec<&'static std::panic::Location<'static>>>);
+
+#[cfg(debug_assertions)]
+impl Locations {
+    /// Returns where the most recent of the outstanding borrows was made.
+    fn last(&self) -> Option<&'static std::panic::Location<'static>> {
+        let list = self.0.take();
+        let last = list.last().copied();
+        self.0.set(list);
+        last
+    }
+}
+
+/// The entry of a `Ref` or `RefMut` in the `Locations` of its `RefCell`, removed when it is dropped,
+/// so that a failed borrow only ever reports borrows that are still outstanding.
+#[cfg(debug_assertions)]
+struct Tracked<'a> {
+    borrows: &'a Locations,
+    location: &'static std::panic::Location<'static>,
+}
+
+#[cfg(debug_assertions)]
+impl<'a> Tracked<'a> {
+    fn new(borrows: &'a Locations, location: &'static std::panic::Location<'static>) -> Self {
+        let mut list = borrows.0.take();
+        list.push(location);
+        borrows.0.set(list);
+        Self { borrows, location }
+    }
+
+    /// Registers another borrow made at the same location, for a `Ref` or `RefMut` split off this one.
+    fn split(&self) -> Self {
+        Self::new(self.borrows, self.location)
+    }
+}
+
+#[cfg(debug_assertions)]
+impl Drop for Tracked<'_> {
+    fn drop(&mut self) {
+        let mut list = self.borrows.0.take();
+        if let Some(i) = list.iter().rposition(|&l| std::ptr::eq(l, self.location)) {
+            list.remove(i);
+        }
+        self.borrows.0.set(list);
+    }
+}
+
+/// An error returned by `RefCell::try_borrow` when the value is currently borrowed mutably.
+///
+/// In debug builds it records where the conflicting borrow was made.
+///
+/// # Examples
+///
+/// ```
+/// use speedy_refs::RefCell;
+///
+/// let cell = RefCell::new(1);
+/// let _writer = cell.borrow_mut();
+///
+/// let err = cell.try_borrow().unwrap_err();
+/// // Only available when speedy_refs is built with debug assertions
+/// if let Some(location) = err.location() {
+///     assert_eq!(location.line(), line!() - 5);
+/// }
+/// println!("{}", err);
+/// ```
+pub struct BorrowError {
+    #[cfg(debug_assertions)]
+    location: Option<&'static std::panic::Location<'static>>,
+}
+
+/// An error returned by `RefCell::try_borrow_mut` when the value is currently borrowed.
+///
+/// In debug builds it records where the most recent of the conflicting borrows was made.
+pub struct BorrowMutError {
+    #[cfg(debug_assertions)]
+    location: Option<&'static std::panic::Location<'static>>,
+}
+
+impl BorrowError {
+    /// Creates an error that carries no borrow location, for cells that do not track one.
+    pub(crate) fn untracked() -> Self {
+        Self {
+            #[cfg(debug_assertions)]
+            location: None,
+        }
+    }
+
+    /// Returns where the conflicting borrow was made.
+    ///
+    /// This is only tracked in debug builds and is always `None` in release builds.
+    pub fn location(&self) -> Option<&'static std::panic::Location<'static>> {
+        #[cfg(debug_assertions)]
+        return self.location;
+        #[cfg(not(debug_assertions))]
+        return None;
+    }
+}
+
+impl BorrowMutError {
+    /// Creates an error that carries no borrow location, for cells that do not track one.
+    pub(crate) fn untracked() -> Self {
+        Self {
+            #[cfg(debug_assertions)]
+            location: None,
+        }
+    }
+
+    /// Returns where the most recent of the conflicting borrows was made.
+    ///
+    /// This is only tracked in debug builds and is always `None` in release builds.
+    pub fn location(&self) -> Option<&'static std::panic::Location<'static>> {
+        #[cfg(debug_assertions)]
+        return self.location;
+        #[cfg(not(debug_assertions))]
+        return None;
     }
 }
 
-struct Inner<T> {
-    val: T,
-    flag: isize,
+impl std::fmt::Debug for BorrowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BorrowError")
+            .field("location", &self.location())
+            .finish()
+    }
 }
 
-impl<T> Inner<T> {
-    fn new(val: T) -> Self {
-        Self { val, flag: 0 }
+impl std::fmt::Debug for BorrowMutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BorrowMutError")
+            .field("location", &self.location())
+            .finish()
+    }
+}
+
+impl std::fmt::Display for BorrowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("T cannot be borrowed immutably while T is borrowed mutably")?;
+        match self.location() {
+            Some(location) => write!(f, " (mutably borrowed at {})", location),
+            None => Ok(()),
+        }
+    }
+}
+
+impl std::fmt::Display for BorrowMutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("T cannot be borrowed mutably while T is borrowed mutably or immutably")?;
+        match self.location() {
+            Some(location) => write!(f, " (borrowed at {})", location),
+            None => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for BorrowError {}
+
+impl std::error::Error for BorrowMutError {}
+
+impl<T: Clone> Clone for RefCell<T> {
+    /// # Panics
+    /// If the value is currently borrowed mutably.
+    #[cfg_attr(debug_assertions, track_caller)]
+    fn clone(&self) -> Self {
+        RefCell::new(self.borrow().clone())
     }
 }
 
@@ -536,7 +1289,6 @@ unsafe impl<T: Send> Send for RefCell<T> {}
 /// // The shared reference reflects the updated value.
 /// assert_eq!(*shared_ref, 43);
 /// ```
-
 pub struct RcCell<T> {
     inner: std::rc::Rc<std::cell::RefCell<T>>,
 }
@@ -548,6 +1300,14 @@ impl<T> RcCell<T> {
             inner: std::rc::Rc::new(std::cell::RefCell::new(value)),
         }
     }
+
+    /// Creates a new `RcCell<T>` instance containing the provided value, or returns an error
+    /// instead of aborting if the allocation fails. The value is dropped in that case.
+    pub fn try_new(value: T) -> Result<RcCell<T>, std::alloc::AllocError> {
+        Ok(Self {
+            inner: std::rc::Rc::try_new(std::cell::RefCell::new(value))?,
+        })
+    }
 }
 
 impl<T> Clone for RcCell<T> {
@@ -605,7 +1365,11 @@ impl<T> SharedCell<T> {
     /// This method uses the `UnsafeCell` internally to allow for mutable access without violating Rust's borrowing rules.
     /// However, it is marked as unsafe because it allows for multiple mutable references to the same value, which can lead
     /// to data races if not used correctly.
-    ///  
+    ///
+    /// # Safety
+    /// The caller must ensure that no other reference to the contained value is in use while
+    /// the returned mutable reference is alive.
+    ///
     /// # Examples
     /// ```
     /// use speedy_refs::SharedCell;
@@ -625,6 +1389,7 @@ impl<T> SharedCell<T> {
     /// assert_eq!(unsafe { &person.get_ref().name }, &String::from("Dennis"));
     ///
     /// ```
+    #[allow(clippy::mut_from_ref)]
     pub unsafe fn get_mut(&self) -> &mut T {
         &mut *self.value.get()
     }
@@ -634,6 +1399,10 @@ impl<T> SharedCell<T> {
     /// This method uses the `UnsafeCell` internally to allow for shared access without violating Rust's borrowing rules.
     /// However, it is marked as unsafe for the same reason as `get_mut`.
     ///
+    /// # Safety
+    /// The caller must ensure that the contained value is not mutated while the returned
+    /// reference is alive.
+    ///
     /// # Examples
     /// ```
     /// use speedy_refs::SharedCell;
@@ -765,4 +1534,158 @@ mod tests {
             // do something
         }
     }
+
+    #[test]
+    fn test_refcell_readers() {
+        let cell = crate::RefCell::new(vec![1, 2, 3]);
+        let first = cell.borrow();
+        let second = cell.borrow();
+        assert_eq!(first.len() + second.len(), 6);
+        assert!(cell.try_borrow_mut().is_err());
+
+        drop(first);
+        assert!(cell.try_borrow_mut().is_err());
+        drop(second);
+
+        let mut writer = cell.borrow_mut();
+        writer.push(4);
+        assert!(cell.try_borrow().is_err());
+        drop(writer);
+        assert_eq!(*cell.borrow(), vec![1, 2, 3, 4]);
+        assert_eq!(cell.take(), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_refcell_reader_projections() {
+        let cell = crate::RefCell::new((String::from("key"), 7));
+        let whole = cell.borrow();
+        let copy = crate::Ref::clone(&whole);
+        let key = crate::Ref::map(crate::Ref::clone(&whole), |pair| pair.0.as_str());
+        drop((whole, copy));
+
+        // The projection is still a reader on its own
+        assert_eq!(&*key, "key");
+        assert!(cell.try_borrow_mut().is_err());
+        assert_eq!(cell.borrow().1, 7);
+        drop(key);
+
+        // Every reader is gone, and none was counted twice
+        assert!(cell.flag.can_borrow_mut());
+        cell.borrow_mut().1 += 1;
+        assert_eq!(cell.borrow().1, 8);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_refcell_borrow_while_borrowed_mut() {
+        let cell = crate::RefCell::new(1);
+        let _writer = cell.borrow_mut();
+        let _reader = cell.borrow();
+    }
+
+    #[test]
+    fn test_ref_map_split() {
+        let cell = crate::RefCell::new([1, 2, 3, 4]);
+        let (head, tail) = crate::Ref::map_split(cell.borrow(), |a| a.split_at(1));
+        assert_eq!((&*head, &*tail), (&[1][..], &[2, 3, 4][..]));
+
+        // Each part is a reader of its own
+        drop(head);
+        assert!(cell.try_borrow_mut().is_err());
+        assert_eq!(tail.len(), 3);
+        drop(tail);
+        assert!(cell.flag.can_borrow_mut());
+    }
+
+    #[test]
+    fn test_ref_mut_map_split() {
+        let cell = crate::RefCell::new((String::from("a"), vec![1]));
+        let (mut name, mut list) =
+            crate::RefMut::map_split(cell.borrow_mut(), |pair| (&mut pair.0, &mut pair.1));
+        name.push('b');
+        list.push(2);
+
+        // The cell stays mutably borrowed until both parts are gone
+        drop(list);
+        assert!(cell.try_borrow().is_err());
+        assert!(cell.try_borrow_mut().is_err());
+        drop(name);
+        assert!(cell.flag.can_borrow_mut());
+        assert_eq!(*cell.borrow(), (String::from("ab"), vec![1, 2]));
+
+        // Splits can be split again
+        let (left, right) =
+            crate::RefMut::map_split(cell.borrow_mut(), |pair| (&mut pair.0, &mut pair.1));
+        let (first, rest) = crate::RefMut::map_split(right, |list| list.split_at_mut(1));
+        drop((left, first));
+        assert!(cell.try_borrow().is_err());
+        drop(rest);
+        assert!(cell.flag.can_borrow_mut());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_refcell_clone_while_borrowed_mut() {
+        let cell = crate::RefCell::new(1);
+        let _writer = cell.borrow_mut();
+        let _ = cell.clone();
+    }
+
+    #[test]
+    fn test_borrow_error_location() {
+        let cell = crate::RefCell::new(String::new());
+        let reader = cell.borrow();
+        let line = line!() - 1;
+
+        let err = cell.try_borrow_mut().unwrap_err();
+        let err: &dyn std::error::Error = &err;
+        let message = err.to_string();
+        assert!(message.starts_with("T cannot be borrowed mutably"));
+
+        if cfg!(debug_assertions) {
+            assert!(message.contains(&format!("{}:{}", file!(), line)));
+        }
+
+        drop(reader);
+        let _writer = cell.borrow_mut();
+        let err = cell.try_borrow().unwrap_err();
+        assert_eq!(err.location().is_some(), cfg!(debug_assertions));
+    }
+
+    #[test]
+    fn test_borrow_error_location_of_outstanding_borrow() {
+        let cell = crate::RefCell::new(0);
+        let first = cell.borrow();
+        let second = cell.borrow();
+        let second_line = line!() - 1;
+        let third = cell.borrow();
+        let third_line = line!() - 1;
+        let line = |err: crate::BorrowMutError| err.location().map(|l| l.line());
+        let expected = |line: u32| cfg!(debug_assertions).then_some(line);
+
+        // The first reader is gone, so the error names one that is still outstanding
+        drop(first);
+        assert_eq!(
+            line(cell.try_borrow_mut().unwrap_err()),
+            expected(third_line)
+        );
+        drop(third);
+        assert_eq!(
+            line(cell.try_borrow_mut().unwrap_err()),
+            expected(second_line)
+        );
+
+        let mapped = crate::Ref::map(second, |val| val);
+        assert_eq!(
+            line(cell.try_borrow_mut().unwrap_err()),
+            expected(second_line)
+        );
+        drop(mapped);
+        let writer = cell.borrow_mut();
+        let writer_line = line!() - 1;
+        let err = cell.try_borrow().unwrap_err();
+        assert_eq!(err.location().map(|l| l.line()), expected(writer_line));
+        drop(writer);
+        assert!(cell.try_borrow_mut().is_ok());
+    }
 }
@@ -0,0 +1,67 @@
+//! Compile-fail tests for the pointer types, run as doc tests.
+//!
+//! An `Rc` or an `Arc` owns its value, so the drop check must reject values whose destructor
+//! could read borrowed data that is already gone.
+//!
+//! ```compile_fail,E0597
+//! use speedy_refs::Rc;
+//!
+//! struct PrintOnDrop<'a>(&'a String);
+//! impl Drop for PrintOnDrop<'_> {
+//!     fn drop(&mut self) {
+//!         println!("{}", self.0);
+//!     }
+//! }
+//!
+//! let rc;
+//! let value = String::from("gone");
+//! rc = Rc::new(PrintOnDrop(&value));
+//! ```
+//!
+//! ```compile_fail,E0597
+//! use speedy_refs::Arc;
+//!
+//! struct PrintOnDrop<'a>(&'a String);
+//! impl Drop for PrintOnDrop<'_> {
+//!     fn drop(&mut self) {
+//!         println!("{}", self.0);
+//!     }
+//! }
+//!
+//! let arc;
+//! let value = String::from("gone");
+//! arc = Arc::new(PrintOnDrop(&value));
+//! ```
+//!
+//! Covariance only shortens lifetimes, it never extends them.
+//!
+//! ```compile_fail
+//! fn extend<'a>(rc: speedy_refs::Rc<&'a str>) -> speedy_refs::Rc<&'static str> {
+//!     rc
+//! }
+//! ```
+//!
+//! ```compile_fail
+//! fn extend<'a>(arc: speedy_refs::Arc<&'a str>) -> speedy_refs::Arc<&'static str> {
+//!     arc
+//! }
+//! ```
+//!
+//! The single-threaded pointers can not be sent to other threads.
+//!
+//! ```compile_fail,E0277
+//! fn assert_send<T: Send>() {}
+//! assert_send::<speedy_refs::Rc<u8>>();
+//! ```
+//!
+//! ```compile_fail,E0277
+//! fn assert_send<T: Send>() {}
+//! assert_send::<speedy_refs::HeapCell<u8>>();
+//! ```
+//!
+//! An `Arc` is only shareable between threads if its value is.
+//!
+//! ```compile_fail,E0277
+//! fn assert_send<T: Send>() {}
+//! assert_send::<speedy_refs::Arc<std::cell::Cell<u8>>>();
+//! ```
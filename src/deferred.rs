@@ -0,0 +1,469 @@
+use std::collections::VecDeque;
+use std::mem::ManuallyDrop;
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex, OnceLock};
+
+use crate::{arc, Arc};
+
+/// The number of drops the global queue holds before dropping threads wait for it.
+const GLOBAL_CAPACITY: usize = 1024;
+
+/// The value behind a `DeferredArc`, with the queue that drops it.
+struct Deferred<T> {
+    // Not a `DropQueue`, which would keep a manual queue open and its values leaked
+    state: std::sync::Arc<State>,
+    val: T,
+}
+
+/// A released allocation waiting to be dropped, with the function dropping it for its type.
+struct Job {
+    ptr: NonNull<()>,
+    drop: unsafe fn(NonNull<()>),
+}
+
+impl Job {
+    fn run(self) {
+        unsafe { (self.drop)(self.ptr) }
+    }
+}
+
+// Only built for values that are `Send`, see `DeferredArc`
+unsafe impl Send for Job {}
+
+unsafe fn drop_released<T: Send + 'static>(ptr: NonNull<()>) {
+    Arc::<Deferred<T>>::drop_released(ptr.cast::<arc::Inner<Deferred<T>>>());
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// Drops wait for `DropQueue::flush`, and run inline once the queue is full.
+    Manual,
+    /// A dedicated thread drains the queue, and dropping threads wait while it is full.
+    Thread,
+    /// Drops run inline, as they would for an `Arc`.
+    Immediate,
+}
+
+struct Jobs {
+    queue: VecDeque<Job>,
+    /// The number of jobs taken out of `queue` and still running.
+    running: usize,
+    /// Set once every `DropQueue` handle is gone. The queue is drained one last time, and the
+    /// values released afterwards are dropped inline.
+    closed: bool,
+}
+
+struct State {
+    jobs: Mutex<Jobs>,
+    /// Signalled when a job is queued or the queue closes.
+    queued: Condvar,
+    /// Signalled when jobs have been taken out of the queue or have finished running.
+    drained: Condvar,
+    capacity: usize,
+    mode: Mode,
+    /// The number of `DropQueue` handles. Those held by `DeferredArc`s are not counted.
+    handles: AtomicUsize,
+    drainer: OnceLock<std::thread::ThreadId>,
+}
+
+impl State {
+    fn push(&self, job: Job) {
+        if self.mode == Mode::Immediate {
+            return job.run();
+        }
+
+        let mut jobs = self.jobs.lock().unwrap();
+        if jobs.queue.len() >= self.capacity && !jobs.closed {
+            // The drain thread must never wait on itself, and nobody drains a manual queue
+            // but `flush`, so both drop inline instead.
+            if self.mode == Mode::Manual || self.is_drainer() {
+                drop(jobs);
+                return job.run();
+            }
+            jobs = self
+                .drained
+                .wait_while(jobs, |jobs| {
+                    jobs.queue.len() >= self.capacity && !jobs.closed
+                })
+                .unwrap();
+        }
+        if jobs.closed {
+            // Nothing will drain the queue anymore
+            drop(jobs);
+            return job.run();
+        }
+        jobs.queue.push_back(job);
+        drop(jobs);
+        self.queued.notify_one();
+    }
+
+    fn is_drainer(&self) -> bool {
+        self.drainer.get() == Some(&std::thread::current().id())
+    }
+
+    /// Runs every queued job on the current thread until the queue is empty.
+    fn flush(&self) {
+        loop {
+            let mut jobs = self.jobs.lock().unwrap();
+            let batch = std::mem::take(&mut jobs.queue);
+            if batch.is_empty() {
+                // Also wait for the jobs the drain thread took before us
+                if jobs.running > 0 && !self.is_drainer() {
+                    drop(
+                        self.drained
+                            .wait_while(jobs, |jobs| jobs.running > 0)
+                            .unwrap(),
+                    );
+                    continue;
+                }
+                return;
+            }
+            self.run(jobs, batch);
+        }
+    }
+
+    /// Runs a batch taken out of the queue while `jobs` was locked.
+    fn run(&self, mut jobs: std::sync::MutexGuard<Jobs>, batch: VecDeque<Job>) {
+        jobs.running += batch.len();
+        drop(jobs);
+        self.drained.notify_all();
+
+        let len = batch.len();
+        batch.into_iter().for_each(Job::run);
+
+        self.jobs.lock().unwrap().running -= len;
+        self.drained.notify_all();
+    }
+
+    fn drain(&self) {
+        let _ = self.drainer.set(std::thread::current().id());
+        loop {
+            let mut jobs = self
+                .queued
+                .wait_while(self.jobs.lock().unwrap(), |jobs| {
+                    jobs.queue.is_empty() && !jobs.closed
+                })
+                .unwrap();
+            if jobs.queue.is_empty() {
+                return;
+            }
+            let batch = std::mem::take(&mut jobs.queue);
+            self.run(jobs, batch);
+        }
+    }
+}
+
+/// # speedy_refs::DropQueue
+/// A queue of values released by their last [`DeferredArc`], dropped later on another thread or on
+/// [`DropQueue::flush`], so that dropping a huge value does not stall the thread releasing it.
+///
+/// - `DropQueue::spawn` drains the queue on a dedicated thread. Threads releasing values wait while
+///   `capacity` drops are pending, so that garbage can not pile up faster than it is dropped.
+/// - `DropQueue::new` only drops on `flush`. Once `capacity` drops are pending, releasing threads
+///   drop values themselves.
+/// - `DropQueue::immediate` drops every value at once on the releasing thread, as `Arc` does. It is
+///   meant for tests that need to observe drops as they happen.
+///
+/// `DropQueue` is a handle, and clones share the same queue. Once every handle is gone, the values
+/// still queued are dropped, by the thread dropping the last handle for a queue made by `new`, and
+/// values released afterwards are dropped by the thread releasing them. `DeferredArc`s do not keep
+/// the queue open.
+///
+/// # Examples
+///
+/// ```
+/// use speedy_refs::{DeferredArc, DropQueue};
+///
+/// let queue = DropQueue::new(16);
+/// let big = DeferredArc::with_queue(vec![0u8; 1 << 20], &queue);
+/// drop(big);
+/// assert_eq!(queue.len(), 1);
+///
+/// queue.flush();
+/// assert_eq!(queue.len(), 0);
+/// ```
+pub struct DropQueue {
+    state: std::sync::Arc<State>,
+}
+
+impl DropQueue {
+    fn with_mode(capacity: usize, mode: Mode) -> Self {
+        Self {
+            state: std::sync::Arc::new(State {
+                jobs: Mutex::new(Jobs {
+                    queue: VecDeque::new(),
+                    running: 0,
+                    closed: false,
+                }),
+                queued: Condvar::new(),
+                drained: Condvar::new(),
+                capacity,
+                mode,
+                handles: AtomicUsize::new(1),
+                drainer: OnceLock::new(),
+            }),
+        }
+    }
+
+    /// Creates a queue that is only drained by `flush`, holding at most `capacity` drops.
+    pub fn new(capacity: usize) -> Self {
+        Self::with_mode(capacity, Mode::Manual)
+    }
+
+    /// Creates a queue drained by a new dedicated thread, holding at most `capacity` drops.
+    /// A `capacity` of 0 is taken as 1, since releasing threads wait while the queue is full.
+    ///
+    /// The thread exits once every handle to the queue is gone and it has dropped the values
+    /// still queued.
+    pub fn spawn(capacity: usize) -> Self {
+        let queue = Self::with_mode(capacity.max(1), Mode::Thread);
+        let state = queue.state.clone();
+        std::thread::Builder::new()
+            .name("speedy_refs-drop".into())
+            .spawn(move || state.drain())
+            .expect("failed to spawn the drop thread");
+        queue
+    }
+
+    /// Creates a queue that drops every value at once on the thread releasing it.
+    pub fn immediate() -> Self {
+        Self::with_mode(0, Mode::Immediate)
+    }
+
+    /// Returns the queue used by `DeferredArc::new`, drained by its own thread.
+    pub fn global() -> &'static DropQueue {
+        static GLOBAL: OnceLock<DropQueue> = OnceLock::new();
+        GLOBAL.get_or_init(|| DropQueue::spawn(GLOBAL_CAPACITY))
+    }
+
+    /// Drops every queued value on the current thread, and waits for those the drain thread
+    /// is dropping. Values queued while flushing are dropped too.
+    pub fn flush(&self) {
+        self.state.flush();
+    }
+
+    /// Returns the number of values waiting to be dropped.
+    pub fn len(&self) -> usize {
+        self.state.jobs.lock().unwrap().queue.len()
+    }
+
+    /// Returns `true` if no value is waiting to be dropped.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Clone for DropQueue {
+    fn clone(&self) -> Self {
+        self.state.handles.fetch_add(1, Ordering::Relaxed);
+        Self {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl Drop for DropQueue {
+    fn drop(&mut self) {
+        if self.state.handles.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.state.jobs.lock().unwrap().closed = true;
+            // Wakes the drain thread to drop what is left, and the threads waiting to push
+            self.state.queued.notify_all();
+            self.state.drained.notify_all();
+            if self.state.mode == Mode::Manual {
+                self.state.flush();
+            }
+        }
+    }
+}
+
+/// # speedy_refs::DeferredArc
+/// An [`Arc`] whose value is dropped through a [`DropQueue`] instead of by the thread releasing it.
+///
+/// Cloning and sharing work as they do for `Arc`. When the last `DeferredArc` is dropped, the
+/// allocation is pushed onto its queue as it is, without moving the value, and the value is dropped
+/// later by the queue.
+///
+/// # Examples
+///
+/// ```
+/// use speedy_refs::{DeferredArc, DropQueue};
+///
+/// let index = DeferredArc::new((0..1000).map(|i| i.to_string()).collect::<Vec<_>>());
+/// let reader = index.clone();
+/// std::thread::spawn(move || assert_eq!(reader.len(), 1000)).join().unwrap();
+///
+/// // Dropped by the global queue's thread
+/// drop(index);
+/// DropQueue::global().flush();
+/// ```
+pub struct DeferredArc<T: Send + 'static> {
+    arc: ManuallyDrop<Arc<Deferred<T>>>,
+}
+
+impl<T: Send + 'static> DeferredArc<T> {
+    /// Creates a new `DeferredArc` whose value is dropped by the [global](DropQueue::global) queue.
+    pub fn new(val: T) -> Self {
+        Self::with_queue(val, DropQueue::global())
+    }
+
+    /// Creates a new `DeferredArc` whose value is dropped by `queue`.
+    pub fn with_queue(val: T, queue: &DropQueue) -> Self {
+        Self {
+            arc: ManuallyDrop::new(Arc::new(Deferred {
+                state: queue.state.clone(),
+                val,
+            })),
+        }
+    }
+
+    /// Returns the number of `DeferredArc` pointers to this value.
+    pub fn strong_count(this: &Self) -> usize {
+        Arc::strong_count(&this.arc)
+    }
+
+    /// Returns `true` if both `DeferredArc`s point to the same allocation.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        Arc::ptr_eq(&this.arc, &other.arc)
+    }
+}
+
+impl<T: Send + 'static> Clone for DeferredArc<T> {
+    fn clone(&self) -> Self {
+        Self {
+            arc: self.arc.clone(),
+        }
+    }
+}
+
+impl<T: Send + 'static> std::ops::Deref for DeferredArc<T> {
+    type Target = T;
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        &self.arc.val
+    }
+}
+
+impl<T: Send + 'static> AsRef<T> for DeferredArc<T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T: Send + 'static> Drop for DeferredArc<T> {
+    fn drop(&mut self) {
+        let arc = unsafe { ManuallyDrop::take(&mut self.arc) };
+        if let Some(inner) = Arc::release(arc) {
+            // The job may free the allocation before `push` returns, so the queue can not be
+            // borrowed from it
+            let state = unsafe { (*arc::Inner::value_ptr(inner.as_ptr())).state.clone() };
+            state.push(Job {
+                ptr: inner.cast(),
+                drop: drop_released::<T>,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DeferredArc, DropQueue};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct Counted(&'static AtomicUsize);
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_manual_queue() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        let queue = DropQueue::new(2);
+        let arcs: Vec<_> = (0..3)
+            .map(|_| DeferredArc::with_queue(Counted(&DROPS), &queue))
+            .collect();
+        let clone = arcs[0].clone();
+        drop(arcs);
+        assert_eq!(queue.len(), 2);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 0);
+
+        // The queue is full, so the releasing thread drops it itself
+        drop(DeferredArc::with_queue(Counted(&DROPS), &queue));
+        assert_eq!(DROPS.load(Ordering::Relaxed), 1);
+
+        queue.flush();
+        assert_eq!(DROPS.load(Ordering::Relaxed), 3);
+        drop(clone);
+        assert_eq!(queue.len(), 1);
+        queue.flush();
+        assert_eq!(DROPS.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn test_dropped_queue() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        let queue = DropQueue::new(4);
+        let arc = DeferredArc::with_queue(Counted(&DROPS), &queue);
+        drop(DeferredArc::with_queue(Counted(&DROPS), &queue));
+        assert_eq!(DROPS.load(Ordering::Relaxed), 0);
+        // The last handle drops what is still queued
+        drop(queue);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 1);
+        // Values released afterwards are dropped at once
+        drop(arc);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 2);
+
+        // Waits for the drain thread instead of for a queue that can hold nothing
+        let queue = DropQueue::spawn(0);
+        drop(DeferredArc::with_queue(Counted(&DROPS), &queue));
+        drop(DeferredArc::with_queue(Counted(&DROPS), &queue));
+        queue.flush();
+        assert_eq!(DROPS.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn test_thread_queue() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        let queue = DropQueue::spawn(4);
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let queue = queue.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..100 {
+                        let arc = DeferredArc::with_queue(Counted(&DROPS), &queue);
+                        // A value whose drop releases another deferred value
+                        let nested = DeferredArc::with_queue(arc.clone(), &queue);
+                        drop(arc);
+                        drop(nested);
+                    }
+                })
+            })
+            .collect();
+        handles.into_iter().for_each(|h| h.join().unwrap());
+
+        queue.flush();
+        assert_eq!(DROPS.load(Ordering::Relaxed), 400);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_immediate_queue() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        let queue = DropQueue::immediate();
+        let arc = DeferredArc::with_queue(Counted(&DROPS), &queue);
+        let clone = arc.clone();
+        assert_eq!(DeferredArc::strong_count(&arc), 2);
+        assert!(DeferredArc::ptr_eq(&arc, &clone));
+        drop(arc);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 0);
+        drop(clone);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,85 @@
+//! The callback an `Rc` or `Arc` allocation runs on its value right before dropping it.
+//!
+//! Only allocations made with a finalizer pay for it: they keep an `Option<Finalizer>` in front
+//! of their `Inner` and set `HAS_FINALIZER` in its weak count, so that every other `Inner` keeps
+//! just its counts and value.
+
+use std::alloc::Layout;
+use std::ptr::NonNull;
+
+/// Set in the weak count of an `Inner` that has a finalizer in front of it. The count itself
+/// is kept in the bits below it.
+pub(crate) const HAS_FINALIZER: usize = !(usize::MAX >> 1);
+
+/// Returns the layout of an allocation with a finalizer in front of an `Inner` of layout `inner`,
+/// and the offset of the `Inner` in it.
+pub(crate) fn layout_with(inner: Layout) -> (Layout, usize) {
+    let (layout, offset) = Layout::new::<Option<Finalizer>>()
+        .extend(inner)
+        .expect("value is too large");
+    (layout.pad_to_align(), offset)
+}
+
+/// Returns a pointer to the finalizer in front of the `Inner` of layout `inner` at `ptr`.
+///
+/// # Safety
+/// `ptr` must point to an `Inner` allocated after a finalizer, at the offset given by `layout_with`.
+pub(crate) unsafe fn slot(ptr: *mut u8, inner: Layout) -> *mut Option<Finalizer> {
+    ptr.sub(layout_with(inner).1).cast()
+}
+
+/// The type-erased start of a boxed `Closure`.
+#[repr(C)]
+struct Header {
+    /// Calls the closure with the value and frees the box.
+    call: unsafe fn(NonNull<Header>, *mut ()),
+    /// Frees the box without calling the closure.
+    free: unsafe fn(NonNull<Header>),
+}
+
+#[repr(C)]
+struct Closure<F> {
+    header: Header,
+    f: F,
+}
+
+/// A boxed `FnOnce(&mut T)` behind a thin pointer, so that storing it in front of an `Inner` only
+/// costs one word, whatever the type of the closure.
+pub(crate) struct Finalizer(NonNull<Header>);
+
+impl Finalizer {
+    pub(crate) fn new<T, F: FnOnce(&mut T)>(f: F) -> Self {
+        unsafe fn call<T, F: FnOnce(&mut T)>(this: NonNull<Header>, val: *mut ()) {
+            let Closure { f, .. } = *Box::from_raw(this.cast::<Closure<F>>().as_ptr());
+            f(&mut *val.cast::<T>())
+        }
+
+        unsafe fn free<F>(this: NonNull<Header>) {
+            drop(Box::from_raw(this.cast::<Closure<F>>().as_ptr()));
+        }
+
+        let closure = Box::new(Closure {
+            header: Header {
+                call: call::<T, F>,
+                free: free::<F>,
+            },
+            f,
+        });
+        Self(NonNull::from(Box::leak(closure)).cast())
+    }
+
+    /// Calls the finalizer with the value at `val`.
+    ///
+    /// # Safety
+    /// `val` must point to a valid, mutable value of the type the finalizer was created for.
+    pub(crate) unsafe fn call(self, val: *mut ()) {
+        let this = std::mem::ManuallyDrop::new(self);
+        (this.0.as_ref().call)(this.0, val)
+    }
+}
+
+impl Drop for Finalizer {
+    fn drop(&mut self) {
+        unsafe { (self.0.as_ref().free)(self.0) }
+    }
+}
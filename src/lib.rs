@@ -1,5 +1,11 @@
 #![feature(negative_impls)]
 #![feature(const_trait_impl)]
+#![feature(coerce_unsized)]
+#![feature(unsize)]
+#![feature(set_ptr_value)]
+#![feature(dropck_eyepatch)]
+#![feature(allocator_api)]
+#![feature(layout_for_ptr)]
 //! # speedy_refs
 //! A collection of useful smart pointers including some alternatives to std smart pointers.
 //! 
@@ -8,19 +14,55 @@
 //! # FEATURES
 //!
 //! - **Rc**:
-//!  Blazingly fast alternative to the std `Rc` smart pointer.
+//!   Blazingly fast alternative to the std `Rc` smart pointer, with `Weak` references for breaking cycles.
 //!
 //!  
 //! - **RefCell**:
-//!  Blazingly fast alternative to the std `RefCell`.
+//!   Alternative to the std `RefCell`, with the same borrow rules and cost, that reports where a conflicting borrow was made in debug builds. Its unchecked borrows skip the borrow flag in release builds and beat the std one.
 //!
 //!  
 //! - **Arc**:
-//! Lighter alternative the std `Arc` with equivalent performance
+//!   Lighter alternative the std `Arc` with equivalent performance, with `arc::Weak` references.
+//!
+//!
+//! - **AtomicArc**:
+//!   An atomically replaceable `Arc` whose readers never block and never see a freed value.
 //!
 //! 
+//! - **BiasedArc**:
+//!   An `Arc` that counts without atomics on the thread that created it, and atomically everywhere else.
+//!
+//!
+//! - **StaticRc** and **StaticArc**:
+//!   `static` storage for immortal values that ordinary `Rc` and `Arc` point to and never drop, even in `const` context.
+//!
+//!
+//! - **DeferredArc**:
+//!   An `Arc` whose value is dropped later by a `DropQueue`, on its own thread or on `flush`, instead of by the thread releasing it.
+//!
+//!
+//! - **RcPool** and **ArcPool**:
+//!   Pools that recycle the allocations of short-lived `Rc`s and `Arc`s through a free list instead of the global allocator.
+//!
+//!
+//! - **RcArena** and **ArenaRc**:
+//!   An arena of reference counted values whose storage is freed all at once when the arena is dropped, even if they point to each other in cycles. Values still caught in a cycle at that point are never dropped, so anything they own outside the arena leaks.
+//!
+//!
+//! - **ThinArc** and **ThinRc**:
+//!   One word wide `Arc` and `Rc` to a header followed by a slice, all stored in one allocation.
+//!
+//!
+//! - **UniqueRc** and **UniqueArc**:
+//!   Uniquely owned `Rc` and `Arc` that can be mutated, and `Weak` pointed to, before being shared for free.
+//!
+//!
+//! - **SendRc** and **RcBundle**:
+//!   `Rc` handles that can move to another thread all together, once a bundle has checked that it holds every clone.
+//!
+//!
 //! - **HeapCell**:
-//! Similar to `NonNull` with simpler type `deallocation` and `dropping`
+//!   Similar to `NonNull` with simpler type `deallocation` and `dropping`
 //!
 //! 
 //! - **Reon** - Read only static pointer that implements `Sync` and `Send`
@@ -30,25 +72,59 @@
 //!
 //! 
 //! - **SharedCell**:
-//! For Shared ownership without borrow checking.
+//!   For Shared ownership without borrow checking.
 //!
 //! 
 //! - **Borrow**:
-//! A cloneable shared ownership without borrow checking. Like how references are used in languages like java, go, python, etc.
+//!   A cloneable shared ownership without borrow checking. Like how references are used in languages like java, go, python, etc.
+//!
+//!
+//! - **ArcCell**:
+//!   Simple and more concise version of `Arc<SyncRefCell>`, the thread-safe counterpart of `RcCell`.
+//!
+//!
+//! - **SyncRefCell**:
+//!   Thread-safe counterpart of `RefCell` with non-blocking `try_read`/`try_write` and waiting `read`/`write`.
+//!
+//!
+//! - **AtomicPtr**:
+//!   An owned heap value that can be read without blocking and atomically replaced from multiple threads.
 
-mod arc;
+pub mod arc;
 mod rc;
 mod reon;
 mod cell;
 mod borrow;
+mod sync_cell;
+mod thin;
+mod send_rc;
+mod biased;
+mod deferred;
+mod pool;
+mod arena;
 
-pub(crate) mod atomic;
+mod atomic;
+mod sync;
+mod finalizer;
 
 pub use arc::*;
 pub use rc::*;
 pub use reon::*;
 pub use cell::*;
 pub use borrow::*;
+pub use atomic::*;
+pub use sync_cell::*;
+pub use thin::*;
+pub use send_rc::*;
+pub use biased::*;
+pub use deferred::*;
+pub use pool::*;
+pub use arena::*;
+// Both `rc` and `arc` define a `Weak`; the one at the crate root belongs to `Rc`.
+// The `Arc` one is available as `speedy_refs::arc::Weak`.
+pub use rc::Weak;
 
 #[cfg(test)]
-mod test;
\ No newline at end of file
+mod test;
+#[cfg(doctest)]
+mod compile_fail;
\ No newline at end of file
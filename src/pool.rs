@@ -0,0 +1,446 @@
+use std::alloc::{AllocError, Allocator, Global, Layout};
+use std::cell::{Cell, UnsafeCell};
+use std::marker::PhantomData;
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::{arc, rc, Arc, Rc};
+
+/// An `Rc` whose allocation comes from, and goes back to, an [`RcPool`].
+pub type PooledRc<'p, T> = Rc<T, &'p RcPool<T>>;
+
+/// An `Arc` whose allocation comes from, and goes back to, an [`ArcPool`].
+pub type PooledArc<'p, T> = Arc<T, &'p ArcPool<T>>;
+
+/// The first word of a block on a free list, pointing to the next free block.
+struct FreeBlock {
+    next: Option<NonNull<FreeBlock>>,
+}
+
+/// An intrusive list of free blocks of the same layout, linked through their first word.
+struct FreeList {
+    head: Option<NonNull<FreeBlock>>,
+    len: usize,
+}
+
+// The blocks are plain memory, owned by the list
+unsafe impl Send for FreeList {}
+
+impl FreeList {
+    const fn new() -> Self {
+        Self { head: None, len: 0 }
+    }
+
+    fn pop(&mut self) -> Option<NonNull<u8>> {
+        let block = self.head?;
+        self.head = unsafe { block.as_ref() }.next;
+        self.len -= 1;
+        Some(block.cast())
+    }
+
+    /// # Safety
+    /// `ptr` must be a block of the list's layout from `Global`, large and aligned enough for a
+    /// `FreeBlock`, that nothing else uses anymore.
+    unsafe fn push(&mut self, ptr: NonNull<u8>) {
+        let block = ptr.cast::<FreeBlock>();
+        block.as_ptr().write(FreeBlock { next: self.head });
+        self.head = Some(block);
+        self.len += 1;
+    }
+
+    /// Adds `additional` new blocks of `layout` to the list.
+    fn reserve(&mut self, layout: Layout, additional: usize) {
+        for _ in 0..additional {
+            let Ok(block) = Global.allocate(layout) else {
+                std::alloc::handle_alloc_error(layout);
+            };
+            unsafe { self.push(block.cast()) };
+        }
+    }
+
+    /// Gives every block back to `Global`.
+    ///
+    /// # Safety
+    /// Every block in the list must have the layout `layout`.
+    unsafe fn clear(&mut self, layout: Layout) {
+        while let Some(block) = self.pop() {
+            Global.deallocate(block, layout);
+        }
+    }
+}
+
+/// # speedy_refs::RcPool
+/// A pool of allocations for [`Rc<T>`](Rc), for workloads that create and drop a lot of
+/// short-lived `Rc`s to values of the same type, like the nodes of a syntax tree.
+///
+/// `RcPool::alloc` hands out a [`PooledRc`], an `Rc` that carries a reference to the pool as its
+/// allocator. When the last `Rc` and `Weak` to a value are dropped, its allocation is pushed on the
+/// pool's free list instead of going back to the global allocator, and the next `alloc` pops it
+/// again. Blocks are only returned to the global allocator when the pool is dropped.
+///
+/// `RcPool<T>` is an [`Allocator`] for blocks laid out like the allocation of an `Rc<T>`. Any other
+/// layout is passed through to the global allocator.
+///
+/// # Examples
+///
+/// ```
+/// use speedy_refs::{PooledRc, RcPool};
+///
+/// enum Expr<'p> {
+///     Num(i64),
+///     Add(PooledRc<'p, Expr<'p>>, PooledRc<'p, Expr<'p>>),
+/// }
+///
+/// fn eval(expr: &Expr) -> i64 {
+///     match expr {
+///         Expr::Num(n) => *n,
+///         Expr::Add(lhs, rhs) => eval(lhs) + eval(rhs),
+///     }
+/// }
+///
+/// let pool = RcPool::with_capacity(3);
+/// for i in 0..1000 {
+///     let one = pool.alloc(Expr::Num(1));
+///     let sum = pool.alloc(Expr::Add(one.clone(), pool.alloc(Expr::Num(i))));
+///     assert_eq!(eval(&sum), i + 1);
+/// }
+/// // Every expression reused the three blocks reserved up front
+/// assert_eq!(pool.free_blocks(), 3);
+/// ```
+pub struct RcPool<T> {
+    free: UnsafeCell<FreeList>,
+    // The pool only stores memory for `T`s, never a `T`
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> RcPool<T> {
+    const LAYOUT: Layout = Layout::new::<rc::Inner<T>>();
+
+    /// Creates an empty pool. Its blocks are allocated as the first `Rc`s are created.
+    pub const fn new() -> Self {
+        Self {
+            free: UnsafeCell::new(FreeList::new()),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Creates a pool holding `capacity` free blocks.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let pool = Self::new();
+        pool.reserve(capacity);
+        pool
+    }
+
+    /// Allocates `additional` more free blocks.
+    pub fn reserve(&self, additional: usize) {
+        self.free().reserve(Self::LAYOUT, additional)
+    }
+
+    /// Creates a new `Rc` to `val` in a block from the pool.
+    pub fn alloc(&self, val: T) -> PooledRc<'_, T> {
+        Rc::new_in(val, self)
+    }
+
+    /// Returns the number of blocks waiting in the pool to be reused.
+    pub fn free_blocks(&self) -> usize {
+        self.free().len
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    fn free(&self) -> &mut FreeList {
+        // The pool is `!Sync` and never calls out while the list is borrowed
+        unsafe { &mut *self.free.get() }
+    }
+}
+
+impl<T> Default for RcPool<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl<T> Allocator for RcPool<T> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if layout != Self::LAYOUT {
+            return Global.allocate(layout);
+        }
+        match self.free().pop() {
+            Some(block) => Ok(NonNull::slice_from_raw_parts(block, layout.size())),
+            None => Global.allocate(layout),
+        }
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout == Self::LAYOUT {
+            self.free().push(ptr)
+        } else {
+            Global.deallocate(ptr, layout)
+        }
+    }
+}
+
+// No `T` is left once the pool can be dropped, the blocks are only memory
+unsafe impl<#[may_dangle] T> Drop for RcPool<T> {
+    fn drop(&mut self) {
+        unsafe { self.free.get_mut().clear(Self::LAYOUT) }
+    }
+}
+
+/// Returns an id unique to the current thread, which is never 0.
+#[inline]
+fn current_thread() -> usize {
+    static NEXT: AtomicUsize = AtomicUsize::new(1);
+    thread_local! {
+        // Assigned on first use. A `const` local without a destructor is never lazily
+        // initialised, so reading it is a plain load on the hot path.
+        static ID: Cell<usize> = const { Cell::new(0) };
+    }
+    ID.with(|id| match id.get() {
+        0 => {
+            let new = NEXT.fetch_add(1, Ordering::Relaxed);
+            id.set(new);
+            new
+        }
+        id => id,
+    })
+}
+
+/// # speedy_refs::ArcPool
+/// The thread-safe counterpart of [`RcPool`], recycling the allocations of [`Arc<T>`](Arc).
+///
+/// `ArcPool::alloc` hands out a [`PooledArc`], which may be sent to other threads like any `Arc`.
+/// The pool is biased towards the thread that created it: that thread allocates from and frees to
+/// a local free list without any synchronization, like an [`RcPool`] does. Blocks freed on
+/// other threads are pushed on a second list behind a `Mutex`, which the owner takes over in one
+/// go once its own list runs dry. Other threads allocate from that shared list, or from the global
+/// allocator when it is empty.
+///
+/// # Examples
+///
+/// ```
+/// use speedy_refs::ArcPool;
+///
+/// let pool = ArcPool::new();
+/// std::thread::scope(|scope| {
+///     for i in 0..4 {
+///         let message = pool.alloc(format!("message {i}"));
+///         scope.spawn(move || assert!(message.starts_with("message")));
+///     }
+/// });
+/// assert!(pool.free_blocks() > 0);
+/// ```
+pub struct ArcPool<T> {
+    owner: usize,
+    /// Only touched by the `owner` thread.
+    local: UnsafeCell<FreeList>,
+    /// Blocks freed by other threads.
+    remote: Mutex<FreeList>,
+    /// Whether `remote` may hold blocks, so that the owner only locks it when it is worth it.
+    remote_pending: AtomicBool,
+    // The pool only stores memory for `T`s, never a `T`
+    _marker: PhantomData<fn() -> T>,
+}
+
+// Only the owner thread touches `local`, everything else is synchronized
+unsafe impl<T> Sync for ArcPool<T> {}
+
+impl<T> ArcPool<T> {
+    const LAYOUT: Layout = Layout::new::<arc::Inner<T>>();
+
+    /// Creates an empty pool owned by the current thread. Its blocks are allocated as the first
+    /// `Arc`s are created.
+    pub fn new() -> Self {
+        Self {
+            owner: current_thread(),
+            local: UnsafeCell::new(FreeList::new()),
+            remote: Mutex::new(FreeList::new()),
+            remote_pending: AtomicBool::new(false),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Creates a pool owned by the current thread, holding `capacity` free blocks.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let pool = Self::new();
+        pool.reserve(capacity);
+        pool
+    }
+
+    /// Allocates `additional` more free blocks.
+    pub fn reserve(&self, additional: usize) {
+        match self.local() {
+            Some(local) => local.reserve(Self::LAYOUT, additional),
+            None => {
+                self.remote().reserve(Self::LAYOUT, additional);
+                self.remote_pending.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Creates a new `Arc` to `val` in a block from the pool.
+    #[inline]
+    pub fn alloc(&self, val: T) -> PooledArc<'_, T> {
+        Arc::new_in(val, self)
+    }
+
+    /// Returns the number of blocks waiting in the pool to be reused.
+    ///
+    /// Only the owner thread sees the blocks on its local list. Other threads only count those
+    /// freed on other threads and not yet taken over by the owner.
+    pub fn free_blocks(&self) -> usize {
+        self.local().map_or(0, |local| local.len) + self.remote().len
+    }
+
+    /// Returns the local free list if called from the owner thread.
+    #[inline]
+    #[allow(clippy::mut_from_ref)]
+    fn local(&self) -> Option<&mut FreeList> {
+        // Only the owner gets the list, and never calls out while it is borrowed
+        (current_thread() == self.owner).then(|| unsafe { &mut *self.local.get() })
+    }
+
+    fn remote(&self) -> MutexGuard<'_, FreeList> {
+        // The list is consistent between calls, even if a thread panicked while holding the lock
+        self.remote
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<T> Default for ArcPool<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl<T> Allocator for ArcPool<T> {
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if layout != Self::LAYOUT {
+            return Global.allocate(layout);
+        }
+        let block = match self.local() {
+            Some(local) => {
+                if local.head.is_none() && self.remote_pending.load(Ordering::Relaxed) {
+                    let mut remote = self.remote();
+                    std::mem::swap(local, &mut *remote);
+                    self.remote_pending.store(false, Ordering::Relaxed);
+                }
+                local.pop()
+            }
+            None => self.remote().pop(),
+        };
+        match block {
+            Some(block) => Ok(NonNull::slice_from_raw_parts(block, layout.size())),
+            None => Global.allocate(layout),
+        }
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout != Self::LAYOUT {
+            return Global.deallocate(ptr, layout);
+        }
+        match self.local() {
+            Some(local) => local.push(ptr),
+            None => {
+                self.remote().push(ptr);
+                self.remote_pending.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+// No `T` is left once the pool can be dropped, the blocks are only memory
+unsafe impl<#[may_dangle] T> Drop for ArcPool<T> {
+    fn drop(&mut self) {
+        let remote = self
+            .remote
+            .get_mut()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        unsafe {
+            remote.clear(Self::LAYOUT);
+            self.local.get_mut().clear(Self::LAYOUT);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ArcPool, PooledRc, RcPool};
+
+    #[test]
+    fn test_rc_pool_reuses_blocks() {
+        let pool = RcPool::new();
+        let first = pool.alloc(String::from("first"));
+        let addr = &*first as *const String;
+        let weak = crate::Rc::downgrade(&first);
+        drop(first);
+        // The `Weak` still holds the block
+        assert_eq!(pool.free_blocks(), 0);
+        drop(weak);
+        assert_eq!(pool.free_blocks(), 1);
+
+        let second = pool.alloc(String::from("second"));
+        assert_eq!(&*second as *const String, addr);
+        assert_eq!(pool.free_blocks(), 0);
+
+        let mut shared = second.clone();
+        crate::Rc::make_mut(&mut shared).push('!');
+        assert_eq!(*second, "second");
+        assert_eq!(*shared, "second!");
+        drop((second, shared));
+        assert_eq!(pool.free_blocks(), 2);
+    }
+
+    #[test]
+    fn test_rc_pool_tree() {
+        struct Node<'p> {
+            children: Vec<PooledRc<'p, Node<'p>>>,
+        }
+
+        let pool = RcPool::with_capacity(4);
+        let leaf = pool.alloc(Node {
+            children: Vec::new(),
+        });
+        let root = pool.alloc(Node {
+            children: vec![
+                leaf.clone(),
+                leaf,
+                pool.alloc(Node {
+                    children: Vec::new(),
+                }),
+            ],
+        });
+        assert_eq!(pool.free_blocks(), 1);
+        assert_eq!(root.children.len(), 3);
+        drop(root);
+        assert_eq!(pool.free_blocks(), 4);
+    }
+
+    #[test]
+    fn test_arc_pool() {
+        let pool = ArcPool::with_capacity(2);
+        std::thread::scope(|scope| {
+            for i in 0..8 {
+                let val = pool.alloc(vec![i; 16]);
+                let other = val.clone();
+                scope.spawn(move || assert_eq!(other.iter().sum::<i32>(), i * 16));
+            }
+        });
+        // Blocks freed on the spawned threads are reused by the owner
+        let blocks = pool.free_blocks();
+        assert!(blocks >= 2);
+        let reused: Vec<_> = (0..blocks).map(|_| pool.alloc(Vec::new())).collect();
+        assert_eq!(pool.free_blocks(), 0);
+        drop(reused);
+        assert_eq!(pool.free_blocks(), blocks);
+
+        let weak = crate::Arc::downgrade(&pool.alloc(Vec::new()));
+        assert!(weak.upgrade().is_none());
+        let blocks = pool.free_blocks();
+        drop(weak);
+        assert_eq!(pool.free_blocks(), blocks + 1);
+    }
+}
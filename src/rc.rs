@@ -1,3 +1,17 @@
+use std::alloc::{AllocError, Allocator, Global};
+use std::marker::PhantomData;
+use std::mem::MaybeUninit;
+use std::ptr::NonNull;
+
+use crate::finalizer::{self, Finalizer, HAS_FINALIZER};
+
+/// The strong count of a value that is never dropped, like the one in a [`StaticRc`].
+/// Pointers to it never touch the counts.
+const IMMORTAL: usize = usize::MAX;
+
+/// The weak count of an immortal value. Unlike `IMMORTAL` it leaves `HAS_FINALIZER` clear.
+const IMMORTAL_WEAK: usize = !HAS_FINALIZER;
+
 /// # speedy_refs::Rc
 /// `Rc<T>` is a reference-counted pointer type that allows multiple shared references
 /// to a value of type `T`. It tracks the number of references and automatically deallocates
@@ -10,15 +24,21 @@
 /// - `inner` is moved to the heap
 /// - A pointer to the heap memory of `inner` is kept by the `Rc` struct
 /// - When the last Rc is dropped, `inner` is deallocated
-/// 
+///
+/// # Allocators
+/// `Rc::new` allocates from the global allocator. `Rc::new_in` and `Rc::try_new_in` take any
+/// [`Allocator`] instead, e.g a bump allocator that lives for a single request. The allocator
+/// is stored in the `Rc` and its `Weak`s, and frees the allocation once they are all gone.
+///
 /// # Weak References
 ///
-/// This `Rc<T>` implementation does not provide a way to distinguish between strong and weak references.
-/// Forming reference cycles with `Rc<T>` instances can lead to memory leaks, even after all strong references have been dropped.
-/// To avoid memory leaks caused by reference cycles, we recommend that you use `std::rc::Rc` when the use case it likely
-/// to form reference cycles.
+/// `Rc::downgrade` creates a [`Weak<T>`] pointer to the same allocation. A `Weak` does not keep the
+/// value alive, it only keeps the allocation alive. This makes `Weak` the right tool for back-pointers
+/// (e.g parent pointers in trees and graphs) that would otherwise form reference cycles and leak.
+/// The value is dropped when the last `Rc` is dropped, while the memory is only deallocated
+/// once the last `Rc` and the last `Weak` are both gone.
+///
 ///
-/// 
 /// # Examples
 ///
 /// ```
@@ -42,106 +62,1147 @@
 ///
 /// // value is deallocated here
 /// ```
-pub struct Rc<T>(*mut Inner<T>);
+// The `PhantomData` tells the drop check that an `Rc` owns, and may drop, an `Inner<T>`.
+pub struct Rc<T: ?Sized, A: Allocator = Global>(NonNull<Inner<T>>, PhantomData<Inner<T>>, A);
 
 /// Cloning An `Rc<T>` only creates a new pointer to the same content.
 ///
 /// For this reason T has no Clone bound.
-impl<T> Clone for Rc<T> {
+impl<T: ?Sized, A: Allocator + Clone> Clone for Rc<T, A> {
     fn clone(&self) -> Self {
-        // Self.0 remains valid until the last reference is dropped.
-        // For this reason it is safe to unwrap the `Option`
-        unsafe { self.0.as_ref().unwrap() }.increment();
-        Self(self.0)
+        self.inner().counts.inc_strong();
+        Self(self.0, PhantomData, self.2.clone())
     }
 }
 
 impl<T> Rc<T> {
     /// Creates a new `speedy_refs::Rc` instance and returns it.
     pub fn new(val: T) -> Self {
-        Self(Inner::new(val).into_ptr())
+        Self(Inner::new(val).into_ptr(), PhantomData, Global)
+    }
+
+    /// Creates a new `Rc`, or returns an error instead of aborting if the allocation fails.
+    /// The value is dropped in that case.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::Rc;
+    ///
+    /// let rc = Rc::try_new(5).expect("out of memory");
+    /// assert_eq!(*rc, 5);
+    /// ```
+    pub fn try_new(val: T) -> Result<Self, AllocError> {
+        Rc::try_new_in(val, Global)
+    }
+
+    /// Creates a new `Rc` whose value is left uninitialised, to be written in place before
+    /// `Rc::assume_init` is called.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::Rc;
+    ///
+    /// let mut rc = Rc::<[u64; 512]>::new_uninit();
+    /// let table = Rc::get_mut(&mut rc).unwrap();
+    /// for (i, slot) in unsafe { table.assume_init_mut() }.iter_mut().enumerate() {
+    ///     *slot = i as u64;
+    /// }
+    /// let table = unsafe { rc.assume_init() };
+    /// assert_eq!(table[511], 511);
+    /// ```
+    pub fn new_uninit() -> Rc<MaybeUninit<T>> {
+        Rc::try_new_uninit().unwrap_or_else(|_| Inner::<MaybeUninit<T>>::alloc_error())
+    }
+
+    /// Creates a new `Rc` whose value is left uninitialised, or returns an error if the
+    /// allocation fails.
+    pub fn try_new_uninit() -> Result<Rc<MaybeUninit<T>>, AllocError> {
+        Ok(Rc(
+            Inner::try_alloc_uninit(&Global, false)?,
+            PhantomData,
+            Global,
+        ))
+    }
+
+    /// Creates a new `Rc` whose value is filled with `0` bytes, which may or may not be a valid `T`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::Rc;
+    ///
+    /// let buffer = unsafe { Rc::<[u8; 1 << 16]>::new_zeroed().assume_init() };
+    /// assert!(buffer.iter().all(|&byte| byte == 0));
+    /// ```
+    pub fn new_zeroed() -> Rc<MaybeUninit<T>> {
+        Rc::try_new_zeroed().unwrap_or_else(|_| Inner::<MaybeUninit<T>>::alloc_error())
+    }
+
+    /// Creates a new `Rc` whose value is filled with `0` bytes, or returns an error if the
+    /// allocation fails.
+    pub fn try_new_zeroed() -> Result<Rc<MaybeUninit<T>>, AllocError> {
+        Ok(Rc(
+            Inner::try_alloc_uninit(&Global, true)?,
+            PhantomData,
+            Global,
+        ))
+    }
+
+    /// Creates a new `Rc` that calls `finalizer` with the value when the last `Rc` to it is dropped,
+    /// right before the value itself is dropped.
+    ///
+    /// The finalizer is not called if the value is taken out with `Rc::try_unwrap`, `Rc::into_inner`
+    /// or `Rc::unwrap_or_clone`. `Rc::make_mut` keeps it with the value when it moves it, but not
+    /// when it clones it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::Rc;
+    /// use std::cell::RefCell;
+    ///
+    /// let pool = std::rc::Rc::new(RefCell::new(Vec::new()));
+    /// let returned = pool.clone();
+    ///
+    /// let buffer = Rc::new_with_finalizer(Vec::<u8>::with_capacity(4096), move |buffer| {
+    ///     buffer.clear();
+    ///     returned.borrow_mut().push(std::mem::take(buffer));
+    /// });
+    /// let reader = buffer.clone();
+    /// drop(buffer);
+    /// assert!(pool.borrow().is_empty());
+    ///
+    /// drop(reader);
+    /// assert_eq!(pool.borrow()[0].capacity(), 4096);
+    /// ```
+    pub fn new_with_finalizer(val: T, finalizer: impl FnOnce(&mut T) + 'static) -> Self
+    where
+        T: 'static,
+    {
+        let finalizer = Finalizer::new(finalizer);
+        Self(
+            Inner::new(val).into_ptr_with_finalizer_in(finalizer, &Global),
+            PhantomData,
+            Global,
+        )
+    }
+}
+
+impl<T, A: Allocator> Rc<MaybeUninit<T>, A> {
+    /// Turns an `Rc` from `Rc::new_uninit` or `Rc::new_zeroed` into an `Rc` to the value
+    /// it has been initialised with.
+    ///
+    /// # Safety
+    /// The value must have been initialised to a valid `T`.
+    pub unsafe fn assume_init(self) -> Rc<T, A> {
+        let this = std::mem::ManuallyDrop::new(self);
+        Rc(this.0.cast(), PhantomData, std::ptr::read(&this.2))
+    }
+}
+
+impl<T, A: Allocator> Rc<T, A> {
+    /// Creates a new `Rc` in memory from `alloc`.
+    ///
+    /// # Panics
+    /// Calls `std::alloc::handle_alloc_error` if `alloc` fails to allocate.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// #![feature(allocator_api)]
+    /// use speedy_refs::Rc;
+    /// use std::alloc::System;
+    ///
+    /// let rc = Rc::new_in(5, System);
+    /// let weak = Rc::downgrade(&rc);
+    /// assert_eq!(*weak.upgrade().unwrap(), 5);
+    /// ```
+    pub fn new_in(val: T, alloc: A) -> Self {
+        Self(Inner::new(val).into_ptr_in(&alloc), PhantomData, alloc)
+    }
+
+    /// Creates a new `Rc` in memory from `alloc`, or returns an error if `alloc` fails to allocate.
+    /// The value is dropped in that case.
+    pub fn try_new_in(val: T, alloc: A) -> Result<Self, AllocError> {
+        Ok(Self(
+            Inner::new(val).try_into_ptr_in(&alloc)?,
+            PhantomData,
+            alloc,
+        ))
+    }
+
+    /// Returns a mutable reference to the value, cloning it into a new allocation first
+    /// if other `Rc` pointers share it.
+    ///
+    /// If only `Weak` pointers share the allocation, the value is moved into a new allocation
+    /// instead and those `Weak` pointers are disassociated from it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::Rc;
+    ///
+    /// let mut data = Rc::new(5);
+    /// *Rc::make_mut(&mut data) += 1; // updates in place
+    ///
+    /// let other = Rc::clone(&data);
+    /// *Rc::make_mut(&mut data) += 1; // clones the value
+    ///
+    /// assert_eq!(*data, 7);
+    /// assert_eq!(*other, 6);
+    /// ```
+    pub fn make_mut(this: &mut Self) -> &mut T
+    where
+        T: Clone,
+        A: Clone,
+    {
+        if Rc::strong_count(this) != 1 {
+            // Other strong pointers exist, so we must clone the value
+            *this = Rc::new_in(T::clone(this), this.2.clone());
+        } else if Rc::weak_count(this) != 0 {
+            // Only weak pointers remain. Give up our strong reference so that they can no longer
+            // upgrade, and move the value out to a fresh allocation.
+            this.inner().counts.dec_strong();
+            let old = Weak(this.0, &this.2);
+            let val = unsafe { std::ptr::read(&(*this.0.as_ptr()).val) };
+            // The value keeps its finalizer in its new allocation
+            this.0 = match unsafe { Inner::take_finalizer(old.0.as_ptr()) } {
+                Some(finalizer) => Inner::new(val).into_ptr_with_finalizer_in(finalizer, &this.2),
+                None => Inner::new(val).into_ptr_in(&this.2),
+            };
+            // Releases the implicit weak reference of the old allocation
+            drop(old);
+        }
+
+        unsafe { &mut (*this.0.as_ptr()).val }
+    }
+
+    /// Returns the inner value if the `Rc` has exactly one strong reference.
+    ///
+    /// Otherwise the same `Rc` is returned in the `Err` variant. Any remaining `Weak`
+    /// pointers can no longer be upgraded once the value has been taken.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::Rc;
+    ///
+    /// let x = Rc::new(3);
+    /// assert_eq!(Rc::try_unwrap(x).ok(), Some(3));
+    ///
+    /// let x = Rc::new(4);
+    /// let _y = Rc::clone(&x);
+    /// assert_eq!(*Rc::try_unwrap(x).err().unwrap(), 4);
+    /// ```
+    pub fn try_unwrap(this: Self) -> Result<T, Self> {
+        if Rc::strong_count(&this) != 1 {
+            return Err(this);
+        }
+
+        let this = std::mem::ManuallyDrop::new(this);
+        this.inner().counts.dec_strong();
+        let val = unsafe { std::ptr::read(&(*this.0.as_ptr()).val) };
+        // Releases the implicit weak reference held by the strong pointers
+        drop(Weak(this.0, unsafe { std::ptr::read(&this.2) }));
+        Ok(val)
+    }
+
+    /// Returns the inner value if this is the last `Rc` to it, otherwise drops this `Rc`
+    /// and returns `None`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::Rc;
+    ///
+    /// let x = Rc::new(3);
+    /// let y = Rc::clone(&x);
+    ///
+    /// assert_eq!(Rc::into_inner(x), None);
+    /// assert_eq!(Rc::into_inner(y), Some(3));
+    /// ```
+    pub fn into_inner(this: Self) -> Option<T> {
+        Rc::try_unwrap(this).ok()
+    }
+
+    /// Returns the inner value if this is the last `Rc` to it, otherwise returns a clone of it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::Rc;
+    ///
+    /// let x = Rc::new(String::from("only"));
+    /// assert_eq!(Rc::unwrap_or_clone(x), "only");
+    ///
+    /// let x = Rc::new(String::from("shared"));
+    /// let y = Rc::clone(&x);
+    /// assert_eq!(Rc::unwrap_or_clone(x), "shared");
+    /// assert_eq!(*y, "shared");
+    /// ```
+    pub fn unwrap_or_clone(this: Self) -> T
+    where
+        T: Clone,
+    {
+        Rc::try_unwrap(this).unwrap_or_else(|rc| T::clone(&rc))
+    }
+}
+
+impl<T: ?Sized, A: Allocator> Rc<T, A> {
+    /// Creates a new [`Weak`] pointer to the value of this `Rc`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::Rc;
+    ///
+    /// let rc = Rc::new(5);
+    /// let weak = Rc::downgrade(&rc);
+    ///
+    /// assert_eq!(*weak.upgrade().unwrap(), 5);
+    /// ```
+    pub fn downgrade(this: &Self) -> Weak<T, A>
+    where
+        A: Clone,
+    {
+        this.inner().counts.inc_weak();
+        Weak(this.0, this.2.clone())
+    }
+
+    /// Returns the number of `Rc` pointers to this value.
+    pub fn strong_count(this: &Self) -> usize {
+        this.inner().counts.strong()
+    }
+
+    /// Returns the number of `Weak` pointers to this value, or `0` for a value in a [`StaticRc`],
+    /// whose `Weak` pointers are not counted.
+    pub fn weak_count(this: &Self) -> usize {
+        let counts = &this.inner().counts;
+        if counts.is_immortal() {
+            return 0;
+        }
+        // The strong pointers collectively hold one implicit weak reference
+        counts.weak() - 1
+    }
+
+    /// Returns `true` if both `Rc`s point to the same allocation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::Rc;
+    ///
+    /// let five = Rc::new(5);
+    /// let same_five = Rc::clone(&five);
+    /// let other_five = Rc::new(5);
+    ///
+    /// assert!(Rc::ptr_eq(&five, &same_five));
+    /// assert!(!Rc::ptr_eq(&five, &other_five));
+    /// ```
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        std::ptr::addr_eq(this.0.as_ptr(), other.0.as_ptr())
+    }
+
+    /// Returns a reference to the allocator the value was allocated from.
+    pub fn allocator(this: &Self) -> &A {
+        &this.2
+    }
+
+    /// Returns a mutable reference to the value if there are no other `Rc` or `Weak` pointers to it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::Rc;
+    ///
+    /// let mut x = Rc::new(3);
+    /// *Rc::get_mut(&mut x).unwrap() = 4;
+    /// assert_eq!(*x, 4);
+    ///
+    /// let _y = Rc::clone(&x);
+    /// assert!(Rc::get_mut(&mut x).is_none());
+    /// ```
+    pub fn get_mut(this: &mut Self) -> Option<&mut T> {
+        if this.is_unique() {
+            // We are the only pointer to the allocation, so nobody else can observe the value.
+            Some(unsafe { &mut (*this.0.as_ptr()).val })
+        } else {
+            None
+        }
+    }
+
+    /// Checks that there are no other `Rc` or `Weak` pointers to the allocation.
+    #[inline]
+    fn is_unique(&self) -> bool {
+        Rc::strong_count(self) == 1 && Rc::weak_count(self) == 0
+    }
+
+    #[inline]
+    fn inner(&self) -> &Inner<T> {
+        // Self.0 remains valid until the last reference is dropped.
+        unsafe { self.0.as_ref() }
     }
 }
 
-impl<T> std::ops::Deref for Rc<T> {
+impl<T: ?Sized> Rc<T> {
+    /// Turns the `Rc` into an [`Arc`](crate::Arc) to the same allocation without copying the value,
+    /// if there are no other `Rc` or `Weak` pointers to it and it has no finalizer.
+    /// Otherwise the `Rc` is returned back.
+    ///
+    /// Not available with the `loom` feature, whose atomics do not share the layout of `Rc`'s counts.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::Rc;
+    ///
+    /// let names: Rc<[String]> = ["a", "b"].iter().map(|name| name.to_string()).collect();
+    /// let names = Rc::try_into_arc(names).ok().unwrap();
+    /// let handle = std::thread::spawn(move || names.concat());
+    /// assert_eq!(handle.join().unwrap(), "ab");
+    /// ```
+    #[cfg(not(feature = "loom"))]
+    pub fn try_into_arc(this: Self) -> Result<crate::Arc<T>, Self> {
+        // A finalizer given to `Rc::new_with_finalizer` may not be safe to call on another thread
+        if !this.is_unique() || this.inner().counts.has_finalizer() {
+            return Err(this);
+        }
+
+        // Both counts are 1, and `arc::Inner` stores them as atomics laid out like ours
+        let ptr = Rc::into_inner_ptr(this) as *mut crate::arc::Inner<T>;
+        Ok(unsafe { crate::Arc::from_inner_ptr(ptr) })
+    }
+
+    /// Gives up this `Rc`'s reference, returning the pointer it held without decrementing the count.
+    #[inline]
+    pub(crate) fn into_inner_ptr(this: Self) -> *mut Inner<T> {
+        std::mem::ManuallyDrop::new(this).0.as_ptr()
+    }
+
+    /// Takes over a strong reference previously given up through `Rc::into_inner_ptr`.
+    ///
+    /// # Safety
+    /// `inner` must carry a strong reference that nothing else will release.
+    #[inline]
+    pub(crate) unsafe fn from_inner_ptr(inner: *mut Inner<T>) -> Self {
+        Self(NonNull::new_unchecked(inner), PhantomData, Global)
+    }
+}
+
+impl<T: ?Sized, A: Allocator> std::ops::Deref for Rc<T, A> {
     type Target = T;
     #[inline]
     fn deref(&self) -> &Self::Target {
-        unsafe { &self.0.as_ref().unwrap().0 }
+        &self.inner().val
     }
 }
 
-impl<T> AsRef<T> for Rc<T> {
+impl<T: ?Sized, A: Allocator> AsRef<T> for Rc<T, A> {
     fn as_ref(&self) -> &T {
         std::ops::Deref::deref(self)
     }
 }
 
-impl<T> Drop for Rc<T> {
+unsafe impl<#[may_dangle] T: ?Sized, A: Allocator> Drop for Rc<T, A> {
     fn drop(&mut self) {
-        if unsafe { self.0.as_ref().unwrap().decrement() } == 0 {
-            // TODO
-            // println!("Dropping actual content");
-            let _ = unsafe { Box::from_raw(self.0) };
-            // unsafe { self.0.drop_in_place() }
-            /*
-            unsafe {
-                // std::ptr::drop_in_place(self.0);
-                std::alloc::dealloc(
-                    self.0.cast(),
-                    std::alloc::Layout::for_value(self.0.as_ref().unwrap()),
-                )
+        if self.inner().counts.dec_strong() == 0 {
+            // The value is dropped as soon as the last strong pointer is gone,
+            // even if weak pointers still keep the allocation alive.
+            unsafe { Inner::drop_value(self.0.as_ptr()) };
+
+            // Release the implicit weak reference held by the strong pointers
+            if unsafe { Inner::counts(self.0.as_ptr()) }.dec_weak() == 0 {
+                unsafe { Inner::dealloc(self.0.as_ptr(), &self.2) };
             }
-            */
+        }
+    }
+}
+
+/// Moves a boxed value, which may be unsized, into a new `Rc`.
+///
+/// # Examples
+///
+/// ```
+/// use speedy_refs::Rc;
+///
+/// let boxed: Box<dyn Fn() -> i32> = Box::new(|| 42);
+/// let rc: Rc<dyn Fn() -> i32> = Rc::from(boxed);
+/// assert_eq!(rc(), 42);
+/// ```
+impl<T: ?Sized> From<Box<T>> for Rc<T> {
+    fn from(boxed: Box<T>) -> Self {
+        Rc(Inner::from_box(boxed), PhantomData, Global)
+    }
+}
+
+/// Moves the elements of a `Vec` into a new `Rc<[T]>`.
+///
+/// # Examples
+///
+/// ```
+/// use speedy_refs::Rc;
+///
+/// let rc: Rc<[String]> = Rc::from(vec![String::from("a"), String::from("b")]);
+/// assert_eq!(rc.len(), 2);
+/// ```
+impl<T> From<Vec<T>> for Rc<[T]> {
+    fn from(mut vec: Vec<T>) -> Self {
+        let ptr = unsafe { Inner::copy_from(vec.as_ptr(), vec.len()) };
+        // The elements have been moved into the `Rc`, only the buffer is left for the `Vec` to free
+        unsafe { vec.set_len(0) };
+        Rc(ptr, PhantomData, Global)
+    }
+}
+
+/// Copies a string slice into a new `Rc<str>`.
+///
+/// # Examples
+///
+/// ```
+/// use speedy_refs::Rc;
+///
+/// let rc: Rc<str> = Rc::from("speedy");
+/// let clone = Rc::clone(&rc);
+/// assert_eq!(&*clone, "speedy");
+/// ```
+impl From<&str> for Rc<str> {
+    fn from(s: &str) -> Self {
+        let ptr = unsafe { Inner::copy_from(s.as_ptr(), s.len()) };
+        // `str` has the same layout as `[u8]`
+        Rc(
+            unsafe { NonNull::new_unchecked(ptr.as_ptr() as *mut Inner<str>) },
+            PhantomData,
+            Global,
+        )
+    }
+}
+
+/// Copies the contents of a `String` into a new `Rc<str>`.
+impl From<String> for Rc<str> {
+    fn from(s: String) -> Self {
+        Rc::from(s.as_str())
+    }
+}
+
+/// Collects the items of an iterator into a new `Rc<[T]>`.
+///
+/// # Examples
+///
+/// ```
+/// use speedy_refs::Rc;
+///
+/// let squares: Rc<[u32]> = (1..=4).map(|x| x * x).collect();
+/// assert_eq!(&*squares, [1, 4, 9, 16]);
+/// ```
+impl<T> FromIterator<T> for Rc<[T]> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Rc::from(iter.into_iter().collect::<Vec<T>>())
+    }
+}
+
+/// Allows `Rc<T>` to be coerced to `Rc<U>` when `T` unsizes to `U`, e.g `Rc<[i32; 3]>` to `Rc<[i32]>`
+/// or `Rc<String>` to `Rc<dyn Display>`.
+impl<T: ?Sized + std::marker::Unsize<U>, U: ?Sized, A: Allocator> std::ops::CoerceUnsized<Rc<U, A>>
+    for Rc<T, A>
+{
+}
+
+/// # speedy_refs::Weak
+/// `Weak<T>` is a non-owning version of [`Rc<T>`]. It is created through `Rc::downgrade`
+/// and must be upgraded back to an `Rc` with `Weak::upgrade` to access the value.
+///
+/// A `Weak` keeps the allocation alive but not the value, so `upgrade` returns `None`
+/// once the last `Rc` to the value has been dropped.
+///
+/// # Examples
+///
+/// ```
+/// use speedy_refs::{Rc, Weak};
+///
+/// let strong = Rc::new(String::from("parent"));
+/// let weak: Weak<String> = Rc::downgrade(&strong);
+///
+/// assert_eq!(Rc::strong_count(&strong), 1);
+/// assert_eq!(Rc::weak_count(&strong), 1);
+/// assert_eq!(weak.upgrade().as_deref().map(String::as_str), Some("parent"));
+///
+/// drop(strong);
+///
+/// // The value is gone but the weak pointer is still safe to use
+/// assert!(weak.upgrade().is_none());
+/// ```
+pub struct Weak<T: ?Sized, A: Allocator = Global>(NonNull<Inner<T>>, A);
+
+impl<T> Weak<T> {
+    /// Creates a new `Weak` that points to no value. Calling `upgrade` on it always returns `None`.
+    pub const fn new() -> Self {
+        Self(
+            unsafe { NonNull::new_unchecked(std::ptr::without_provenance_mut(usize::MAX)) },
+            Global,
+        )
+    }
+}
+
+impl<T: ?Sized, A: Allocator> Weak<T, A> {
+    /// Attempts to upgrade the `Weak` pointer to an `Rc`.
+    ///
+    /// Returns `None` if the value has already been dropped.
+    pub fn upgrade(&self) -> Option<Rc<T, A>>
+    where
+        A: Clone,
+    {
+        let counts = self.counts()?;
+        if counts.strong() == 0 {
+            None
+        } else {
+            counts.inc_strong();
+            Some(Rc(self.0, PhantomData, self.1.clone()))
+        }
+    }
+
+    /// Returns the number of `Rc` pointers to the value this `Weak` points to.
+    pub fn strong_count(&self) -> usize {
+        self.counts().map_or(0, Counts::strong)
+    }
+
+    /// Returns the number of `Weak` pointers to the value this `Weak` points to,
+    /// or `0` if there are no remaining `Rc` pointers or the value is in a [`StaticRc`].
+    pub fn weak_count(&self) -> usize {
+        match self.counts() {
+            Some(counts) if counts.strong() > 0 && !counts.is_immortal() => counts.weak() - 1,
+            _ => 0,
+        }
+    }
+
+    /// Returns `None` for a `Weak` created through `Weak::new`.
+    #[inline]
+    fn counts(&self) -> Option<&Counts> {
+        if self.0.as_ptr().addr() == usize::MAX {
+            None
         } else {
-            // println!("Dropping clone");
-            // TODO
+            // The allocation stays valid as long as a `Weak` exists, and starts with the counts
+            Some(unsafe { self.0.cast::<Counts>().as_ref() })
+        }
+    }
+}
+
+impl<T: ?Sized + std::marker::Unsize<U>, U: ?Sized, A: Allocator>
+    std::ops::CoerceUnsized<Weak<U, A>> for Weak<T, A>
+{
+}
+
+impl<T> Default for Weak<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: ?Sized, A: Allocator + Clone> Clone for Weak<T, A> {
+    fn clone(&self) -> Self {
+        if let Some(counts) = self.counts() {
+            counts.inc_weak();
+        }
+        Self(self.0, self.1.clone())
+    }
+}
+
+unsafe impl<#[may_dangle] T: ?Sized, A: Allocator> Drop for Weak<T, A> {
+    fn drop(&mut self) {
+        if let Some(counts) = self.counts() {
+            if counts.dec_weak() == 0 {
+                unsafe { Inner::dealloc(self.0.as_ptr(), &self.1) };
+            }
+        }
+    }
+}
+
+/// # speedy_refs::StaticRc
+/// Storage for a value that [`Rc`]s can point to without ever counting, for example a default
+/// built at compile time.
+///
+/// `StaticRc::new` is `const`, so it can initialise a `static`. `Rc::from_static` then turns a
+/// reference to that `static` into an ordinary `Rc`, in `const` context too. Cloning and dropping
+/// such an `Rc`, or `Weak` pointers to it, never touches the counts, and the value is never dropped.
+/// `Rc::strong_count` returns `usize::MAX` for it, and `Rc::weak_count` returns `0`.
+///
+/// # Examples
+///
+/// ```
+/// use speedy_refs::{Rc, StaticRc};
+///
+/// static EMPTY: StaticRc<Vec<u8>> = StaticRc::new(Vec::new());
+///
+/// let bytes = Rc::from_static(&EMPTY);
+/// let other = bytes.clone();
+/// assert!(Rc::ptr_eq(&bytes, &other));
+/// assert!(bytes.is_empty());
+/// ```
+pub struct StaticRc<T>(Inner<T>);
+
+impl<T> StaticRc<T> {
+    /// Creates the storage for an immortal `val`.
+    pub const fn new(val: T) -> Self {
+        Self(Inner {
+            counts: Counts::new(IMMORTAL, IMMORTAL_WEAK),
+            val,
+        })
+    }
+}
+
+impl<T> Rc<T> {
+    /// Creates an `Rc` to the value in a `static` [`StaticRc`], without counting.
+    pub const fn from_static(value: &'static StaticRc<T>) -> Self {
+        // Never written to, the counts of an immortal value are only read
+        Rc(
+            unsafe { NonNull::new_unchecked(std::ptr::from_ref(&value.0).cast_mut()) },
+            PhantomData,
+            Global,
+        )
+    }
+}
+
+// The counts are never written, so every thread may make its own `Rc`s to the value
+unsafe impl<T: Sync> Sync for StaticRc<T> {}
+
+/// # speedy_refs::UniqueRc
+/// `UniqueRc<T>` is an [`Rc<T>`] under construction. It is the only strong pointer to its value,
+/// so it gives out mutable access through `DerefMut`, and is turned into an `Rc` for free with
+/// `UniqueRc::into_shared`.
+///
+/// It lives in the same allocation an `Rc` does. [`Weak`] pointers may be created with
+/// `UniqueRc::downgrade` before sharing, e.g to build self-referential values, but they can
+/// only be upgraded once the `UniqueRc` has become an `Rc`.
+///
+/// # Examples
+///
+/// ```
+/// use speedy_refs::{Rc, UniqueRc, Weak};
+///
+/// struct Node {
+///     this: Weak<Node>,
+///     children: Vec<u32>,
+/// }
+///
+/// let mut node = UniqueRc::new(Node { this: Weak::new(), children: Vec::new() });
+/// node.this = UniqueRc::downgrade(&node);
+/// node.children.extend([1, 2, 3]);
+///
+/// let node: Rc<Node> = UniqueRc::into_shared(node);
+/// assert_eq!(node.this.upgrade().unwrap().children, [1, 2, 3]);
+/// ```
+pub struct UniqueRc<T: ?Sized> {
+    ptr: NonNull<Inner<T>>,
+    _marker: PhantomData<Inner<T>>,
+}
+
+impl<T> UniqueRc<T> {
+    /// Creates a new `UniqueRc` holding `val`.
+    pub fn new(val: T) -> Self {
+        let ptr = Inner::new(val).into_ptr();
+        // There is no `Rc` until `into_shared`, so `Weak` pointers can not upgrade yet
+        unsafe { ptr.as_ref() }.counts.dec_strong();
+        Self {
+            ptr,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: ?Sized> UniqueRc<T> {
+    /// Creates a new [`Weak`] pointer to the value. It can only be upgraded once `this` has been
+    /// turned into an `Rc` with `UniqueRc::into_shared`.
+    pub fn downgrade(this: &Self) -> Weak<T> {
+        this.inner().counts.inc_weak();
+        Weak(this.ptr, Global)
+    }
+
+    /// Turns the `UniqueRc` into an `Rc` to the same allocation, without copying the value.
+    pub fn into_shared(this: Self) -> Rc<T> {
+        let this = std::mem::ManuallyDrop::new(this);
+        this.inner().counts.inc_strong();
+        Rc(this.ptr, PhantomData, Global)
+    }
+
+    #[inline]
+    fn inner(&self) -> &Inner<T> {
+        // The allocation stays valid until the `UniqueRc` is dropped
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T: ?Sized> std::ops::Deref for UniqueRc<T> {
+    type Target = T;
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.inner().val
+    }
+}
+
+impl<T: ?Sized> std::ops::DerefMut for UniqueRc<T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // No `Rc` exists and `Weak` pointers can not upgrade, so nobody else can observe the value
+        unsafe { &mut (*self.ptr.as_ptr()).val }
+    }
+}
+
+impl<T: ?Sized> AsRef<T> for UniqueRc<T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T: ?Sized> AsMut<T> for UniqueRc<T> {
+    fn as_mut(&mut self) -> &mut T {
+        self
+    }
+}
+
+unsafe impl<#[may_dangle] T: ?Sized> Drop for UniqueRc<T> {
+    fn drop(&mut self) {
+        unsafe { Inner::drop_value(self.ptr.as_ptr()) };
+
+        // Release the implicit weak reference held by the strong pointers
+        if unsafe { Inner::counts(self.ptr.as_ptr()) }.dec_weak() == 0 {
+            unsafe { Inner::dealloc(self.ptr.as_ptr(), &Global) };
         }
     }
 }
 
+impl<T: ?Sized> From<UniqueRc<T>> for Rc<T> {
+    fn from(unique: UniqueRc<T>) -> Self {
+        UniqueRc::into_shared(unique)
+    }
+}
+
+impl<T: ?Sized + std::marker::Unsize<U>, U: ?Sized> std::ops::CoerceUnsized<UniqueRc<U>>
+    for UniqueRc<T>
+{
+}
+
 /// # Inner
-/// A helper struct for `Rc` that stores the value and the reference count
+/// A helper struct for `Rc` and `Weak` that stores the value and the reference counts
 /// for a shared value of type `T`. It is used to implement reference counting for the `Rc` type.
 ///
-/// The first field of `Inner` is the value of type `T` being shared by one or more `Rc`
-/// instances. The second field is an `UnsafeCell<usize>` that is used to store the reference count
-/// of the shared value. The `UnsafeCell` allows for interior mutability, which is necessary to
-/// increment or decrement the reference count from immutable context.
+/// The `strong` count is the number of `Rc` instances sharing the value. The `weak` count is the
+/// number of `Weak` instances plus one implicit reference held by all the strong pointers together.
+/// Both counts are stored in an `UnsafeCell<usize>`, which allows for interior mutability
+/// so that they can be incremented or decremented from immutable context.
+///
+/// The value is dropped when `strong` reaches zero, right after the finalizer if there is one,
+/// and the allocation is freed when `weak` reaches zero. A finalizer is kept in front of the
+/// `Inner`, which is flagged with `HAS_FINALIZER` in its weak count.
+///
+/// It is `repr(C)` so that the value always comes last and an `Inner` can be allocated by hand
+/// for unsized values like slices, strings and trait objects.
+/// It is laid out like `arc::Inner`, so that a unique `Rc` and `Arc` can be converted in place.
+#[repr(C)]
+pub(crate) struct Inner<T: ?Sized> {
+    counts: Counts,
+    val: T,
+}
 
-struct Inner<T>(T, std::cell::UnsafeCell<usize>);
+/// The counts at the start of every `Inner`.
+///
+/// `Weak` pointers only ever borrow the counts, never the whole `Inner`, so that they do not
+/// conflict with the `&mut` to the value handed out by a `UniqueRc`.
+#[repr(C)]
+struct Counts {
+    strong: std::cell::UnsafeCell<usize>,
+    weak: std::cell::UnsafeCell<usize>,
+}
 
 impl<T> Inner<T> {
-    /// Constructs a new `Inner` instance with the given value and an initial reference count of 1.
-    ///
+    /// Constructs a new `Inner` instance with the given value, a strong count of 1
+    /// and the implicit weak reference.
     pub(super) fn new(val: T) -> Self {
-        Self(val, std::cell::UnsafeCell::new(1))
+        Self {
+            counts: Counts::new(1, 1),
+            val,
+        }
     }
 
     /// Takes ownership of an `Inner` instance and returns a raw pointer to it.
+    pub(super) fn into_ptr(self) -> NonNull<Self> {
+        NonNull::from(Box::leak(Box::new(self)))
+    }
 
-    pub(super) fn into_ptr(self) -> *mut Self {
-        Box::into_raw(Box::new(self))
+    /// Moves an `Inner` into memory from `alloc`, calling `std::alloc::handle_alloc_error` if that fails.
+    fn into_ptr_in<A: Allocator>(self, alloc: &A) -> NonNull<Self> {
+        self.try_into_ptr_in(alloc)
+            .unwrap_or_else(|_| std::alloc::handle_alloc_error(std::alloc::Layout::new::<Self>()))
     }
 
-    /// Immutably decrement the count of the clones of the `Rc`
+    /// Moves an `Inner` into memory from `alloc`.
+    fn try_into_ptr_in<A: Allocator>(self, alloc: &A) -> Result<NonNull<Self>, AllocError> {
+        let ptr = alloc
+            .allocate(std::alloc::Layout::new::<Self>())?
+            .cast::<Self>();
+        unsafe { ptr.as_ptr().write(self) };
+        Ok(ptr)
+    }
 
-    fn decrement(&self) -> usize {
+    /// Moves an `Inner` into memory from `alloc`, after `finalizer`, calling
+    /// `std::alloc::handle_alloc_error` if that fails.
+    fn into_ptr_with_finalizer_in<A: Allocator>(
+        mut self,
+        finalizer: Finalizer,
+        alloc: &A,
+    ) -> NonNull<Self> {
+        let (layout, offset) = finalizer::layout_with(std::alloc::Layout::new::<Self>());
+        let Ok(mem) = alloc.allocate(layout) else {
+            std::alloc::handle_alloc_error(layout);
+        };
+
+        *self.counts.weak.get_mut() |= HAS_FINALIZER;
+        unsafe {
+            let mem = mem.cast::<u8>();
+            mem.cast::<Option<Finalizer>>().write(Some(finalizer));
+            let ptr = mem.add(offset).cast::<Self>();
+            ptr.write(self);
+            ptr
+        }
+    }
+}
+
+impl<T> Inner<MaybeUninit<T>> {
+    /// Allocates an `Inner` from `alloc` with a strong count of 1 and the implicit weak reference,
+    /// without building its value on the stack first. The value is zeroed if `zeroed` is set,
+    /// otherwise it is left uninitialised.
+    fn try_alloc_uninit<A: Allocator>(
+        alloc: &A,
+        zeroed: bool,
+    ) -> Result<NonNull<Self>, AllocError> {
+        let layout = std::alloc::Layout::new::<Self>();
+        let mem = if zeroed {
+            alloc.allocate_zeroed(layout)?
+        } else {
+            alloc.allocate(layout)?
+        };
+
+        let ptr = mem.cast::<Self>().as_ptr();
         unsafe {
-            *self.1.get() -= 1;
-            *self.1.get()
+            std::ptr::addr_of_mut!((*ptr).counts).write(Counts::new(1, 1));
         }
+        Ok(mem.cast())
+    }
+
+    fn alloc_error() -> ! {
+        std::alloc::handle_alloc_error(std::alloc::Layout::new::<Self>())
+    }
+}
+
+impl<T> Inner<[T]> {
+    /// Allocates an `Inner` for `len` elements and bitwise copies the elements at `src` into it.
+    ///
+    /// # Safety
+    /// `src` must be valid for reading `len` elements. The caller becomes responsible for not
+    /// dropping the originals unless `T` is `Copy`.
+    unsafe fn copy_from(src: *const T, len: usize) -> NonNull<Self> {
+        let layout = std::alloc::Layout::array::<T>(len).expect("slice is too large");
+        let ptr = Self::alloc_for(layout, |mem| {
+            std::ptr::slice_from_raw_parts_mut(mem.cast::<T>(), len) as *mut Self
+        });
+        std::ptr::copy_nonoverlapping(src, Self::value_ptr(ptr.as_ptr()).cast::<T>(), len);
+        ptr
+    }
+}
+
+impl<T: ?Sized> Inner<T> {
+    /// Returns the layout of an `Inner` whose value has the layout `val_layout`.
+    fn layout_for(val_layout: std::alloc::Layout) -> std::alloc::Layout {
+        std::alloc::Layout::new::<Inner<()>>()
+            .extend(val_layout)
+            .expect("value is too large")
+            .0
+            .pad_to_align()
+    }
+
+    /// Allocates an `Inner` for a value of layout `val_layout`, with a strong count of 1 and
+    /// the implicit weak reference. The value is left uninitialised.
+    ///
+    /// `with_metadata` turns the address of the allocation into a (possibly fat) pointer to the `Inner`.
+    ///
+    /// # Safety
+    /// `with_metadata` must return a pointer to the allocation whose value has the layout `val_layout`.
+    pub(crate) unsafe fn alloc_for(
+        val_layout: std::alloc::Layout,
+        with_metadata: impl FnOnce(*mut u8) -> *mut Self,
+    ) -> NonNull<Self> {
+        let layout = Self::layout_for(val_layout);
+        let Some(mem) = NonNull::new(std::alloc::alloc(layout)) else {
+            std::alloc::handle_alloc_error(layout);
+        };
+
+        let ptr = with_metadata(mem.as_ptr());
+        std::ptr::addr_of_mut!((*ptr).counts).write(Counts::new(1, 1));
+        NonNull::new_unchecked(ptr)
+    }
+
+    /// Frees an `Inner` from `Inner::alloc_for` whose value was never initialised.
+    ///
+    /// # Safety
+    /// `val_layout` must be the layout `ptr` was allocated for, and `ptr` must not be used afterwards.
+    pub(crate) unsafe fn dealloc_for(ptr: NonNull<Self>, val_layout: std::alloc::Layout) {
+        std::alloc::dealloc(ptr.as_ptr().cast(), Self::layout_for(val_layout));
+    }
+
+    /// Moves the value out of `boxed` into a new `Inner` and frees the box.
+    fn from_box(boxed: Box<T>) -> NonNull<Self> {
+        let raw = Box::into_raw(boxed);
+        unsafe {
+            let val_layout = std::alloc::Layout::for_value(&*raw);
+            let ptr = Self::alloc_for(val_layout, |mem| mem.with_metadata_of(raw as *const Self));
+            std::ptr::copy_nonoverlapping(
+                raw.cast::<u8>(),
+                Self::value_ptr(ptr.as_ptr()).cast::<u8>(),
+                val_layout.size(),
+            );
+            // Frees the box without dropping the value that was moved out of it
+            drop(Box::from_raw(raw as *mut std::mem::ManuallyDrop<T>));
+            ptr
+        }
+    }
+
+    /// Returns a pointer to the value of the `Inner` at `this`, which may not be initialised yet.
+    ///
+    /// # Safety
+    /// `this` must point to an allocated `Inner`.
+    #[inline]
+    pub(crate) unsafe fn value_ptr(this: *mut Self) -> *mut T {
+        std::ptr::addr_of_mut!((*this).val)
+    }
+
+    /// Returns the counts of the `Inner` at `this`, without going through its value.
+    ///
+    /// # Safety
+    /// `this` must point to an allocated `Inner`, whose value may already have been dropped.
+    #[inline]
+    unsafe fn counts<'a>(this: *mut Self) -> &'a Counts {
+        &*std::ptr::addr_of!((*this).counts)
+    }
+
+    /// Frees the memory of an `Inner` whose value has already been dropped.
+    ///
+    /// # Safety
+    /// `ptr` must have been allocated from `alloc`, which is `Global` for `Inner::into_ptr` and
+    /// `Inner::alloc_for`. Its value must have been dropped and it must not be used afterwards.
+    unsafe fn dealloc<A: Allocator>(ptr: *mut Self, alloc: &A) {
+        let layout = std::alloc::Layout::for_value_raw(ptr);
+        if !Self::counts(ptr).has_finalizer() {
+            alloc.deallocate(NonNull::new_unchecked(ptr.cast()), layout);
+            return;
+        }
+
+        // A finalizer left over by `try_unwrap` and friends is freed without being called
+        let slot = finalizer::slot(ptr.cast(), layout);
+        std::ptr::drop_in_place(slot);
+        let layout = finalizer::layout_with(layout).0;
+        alloc.deallocate(NonNull::new_unchecked(slot.cast()), layout);
+    }
+
+    /// Takes the finalizer out of the allocation of the `Inner` at `ptr`, if it has one.
+    ///
+    /// # Safety
+    /// `ptr` must point to an allocated `Inner`.
+    unsafe fn take_finalizer(ptr: *mut Self) -> Option<Finalizer> {
+        if !(*ptr).counts.has_finalizer() {
+            return None;
+        }
+        let layout = std::alloc::Layout::for_value(&*ptr);
+        (*finalizer::slot(ptr.cast(), layout)).take()
+    }
+
+    /// Runs the finalizer, if any, then drops the value.
+    ///
+    /// # Safety
+    /// The value must be initialised, and must not be used afterwards.
+    unsafe fn drop_value(ptr: *mut Self) {
+        if let Some(finalizer) = Self::take_finalizer(ptr) {
+            finalizer.call(Self::value_ptr(ptr).cast());
+        }
+        std::ptr::drop_in_place(Self::value_ptr(ptr));
+    }
+}
+
+impl Counts {
+    /// Counts of 1 for a new `Rc`, or `IMMORTAL` and `IMMORTAL_WEAK` for a `StaticRc`.
+    const fn new(strong: usize, weak: usize) -> Self {
+        Self {
+            strong: std::cell::UnsafeCell::new(strong),
+            weak: std::cell::UnsafeCell::new(weak),
+        }
+    }
+
+    #[inline]
+    fn strong(&self) -> usize {
+        unsafe { *self.strong.get() }
+    }
+
+    #[inline]
+    fn weak(&self) -> usize {
+        unsafe { *self.weak.get() & !HAS_FINALIZER }
+    }
+
+    /// Returns `true` if the `Inner` has a finalizer in front of it.
+    #[inline]
+    fn has_finalizer(&self) -> bool {
+        unsafe { *self.weak.get() & HAS_FINALIZER != 0 }
+    }
+
+    /// Returns `true` for a value that is never dropped. Its counts are never written,
+    /// so that a `StaticRc` can be shared by every thread.
+    #[inline]
+    fn is_immortal(&self) -> bool {
+        self.strong() == IMMORTAL
     }
 
     // Immutably increment the count of the clones of the `Rc`
+    #[inline]
+    fn inc_strong(&self) {
+        if !self.is_immortal() {
+            unsafe { *self.strong.get() += 1 }
+        }
+    }
 
-    fn increment(&self) {
-        unsafe { *self.1.get() += 1 }
+    /// Immutably decrement the count of the clones of the `Rc` and return the new count
+    #[inline]
+    fn dec_strong(&self) -> usize {
+        if self.is_immortal() {
+            return IMMORTAL;
+        }
+        unsafe {
+            *self.strong.get() -= 1;
+            *self.strong.get()
+        }
+    }
+
+    #[inline]
+    fn inc_weak(&self) {
+        if !self.is_immortal() {
+            unsafe { *self.weak.get() += 1 }
+        }
+    }
+
+    /// Immutably decrement the weak count and return the new count
+    #[inline]
+    fn dec_weak(&self) -> usize {
+        if self.is_immortal() {
+            return IMMORTAL;
+        }
+        unsafe { *self.weak.get() -= 1 };
+        self.weak()
     }
 }
 
-impl<T> !Send for Rc<T> {}
-impl<T> !Sync for Rc<T> {}
+impl<T: ?Sized, A: Allocator> !Send for Rc<T, A> {}
+impl<T: ?Sized, A: Allocator> !Sync for Rc<T, A> {}
+impl<T: ?Sized, A: Allocator> !Send for Weak<T, A> {}
+impl<T: ?Sized, A: Allocator> !Sync for Weak<T, A> {}
+impl<T: ?Sized> !Send for UniqueRc<T> {}
+impl<T: ?Sized> !Sync for UniqueRc<T> {}
 
 mod test {
     #[test]
@@ -172,4 +1233,330 @@ mod test {
     fn test_1() {
         assert_eq!(1, 1)
     }
+
+    #[test]
+    fn test_weak_upgrade() {
+        let rc = super::Rc::new(vec![1, 2, 3]);
+        let weak = super::Rc::downgrade(&rc);
+        assert_eq!(super::Rc::strong_count(&rc), 1);
+        assert_eq!(super::Rc::weak_count(&rc), 1);
+
+        let upgraded = weak.upgrade().unwrap();
+        assert_eq!(*upgraded, vec![1, 2, 3]);
+        assert_eq!(weak.strong_count(), 2);
+
+        drop(upgraded);
+        drop(rc);
+        assert!(weak.upgrade().is_none());
+        assert_eq!(weak.strong_count(), 0);
+        assert_eq!(weak.weak_count(), 0);
+        assert!(super::Weak::<u8>::new().upgrade().is_none());
+    }
+
+    #[test]
+    fn test_weak_drops_value_once() {
+        use std::cell::Cell;
+        struct Counted<'a>(&'a Cell<usize>);
+        impl Drop for Counted<'_> {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let drops = Cell::new(0);
+        let rc = super::Rc::new(Counted(&drops));
+        let weak = super::Rc::downgrade(&rc);
+        let weak2 = weak.clone();
+        drop(rc);
+        // The value goes away with the last strong pointer
+        assert_eq!(drops.get(), 1);
+        drop(weak);
+        drop(weak2);
+        assert_eq!(drops.get(), 1);
+    }
+
+    #[test]
+    fn test_parent_back_pointer() {
+        struct Node {
+            parent: super::Weak<Node>,
+            children: std::cell::RefCell<Vec<super::Rc<Node>>>,
+        }
+
+        let root = super::Rc::new(Node {
+            parent: super::Weak::new(),
+            children: Default::default(),
+        });
+        let child = super::Rc::new(Node {
+            parent: super::Rc::downgrade(&root),
+            children: Default::default(),
+        });
+        root.children.borrow_mut().push(child.clone());
+
+        assert!(root.parent.upgrade().is_none());
+        assert!(child.parent.upgrade().is_some());
+        assert_eq!(super::Rc::strong_count(&child), 2);
+        drop(root);
+        assert!(child.parent.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_make_mut_disassociates_weak() {
+        let mut rc = super::Rc::new(String::from("old"));
+        let weak = super::Rc::downgrade(&rc);
+        assert!(super::Rc::get_mut(&mut rc).is_none());
+
+        super::Rc::make_mut(&mut rc).push_str("er");
+        assert_eq!(*rc, "older");
+        assert!(weak.upgrade().is_none());
+        assert_eq!(super::Rc::weak_count(&rc), 0);
+        assert!(super::Rc::get_mut(&mut rc).is_some());
+    }
+
+    #[test]
+    fn test_try_unwrap_with_weak() {
+        let rc = super::Rc::new(vec![1]);
+        let weak = super::Rc::downgrade(&rc);
+        let clone = rc.clone();
+
+        let rc = super::Rc::try_unwrap(rc).err().unwrap();
+        drop(clone);
+        assert_eq!(super::Rc::try_unwrap(rc).ok(), Some(vec![1]));
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_unsized() {
+        use std::cell::Cell;
+        use std::fmt::Display;
+        struct Counted<'a>(&'a Cell<usize>);
+        impl Drop for Counted<'_> {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let display: super::Rc<dyn Display> = super::Rc::new(String::from("dyn"));
+        let weak: super::Weak<dyn Display> = super::Rc::downgrade(&display);
+        assert_eq!(display.to_string(), "dyn");
+        assert!(super::Rc::ptr_eq(&display, &weak.upgrade().unwrap()));
+        drop(display);
+        assert!(weak.upgrade().is_none());
+
+        let drops = Cell::new(0);
+        let slice: super::Rc<[Counted]> = (0..3).map(|_| Counted(&drops)).collect();
+        let clone = slice.clone();
+        drop(slice);
+        assert_eq!(drops.get(), 0);
+        drop(clone);
+        assert_eq!(drops.get(), 3);
+
+        let empty: super::Rc<[u64]> = super::Rc::from(Vec::new());
+        assert!(empty.is_empty());
+        let array: super::Rc<[u16]> = super::Rc::new([1, 2, 3]);
+        assert_eq!(&*array, [1, 2, 3]);
+        let boxed: super::Rc<[u8]> = super::Rc::from(Box::from([7u8; 5]));
+        assert_eq!(&*boxed, [7; 5]);
+        assert_eq!(&*super::Rc::<str>::from(String::from("string")), "string");
+    }
+
+    #[test]
+    fn test_unique_rc() {
+        use std::cell::Cell;
+        struct Counted<'a>(&'a Cell<usize>, Vec<u8>);
+        impl Drop for Counted<'_> {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let drops = Cell::new(0);
+        let mut unique = super::UniqueRc::new(Counted(&drops, Vec::new()));
+        let weak = super::UniqueRc::downgrade(&unique);
+        let value = &mut *unique;
+        // `Weak` pointers only borrow the counts, so they can be used while the value is borrowed
+        assert!(weak.upgrade().is_none());
+        value.1.push(1);
+        drop(unique);
+        assert_eq!(drops.get(), 1);
+        assert!(weak.upgrade().is_none());
+
+        let mut unique = super::UniqueRc::new(Counted(&drops, Vec::new()));
+        let weak = super::UniqueRc::downgrade(&unique);
+        unique.1.push(2);
+        let rc: super::Rc<Counted> = unique.into();
+        assert_eq!(super::Rc::strong_count(&rc), 1);
+        assert_eq!(super::Rc::weak_count(&rc), 1);
+        assert_eq!(weak.upgrade().unwrap().as_ref().1, [2]);
+        drop(rc);
+        assert_eq!(drops.get(), 2);
+    }
+
+    #[test]
+    fn test_into_arc() {
+        let rc = super::Rc::new(String::from("moved"));
+        let addr = &*rc as *const String;
+        let weak = super::Rc::downgrade(&rc);
+        let rc = super::Rc::try_into_arc(rc).err().unwrap();
+        drop(weak);
+
+        let arc = super::Rc::try_into_arc(rc).ok().unwrap();
+        assert_eq!(&*arc as *const String, addr);
+        let handle =
+            std::thread::spawn(move || crate::Arc::try_into_rc(arc).ok().map(|rc| rc.len()));
+        assert_eq!(handle.join().unwrap(), Some(5));
+
+        let rc: super::Rc<[u8]> = super::Rc::from(vec![1, 2, 3]);
+        let arc = super::Rc::try_into_arc(rc).ok().unwrap();
+        let other = arc.clone();
+        let arc = crate::Arc::try_into_rc(arc).err().unwrap();
+        drop(other);
+        let rc = crate::Arc::try_into_rc(arc).ok().unwrap();
+        assert_eq!(super::Rc::weak_count(&rc), 0);
+        assert_eq!(&*rc, [1, 2, 3]);
+    }
+
+    #[test]
+    fn test_static() {
+        static VALUE: super::StaticRc<String> = super::StaticRc::new(String::new());
+        const EMPTY: super::Rc<String> = super::Rc::from_static(&VALUE);
+
+        let mut rc = EMPTY;
+        let clones: Vec<_> = (0..4).map(|_| rc.clone()).collect();
+        let weak = super::Rc::downgrade(&rc);
+        assert_eq!(super::Rc::strong_count(&rc), usize::MAX);
+        assert_eq!(super::Rc::weak_count(&rc), 0);
+        assert_eq!(weak.weak_count(), 0);
+        assert!(super::Rc::ptr_eq(&weak.upgrade().unwrap(), &clones[0]));
+        assert!(super::Rc::get_mut(&mut rc).is_none());
+        assert!(super::Rc::try_unwrap(rc.clone()).is_err());
+        super::Rc::make_mut(&mut rc).push_str("copy");
+        assert_eq!(*rc, "copy");
+        drop((clones, weak));
+
+        let handle = std::thread::spawn(|| EMPTY.len() + super::Rc::from_static(&VALUE).len());
+        assert_eq!(handle.join().unwrap(), 0);
+        assert_eq!(VALUE.0.counts.strong(), usize::MAX);
+        assert_eq!(unsafe { *VALUE.0.counts.weak.get() }, super::IMMORTAL_WEAK);
+        assert!(!VALUE.0.counts.has_finalizer());
+    }
+
+    #[test]
+    fn test_finalizer() {
+        type Log = std::rc::Rc<std::cell::RefCell<Vec<&'static str>>>;
+        #[derive(Clone)]
+        struct Logged(Log, Vec<u8>);
+        impl Drop for Logged {
+            fn drop(&mut self) {
+                self.0.borrow_mut().push("drop");
+            }
+        }
+        let new = |log: &Log| {
+            let finalizer_log = log.clone();
+            super::Rc::new_with_finalizer(Logged(log.clone(), vec![1]), move |val| {
+                val.1.push(2);
+                finalizer_log.borrow_mut().push("finalize")
+            })
+        };
+
+        // Allocations without a finalizer do not make room for one
+        assert_eq!(std::mem::size_of::<super::Inner<u64>>(), 24);
+
+        let log = Log::default();
+        let rc = new(&log);
+        let weak = super::Rc::downgrade(&rc);
+        assert_eq!(super::Rc::weak_count(&rc), 1);
+        drop(rc.clone());
+        assert!(log.borrow().is_empty());
+        drop(rc);
+        assert_eq!(*log.borrow(), ["finalize", "drop"]);
+        drop(weak);
+        assert_eq!(log.borrow().len(), 2);
+
+        log.borrow_mut().clear();
+        let rc = new(&log);
+        let val = super::Rc::try_unwrap(rc).ok().unwrap();
+        assert_eq!(val.1, [1]);
+        drop(val);
+        assert_eq!(*log.borrow(), ["drop"]);
+
+        log.borrow_mut().clear();
+        let mut rc = new(&log);
+        let weak = super::Rc::downgrade(&rc);
+        super::Rc::make_mut(&mut rc).1.push(3);
+        assert!(weak.upgrade().is_none());
+        assert!(log.borrow().is_empty());
+        let rc = super::Rc::try_into_arc(rc).err().unwrap();
+        drop(rc);
+        assert_eq!(*log.borrow(), ["finalize", "drop"]);
+
+        #[repr(align(32))]
+        struct Aligned(u8);
+        log.borrow_mut().clear();
+        let finalizer_log = log.clone();
+        let rc: super::Rc<dyn std::any::Any> =
+            super::Rc::new_with_finalizer(Aligned(1), move |val| {
+                val.0 = 2;
+                finalizer_log.borrow_mut().push("finalize")
+            });
+        assert!(std::ptr::from_ref(&*rc).addr().is_multiple_of(32));
+        drop(rc);
+        assert_eq!(*log.borrow(), ["finalize"]);
+    }
+
+    #[test]
+    fn test_allocator() {
+        use crate::test::{Counting, Exhausted};
+
+        let alloc = Counting::default();
+        let rc = super::Rc::new_in(String::from("value"), alloc.clone());
+        let clone = rc.clone();
+        let weak = super::Rc::downgrade(&rc);
+        assert_eq!(alloc.live(), 1);
+        drop((rc, clone));
+        assert!(weak.upgrade().is_none());
+        assert_eq!(alloc.live(), 1);
+        drop(weak);
+        assert_eq!(alloc.live(), 0);
+
+        let mut rc = super::Rc::try_new_in(vec![1], alloc.clone()).ok().unwrap();
+        let weak = super::Rc::downgrade(&rc);
+        super::Rc::make_mut(&mut rc).push(2);
+        assert_eq!(alloc.live(), 2);
+        drop(weak);
+        let other = rc.clone();
+        super::Rc::make_mut(&mut rc).push(3);
+        assert_eq!(alloc.live(), 2);
+        assert_eq!(*other, [1, 2]);
+        drop(other);
+        assert_eq!(super::Rc::try_unwrap(rc).ok().unwrap(), [1, 2, 3]);
+        assert_eq!(alloc.live(), 0);
+
+        let drops = std::rc::Rc::new(());
+        assert!(super::Rc::try_new_in(drops.clone(), Exhausted).is_err());
+        assert_eq!(std::rc::Rc::strong_count(&drops), 1);
+    }
+
+    #[test]
+    fn test_uninit() {
+        let mut rc = super::Rc::<String>::new_uninit();
+        let weak = super::Rc::downgrade(&rc);
+        assert!(super::Rc::get_mut(&mut rc).is_none());
+        drop(weak);
+        super::Rc::get_mut(&mut rc)
+            .unwrap()
+            .write(String::from("in place"));
+        let rc = unsafe { rc.assume_init() };
+        let weak = super::Rc::downgrade(&rc);
+        assert_eq!(*weak.upgrade().unwrap(), "in place");
+        drop(rc);
+        assert!(weak.upgrade().is_none());
+
+        let zeroed = unsafe {
+            super::Rc::<[u64; 256]>::try_new_zeroed()
+                .unwrap()
+                .assume_init()
+        };
+        assert_eq!(*zeroed, [0; 256]);
+        assert_eq!(*super::Rc::try_new(1).unwrap(), 1);
+    }
 }
@@ -30,27 +30,23 @@
 /// use std::thread;
 /// use speedy_refs::Reon;
 ///
-/// fn main() {
-///     let x = Reon::new(42);
-///     let num_threads = 4;
-///
-///     let mut handles = Vec::with_capacity(num_threads);
+/// let x = Reon::new(42);
+/// let num_threads = 4;
 ///
+/// let mut handles = Vec::with_capacity(num_threads);
 ///
-///     for _ in 0..num_threads {
-///         let x = x.clone();
-///         let thread = thread::spawn(move || {
-///             // Do some immutable stuff with x here
-///             assert_eq!(42, *x);
-///         });
-///         handles.push(thread);
-///     }
+/// for _ in 0..num_threads {
+///     let x = x.clone();
+///     let thread = thread::spawn(move || {
+///         // Do some immutable stuff with x here
+///         assert_eq!(42, *x);
+///     });
+///     handles.push(thread);
+/// }
 ///
-///     for thread in handles {
-///         thread.join().unwrap();
-///     }
-///}
-
+/// for thread in handles {
+///     thread.join().unwrap();
+/// }
 /// ```
 #[derive(Copy)]
 pub struct Reon<T: 'static + Sync> {
@@ -92,6 +88,22 @@ impl<T: 'static + Sync> Reon<T> {
             inner: Box::leak(Box::new(value)),
         }
     }
+
+    /// Constructs a new `Reon<T>` from a given value, or returns an error instead of aborting
+    /// if the allocation fails. The value is dropped in that case.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::Reon;
+    /// let x = Reon::try_new(42).expect("out of memory");
+    /// assert_eq!(*x, 42);
+    /// ```
+    pub fn try_new(value: T) -> Result<Self, std::alloc::AllocError> {
+        Ok(Self {
+            inner: Box::leak(Box::try_new(value)?),
+        })
+    }
 }
 
 unsafe impl<T: 'static + Sync> Send for Reon<T> {}
@@ -0,0 +1,523 @@
+use std::collections::HashMap;
+use std::mem::ManuallyDrop;
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::Rc;
+
+/// The owner of allocations packed into an [`RcBundle`] while it travels between threads.
+const IN_TRANSIT: usize = 0;
+
+/// Returns an id unique to the current thread, which is never `IN_TRANSIT`.
+fn current_thread() -> usize {
+    static NEXT: AtomicUsize = AtomicUsize::new(IN_TRANSIT + 1);
+    thread_local! {
+        static ID: usize = NEXT.fetch_add(1, Ordering::Relaxed);
+    }
+    // A thread whose locals are being destroyed owns nothing, so its handles are leaked
+    ID.try_with(|id| *id).unwrap_or(usize::MAX)
+}
+
+/// The value behind a `SendRc`, together with the thread allowed to use it.
+struct Owned<T: ?Sized> {
+    owner: AtomicUsize,
+    val: T,
+}
+
+/// # speedy_refs::SendRc
+/// An [`Rc`] that can be moved to another thread together with every other clone of it,
+/// inside an [`RcBundle`].
+///
+/// Every allocation belongs to one thread, the one that created it at first. Using a `SendRc`
+/// on any other thread panics, and dropping it there leaks its reference instead of touching
+/// the count. The counts themselves stay as cheap as those of `Rc`, which `SendRc` is built on.
+///
+/// Ownership moves with [`RcBundle::pack`], once it has checked that the bundle holds every
+/// clone of each `SendRc` in it.
+///
+/// # Examples
+///
+/// ```
+/// use speedy_refs::{RcBundle, SendRc};
+///
+/// let shared = SendRc::new(5);
+/// let graph = vec![shared.clone(), shared, SendRc::new(6)];
+///
+/// let bundle = RcBundle::pack(graph).ok().unwrap();
+/// let handle = std::thread::spawn(move || {
+///     let graph = bundle.unpack();
+///     graph.iter().map(|val| **val).sum::<i32>()
+/// });
+/// assert_eq!(handle.join().unwrap(), 16);
+/// ```
+pub struct SendRc<T: ?Sized> {
+    rc: ManuallyDrop<Rc<Owned<T>>>,
+}
+
+impl<T> SendRc<T> {
+    /// Creates a new `SendRc` owned by the current thread.
+    pub fn new(val: T) -> Self {
+        Self {
+            rc: ManuallyDrop::new(Rc::new(Owned {
+                owner: AtomicUsize::new(current_thread()),
+                val,
+            })),
+        }
+    }
+}
+
+impl<T: ?Sized> SendRc<T> {
+    /// Returns the number of `SendRc` pointers to this value.
+    ///
+    /// # Panics
+    /// If the current thread does not own the value.
+    pub fn strong_count(this: &Self) -> usize {
+        this.check_owner();
+        Rc::strong_count(&this.rc)
+    }
+
+    /// Returns `true` if both `SendRc`s point to the same allocation.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        Rc::ptr_eq(&this.rc, &other.rc)
+    }
+
+    /// Returns `true` if the current thread owns the value.
+    #[inline]
+    fn is_owned(&self) -> bool {
+        // Only reads the `Owned`, which stays alive as long as any handle does
+        self.rc.owner.load(Ordering::Relaxed) == current_thread()
+    }
+
+    #[inline]
+    #[track_caller]
+    fn check_owner(&self) {
+        if !self.is_owned() {
+            panic!("SendRc used on a thread that does not own it");
+        }
+    }
+}
+
+impl<T: ?Sized> Clone for SendRc<T> {
+    /// # Panics
+    /// If the current thread does not own the value.
+    #[track_caller]
+    fn clone(&self) -> Self {
+        self.check_owner();
+        Self {
+            rc: self.rc.clone(),
+        }
+    }
+}
+
+impl<T: ?Sized> std::ops::Deref for SendRc<T> {
+    type Target = T;
+
+    /// # Panics
+    /// If the current thread does not own the value.
+    #[inline]
+    #[track_caller]
+    fn deref(&self) -> &Self::Target {
+        self.check_owner();
+        &self.rc.val
+    }
+}
+
+impl<T: ?Sized> AsRef<T> for SendRc<T> {
+    #[track_caller]
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T: ?Sized> Drop for SendRc<T> {
+    fn drop(&mut self) {
+        // Another thread may be using the counts, so a handle left behind gives up its reference
+        if self.is_owned() {
+            unsafe { ManuallyDrop::drop(&mut self.rc) };
+        }
+    }
+}
+
+// Only the owning thread reads or writes the counts and the value
+unsafe impl<T: ?Sized + Send> Send for SendRc<T> {}
+
+/// # speedy_refs::Bundle
+/// Values whose [`SendRc`]s can be found by [`RcBundle::pack`].
+///
+/// It is implemented for `SendRc`, the std containers, the cells of this crate and the
+/// primitive types. Types holding `SendRc`s implement it by packing each of their fields.
+///
+/// # Safety
+/// `pack` must pass every `SendRc` stored in `self` to [`Packer::park`], either directly or through
+/// the `Bundle` implementation of a field, exactly once. It must not park any other `SendRc`.
+///
+/// # Examples
+///
+/// ```
+/// use speedy_refs::{Bundle, Packer, RefCell, SendRc};
+///
+/// struct Node {
+///     name: String,
+///     children: RefCell<Vec<SendRc<Node>>>,
+/// }
+///
+/// unsafe impl Bundle for Node {
+///     fn pack(&self, packer: &mut Packer) {
+///         self.name.pack(packer);
+///         self.children.pack(packer);
+///     }
+/// }
+/// ```
+pub unsafe trait Bundle {
+    /// Parks every `SendRc` stored in `self` in `packer`.
+    fn pack(&self, packer: &mut Packer);
+}
+
+/// Collects the `SendRc`s of a value for [`RcBundle::pack`].
+pub struct Packer {
+    allocations: HashMap<*const (), Parked>,
+    /// Set when a `SendRc` owned by another thread is found.
+    foreign: bool,
+}
+
+/// The handles to one allocation found so far.
+struct Parked {
+    owner: NonNull<AtomicUsize>,
+    handles: usize,
+    strong: usize,
+}
+
+impl Packer {
+    /// Counts `rc` as being inside the bundle, and packs its value the first time its allocation is found.
+    pub fn park<T: ?Sized + Bundle>(&mut self, rc: &SendRc<T>) {
+        if !rc.is_owned() {
+            self.foreign = true;
+            return;
+        }
+
+        let key = std::ptr::from_ref::<Owned<T>>(&rc.rc).cast::<()>();
+        if let Some(parked) = self.allocations.get_mut(&key) {
+            parked.handles += 1;
+            return;
+        }
+
+        self.allocations.insert(
+            key,
+            Parked {
+                owner: NonNull::from(&rc.rc.owner),
+                handles: 1,
+                strong: Rc::strong_count(&rc.rc),
+            },
+        );
+        // Registered first so that cycles are only walked once
+        rc.rc.val.pack(self);
+    }
+}
+
+/// # speedy_refs::RcBundle
+/// A value holding [`SendRc`]s, checked to hold every clone of each of them, so that it can be
+/// sent to another thread as a whole.
+///
+/// While the bundle travels no thread owns its `SendRc`s. [`RcBundle::unpack`], or dropping the
+/// bundle, hands them to the thread doing it.
+pub struct RcBundle<T: Bundle> {
+    root: ManuallyDrop<T>,
+    owners: Vec<NonNull<AtomicUsize>>,
+}
+
+impl<T: Bundle> RcBundle<T> {
+    /// Packs `root` into a bundle that can be sent to another thread.
+    ///
+    /// Returns `root` back if a `SendRc` in it is owned by another thread, or has clones
+    /// outside of `root`.
+    pub fn pack(root: T) -> Result<Self, T> {
+        let mut packer = Packer {
+            allocations: HashMap::new(),
+            foreign: false,
+        };
+        root.pack(&mut packer);
+
+        if packer.foreign
+            || packer
+                .allocations
+                .values()
+                .any(|parked| parked.handles != parked.strong)
+        {
+            return Err(root);
+        }
+
+        let owners: Vec<_> = packer
+            .allocations
+            .into_values()
+            .map(|parked| parked.owner)
+            .collect();
+        for owner in &owners {
+            // The handles in `root` keep every allocation alive
+            unsafe { owner.as_ref() }.store(IN_TRANSIT, Ordering::Relaxed);
+        }
+        Ok(Self {
+            root: ManuallyDrop::new(root),
+            owners,
+        })
+    }
+
+    /// Hands every `SendRc` in the bundle to the current thread and returns the value.
+    pub fn unpack(self) -> T {
+        let mut this = ManuallyDrop::new(self);
+        this.claim();
+        unsafe {
+            std::ptr::drop_in_place(&mut this.owners);
+            ManuallyDrop::take(&mut this.root)
+        }
+    }
+
+    fn claim(&mut self) {
+        let thread = current_thread();
+        for owner in &self.owners {
+            unsafe { owner.as_ref() }.store(thread, Ordering::Relaxed);
+        }
+    }
+}
+
+impl<T: Bundle> Drop for RcBundle<T> {
+    fn drop(&mut self) {
+        self.claim();
+        unsafe { ManuallyDrop::drop(&mut self.root) };
+    }
+}
+
+// Every `SendRc` in the bundle has been checked to be inside it, and none can be used until it
+// is unpacked, so the whole value moves together.
+unsafe impl<T: Bundle + Send> Send for RcBundle<T> {}
+
+unsafe impl<T: ?Sized + Bundle> Bundle for SendRc<T> {
+    fn pack(&self, packer: &mut Packer) {
+        packer.park(self);
+    }
+}
+
+unsafe impl<T: Bundle> Bundle for [T] {
+    fn pack(&self, packer: &mut Packer) {
+        self.iter().for_each(|item| item.pack(packer));
+    }
+}
+
+unsafe impl<T: Bundle, const N: usize> Bundle for [T; N] {
+    fn pack(&self, packer: &mut Packer) {
+        self.as_slice().pack(packer);
+    }
+}
+
+unsafe impl<T: Bundle> Bundle for Vec<T> {
+    fn pack(&self, packer: &mut Packer) {
+        self.as_slice().pack(packer);
+    }
+}
+
+unsafe impl<T: Bundle> Bundle for std::collections::VecDeque<T> {
+    fn pack(&self, packer: &mut Packer) {
+        self.iter().for_each(|item| item.pack(packer));
+    }
+}
+
+unsafe impl<K: Bundle, V: Bundle, S> Bundle for HashMap<K, V, S> {
+    fn pack(&self, packer: &mut Packer) {
+        self.iter().for_each(|(key, val)| {
+            key.pack(packer);
+            val.pack(packer);
+        });
+    }
+}
+
+unsafe impl<K: Bundle, V: Bundle> Bundle for std::collections::BTreeMap<K, V> {
+    fn pack(&self, packer: &mut Packer) {
+        self.iter().for_each(|(key, val)| {
+            key.pack(packer);
+            val.pack(packer);
+        });
+    }
+}
+
+unsafe impl<T: Bundle> Bundle for Option<T> {
+    fn pack(&self, packer: &mut Packer) {
+        if let Some(val) = self {
+            val.pack(packer);
+        }
+    }
+}
+
+unsafe impl<T: ?Sized + Bundle> Bundle for Box<T> {
+    fn pack(&self, packer: &mut Packer) {
+        (**self).pack(packer);
+    }
+}
+
+/// A value that is mutably borrowed can not be walked, so its `SendRc`s are left out
+/// and `RcBundle::pack` fails if they have clones elsewhere in the bundle.
+unsafe impl<T: Bundle> Bundle for std::cell::RefCell<T> {
+    fn pack(&self, packer: &mut Packer) {
+        if let Ok(val) = self.try_borrow() {
+            val.pack(packer);
+        }
+    }
+}
+
+/// A value that is mutably borrowed can not be walked, so its `SendRc`s are left out
+/// and `RcBundle::pack` fails if they have clones elsewhere in the bundle.
+unsafe impl<T: Bundle> Bundle for crate::RefCell<T> {
+    fn pack(&self, packer: &mut Packer) {
+        if let Ok(val) = self.try_borrow() {
+            val.pack(packer);
+        }
+    }
+}
+
+macro_rules! impl_bundle_for_leaves {
+    ($($ty:ty),*) => {
+        $(
+            unsafe impl Bundle for $ty {
+                #[inline]
+                fn pack(&self, _: &mut Packer) {}
+            }
+        )*
+    };
+}
+
+impl_bundle_for_leaves!(
+    (),
+    bool,
+    char,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    f32,
+    f64,
+    str,
+    String
+);
+
+unsafe impl<T: Copy + Bundle> Bundle for std::cell::Cell<T> {
+    #[inline]
+    fn pack(&self, packer: &mut Packer) {
+        self.get().pack(packer);
+    }
+}
+
+macro_rules! impl_bundle_for_tuples {
+    ($($name:ident)+) => {
+        unsafe impl<$($name: Bundle),+> Bundle for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn pack(&self, packer: &mut Packer) {
+                let ($($name,)+) = self;
+                $($name.pack(packer);)+
+            }
+        }
+    };
+}
+
+impl_bundle_for_tuples!(A);
+impl_bundle_for_tuples!(A B);
+impl_bundle_for_tuples!(A B C);
+impl_bundle_for_tuples!(A B C D);
+
+#[cfg(test)]
+mod test {
+    use super::{Bundle, Packer, RcBundle, SendRc};
+    use crate::RefCell;
+
+    struct Node {
+        val: std::cell::Cell<u32>,
+        children: RefCell<Vec<SendRc<Node>>>,
+    }
+
+    unsafe impl Bundle for Node {
+        fn pack(&self, packer: &mut Packer) {
+            self.val.pack(packer);
+            self.children.pack(packer);
+        }
+    }
+
+    fn node(val: u32) -> SendRc<Node> {
+        SendRc::new(Node {
+            val: std::cell::Cell::new(val),
+            children: RefCell::new(Vec::new()),
+        })
+    }
+
+    fn sum(node: &Node) -> u32 {
+        node.val.get()
+            + node
+                .children
+                .borrow()
+                .iter()
+                .map(|child| sum(child))
+                .sum::<u32>()
+    }
+
+    #[test]
+    fn test_bundle_moves_graph() {
+        let root = node(1);
+        let shared = node(2);
+        root.children.borrow_mut().push(shared.clone());
+        root.children.borrow_mut().push(node(3));
+        root.children.borrow_mut()[1]
+            .children
+            .borrow_mut()
+            .push(shared.clone());
+
+        // `shared` is still held outside of the bundle
+        let root = RcBundle::pack(root).err().unwrap();
+        drop(shared);
+        let bundle = RcBundle::pack(root).ok().unwrap();
+
+        let handle = std::thread::spawn(move || {
+            let root = bundle.unpack();
+            root.children.borrow()[0].val.set(10);
+            assert_eq!(SendRc::strong_count(&root.children.borrow()[0]), 2);
+            sum(&root)
+        });
+        assert_eq!(handle.join().unwrap(), 1 + 10 + 3 + 10);
+    }
+
+    #[test]
+    fn test_cycles_and_owners() {
+        let a = node(1);
+        let b = node(2);
+        a.children.borrow_mut().push(b.clone());
+        b.children.borrow_mut().push(a.clone());
+        drop(b);
+        let bundle = RcBundle::pack(a).ok().unwrap();
+
+        let a = std::thread::spawn(move || {
+            let a = bundle.unpack();
+            assert_eq!(a.children.borrow()[0].children.borrow()[0].val.get(), 1);
+            RcBundle::pack(a).ok().unwrap()
+        })
+        .join()
+        .unwrap()
+        .unpack();
+        assert_eq!(a.children.borrow()[0].val.get(), 2);
+
+        // A handle moved out on its own can not be used on the other thread
+        let lone = SendRc::new(5);
+        let stray = lone.clone();
+        let handle = std::thread::spawn(move || {
+            let used = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| *stray));
+            // Leaks its reference instead of racing with the owner
+            drop(stray);
+            used.is_err()
+        });
+        assert!(handle.join().unwrap());
+        assert_eq!(SendRc::strong_count(&lone), 2);
+        assert!(RcBundle::pack(lone).is_err());
+    }
+}
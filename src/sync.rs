@@ -0,0 +1,14 @@
+//! The atomics behind reference counting, swapped for `loom`'s when the `loom` feature is enabled.
+
+#[cfg(feature = "loom")]
+pub(crate) use ::loom::{
+    hint,
+    sync::{atomic, Mutex},
+    thread,
+};
+#[cfg(not(feature = "loom"))]
+pub(crate) use std::{
+    hint,
+    sync::{atomic, Mutex},
+    thread,
+};
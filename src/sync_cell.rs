@@ -0,0 +1,613 @@
+use std::sync::atomic::{AtomicIsize, Ordering};
+
+use crate::{BorrowError, BorrowMutError};
+
+/// # AtomicBorrowFlag
+/// The thread-safe counterpart of [`BorrowFlag`](crate::BorrowFlag), for tracking the reads and writes
+/// to a value shared between threads.
+///
+/// It follows the same rules: any number of reads or a single write at a time. Since another thread
+/// may change the flag between a check and a mark, checking and marking are done in one atomic step
+/// by `try_borrow` and `try_borrow_mut`.
+///
+/// # Fields
+/// * `inner: std::sync::atomic::AtomicIsize` - The number of reads, or `-1` while written
+///
+/// # Note
+/// Like `BorrowFlag`, it is meant to be added as a field in your struct since it doesn't store the actual value described.
+#[repr(transparent)]
+pub struct AtomicBorrowFlag {
+    inner: AtomicIsize,
+}
+
+impl Default for AtomicBorrowFlag {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AtomicBorrowFlag {
+    /// Initiallizes a new AtomicBorrowFlag with 0 current reads and no current writer
+    pub const fn new() -> Self {
+        Self {
+            inner: AtomicIsize::new(0),
+        }
+    }
+
+    /// Checks if the described value can currently be borrowed immutably.
+    ///
+    /// The answer may already be outdated when it is returned if other threads use the flag.
+    pub fn can_borrow(&self) -> bool {
+        self.inner.load(Ordering::Relaxed) >= 0
+    }
+
+    /// Checks if the described value can currently be borrowed mutably.
+    ///
+    /// The answer may already be outdated when it is returned if other threads use the flag.
+    pub fn can_borrow_mut(&self) -> bool {
+        self.inner.load(Ordering::Relaxed) == 0
+    }
+
+    /// Checks if the described value can be taken ownership of
+    pub fn can_own(&self) -> bool {
+        self.can_borrow_mut()
+    }
+
+    /// Marks the start of a new read if there is no writer.
+    ///
+    /// # Returns
+    /// * `true` - If the read was started
+    /// * `false` - If the value is currently borrowed mutably
+    #[inline]
+    pub fn try_borrow(&self) -> bool {
+        let mut cur = self.inner.load(Ordering::Relaxed);
+        loop {
+            // A negative count means a writer, `isize::MAX` readers can not be counted further
+            if cur < 0 || cur == isize::MAX {
+                return false;
+            }
+            // Acquire synchronises with the Release of the last writer
+            match self.inner.compare_exchange_weak(
+                cur,
+                cur + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(actual) => cur = actual,
+            }
+        }
+    }
+
+    /// Marks the end of an ongoing read started with `try_borrow`
+    #[inline]
+    pub fn drop_borrow(&self) {
+        self.inner.fetch_sub(1, Ordering::Release);
+    }
+
+    /// Marks the start of a write if there are no readers and no writer.
+    ///
+    /// # Returns
+    /// * `true` - If the write was started
+    /// * `false` - If the value is currently borrowed
+    #[inline]
+    pub fn try_borrow_mut(&self) -> bool {
+        self.inner
+            .compare_exchange(0, -1, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    /// Marks the end of a write session started with `try_borrow_mut`
+    #[inline]
+    pub fn drop_borrow_mut(&self) {
+        self.inner.fetch_add(1, Ordering::Release);
+    }
+}
+
+/// An immutable borrow of a `SyncRefCell`, the thread-safe counterpart of [`Ref`](crate::Ref).
+pub struct SyncRef<'a, T: ?Sized> {
+    val: std::ptr::NonNull<T>,
+    flag: &'a AtomicBorrowFlag,
+    _marker: std::marker::PhantomData<&'a T>,
+}
+
+impl<'a, T: ?Sized> Drop for SyncRef<'a, T> {
+    #[inline]
+    fn drop(&mut self) {
+        self.flag.drop_borrow();
+    }
+}
+
+impl<'a, T: ?Sized> std::ops::Deref for SyncRef<'a, T> {
+    type Target = T;
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        // The flag guarantees that there is no `SyncRefMut` while this `SyncRef` is alive
+        unsafe { self.val.as_ref() }
+    }
+}
+
+impl<'a, T: ?Sized> AsRef<T> for SyncRef<'a, T> {
+    fn as_ref(&self) -> &T {
+        std::ops::Deref::deref(self)
+    }
+}
+
+impl<'a, T: ?Sized + std::fmt::Debug> std::fmt::Debug for SyncRef<'a, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + std::fmt::Display> std::fmt::Display for SyncRef<'a, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(&**self, f)
+    }
+}
+
+/// A mutable borrow of a `SyncRefCell`, the thread-safe counterpart of [`RefMut`](crate::RefMut).
+pub struct SyncRefMut<'a, T: ?Sized> {
+    val: std::ptr::NonNull<T>,
+    flag: &'a AtomicBorrowFlag,
+    _marker: std::marker::PhantomData<&'a mut T>,
+}
+
+impl<'a, T> SyncRefMut<'a, T> {
+    pub fn replace(&mut self, val: T) -> T {
+        std::mem::replace(&mut **self, val)
+    }
+}
+
+impl<'a, T: ?Sized> Drop for SyncRefMut<'a, T> {
+    #[inline]
+    fn drop(&mut self) {
+        self.flag.drop_borrow_mut();
+    }
+}
+
+impl<'a, T: ?Sized> std::ops::Deref for SyncRefMut<'a, T> {
+    type Target = T;
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        // The flag guarantees that this `SyncRefMut` is the only borrow
+        unsafe { self.val.as_ref() }
+    }
+}
+
+impl<'a, T: ?Sized> std::ops::DerefMut for SyncRefMut<'a, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { self.val.as_mut() }
+    }
+}
+
+impl<'a, T: ?Sized> AsRef<T> for SyncRefMut<'a, T> {
+    fn as_ref(&self) -> &T {
+        std::ops::Deref::deref(self)
+    }
+}
+
+impl<'a, T: ?Sized> AsMut<T> for SyncRefMut<'a, T> {
+    fn as_mut(&mut self) -> &mut T {
+        std::ops::DerefMut::deref_mut(self)
+    }
+}
+
+impl<'a, T: ?Sized + std::fmt::Debug> std::fmt::Debug for SyncRefMut<'a, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + std::fmt::Display> std::fmt::Display for SyncRefMut<'a, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(&**self, f)
+    }
+}
+
+/// # SyncRefCell
+/// The thread-safe counterpart of [`RefCell`](crate::RefCell): a mutable memory location with
+/// dynamically checked borrow rules that can be shared between threads.
+///
+/// It allows any number of immutable borrows (`SyncRef`) or exactly one mutable borrow (`SyncRefMut`)
+/// at a time, across all threads.
+///
+/// - `try_read` and `try_write` never block, they fail like `RefCell::try_borrow` and
+///   `RefCell::try_borrow_mut` when the borrow rules do not allow the borrow right now.
+/// - `read` and `write` spin, and then yield to other threads, until the borrow is allowed.
+///   They are not fair: a steady stream of readers can keep a writer waiting.
+///
+/// # Examples
+///
+/// ```
+/// use speedy_refs::SyncRefCell;
+/// use std::sync::Arc;
+/// use std::thread;
+///
+/// let counter = Arc::new(SyncRefCell::new(0));
+///
+/// let handles: Vec<_> = (0..4)
+///     .map(|_| {
+///         let counter = counter.clone();
+///         thread::spawn(move || *counter.write() += 1)
+///     })
+///     .collect();
+///
+/// for handle in handles {
+///     handle.join().unwrap();
+/// }
+///
+/// assert_eq!(*counter.try_read().unwrap(), 4);
+/// ```
+pub struct SyncRefCell<T> {
+    flag: AtomicBorrowFlag,
+    val: std::cell::UnsafeCell<T>,
+}
+
+impl<T> SyncRefCell<T> {
+    /// Creates a new `SyncRefCell` containing the given value.
+    pub const fn new(val: T) -> Self {
+        Self {
+            flag: AtomicBorrowFlag::new(),
+            val: std::cell::UnsafeCell::new(val),
+        }
+    }
+
+    /// Tries to borrow the value immutably without blocking.
+    /// Returns a [`BorrowError`] if the value is currently borrowed mutably.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::SyncRefCell;
+    ///
+    /// let cell = SyncRefCell::new(42);
+    ///
+    /// let first = cell.try_read().unwrap();
+    /// let second = cell.try_read().unwrap();
+    /// assert_eq!(*first + *second, 84);
+    ///
+    /// assert!(cell.try_write().is_err());
+    /// ```
+    #[inline]
+    pub fn try_read(&self) -> Result<SyncRef<'_, T>, BorrowError> {
+        if self.flag.try_borrow() {
+            Ok(SyncRef {
+                val: self.as_non_null(),
+                flag: &self.flag,
+                _marker: std::marker::PhantomData,
+            })
+        } else {
+            Err(BorrowError::untracked())
+        }
+    }
+
+    /// Tries to borrow the value mutably without blocking.
+    /// Returns a [`BorrowMutError`] if the value is currently borrowed (either mutably or immutably).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use speedy_refs::SyncRefCell;
+    ///
+    /// let cell = SyncRefCell::new(42);
+    ///
+    /// let mut writer = cell.try_write().unwrap();
+    /// *writer = 13;
+    ///
+    /// assert!(cell.try_read().is_err());
+    /// assert!(cell.try_write().is_err());
+    /// ```
+    #[inline]
+    pub fn try_write(&self) -> Result<SyncRefMut<'_, T>, BorrowMutError> {
+        if self.flag.try_borrow_mut() {
+            Ok(SyncRefMut {
+                val: self.as_non_null(),
+                flag: &self.flag,
+                _marker: std::marker::PhantomData,
+            })
+        } else {
+            Err(BorrowMutError::untracked())
+        }
+    }
+
+    /// Borrows the value immutably, waiting for the current writer, if any, to finish.
+    ///
+    /// # Deadlocks
+    /// If the current thread itself holds a `SyncRefMut` to this cell.
+    pub fn read(&self) -> SyncRef<'_, T> {
+        let mut backoff = Backoff::new();
+        loop {
+            match self.try_read() {
+                Ok(r) => return r,
+                Err(_) => backoff.wait(),
+            }
+        }
+    }
+
+    /// Borrows the value mutably, waiting for the current readers or writer, if any, to finish.
+    ///
+    /// # Deadlocks
+    /// If the current thread itself holds a `SyncRef` or a `SyncRefMut` to this cell.
+    pub fn write(&self) -> SyncRefMut<'_, T> {
+        let mut backoff = Backoff::new();
+        loop {
+            match self.try_write() {
+                Ok(r) => return r,
+                Err(_) => backoff.wait(),
+            }
+        }
+    }
+
+    /// Returns a mutable reference to the value.
+    ///
+    /// No borrow can be alive since this requires exclusive access to the `SyncRefCell`.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.val.get_mut()
+    }
+
+    /// Consumes the `SyncRefCell` and returns the wrapped value.
+    ///
+    /// # Panics
+    /// If a borrow of the value was leaked (e.g with `std::mem::forget`) and is therefore still outstanding.
+    pub fn take(self) -> T {
+        if self.flag.can_own() {
+            self.val.into_inner()
+        } else {
+            panic!("{}", BorrowMutError::untracked())
+        }
+    }
+
+    /// Replaces the wrapped value with `val`, waiting for the current borrows to finish,
+    /// and returns the old value.
+    pub fn replace(&self, val: T) -> T {
+        self.write().replace(val)
+    }
+
+    #[inline(always)]
+    fn as_non_null(&self) -> std::ptr::NonNull<T> {
+        // `UnsafeCell::get` never returns null
+        unsafe { std::ptr::NonNull::new_unchecked(self.val.get()) }
+    }
+}
+
+impl<T: Default> Default for SyncRefCell<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: Clone> Clone for SyncRefCell<T> {
+    fn clone(&self) -> Self {
+        SyncRefCell::new(self.read().clone())
+    }
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for SyncRefCell<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.try_read() {
+            Ok(val) => f.debug_tuple("SyncRefCell").field(&*val).finish(),
+            Err(_) => f.write_str("SyncRefCell(<borrowed>)"),
+        }
+    }
+}
+
+/// # speedy_refs::ArcCell
+/// An atomically reference-counted cell that allows for interior mutability across threads.
+///
+/// This is the multi-threaded counterpart of [`RcCell`](crate::RcCell): a concise version of
+/// `speedy_refs::Arc<SyncRefCell<T>>`. Multiple `ArcCell` instances, possibly on different threads,
+/// share ownership of the same value, which can be read by many or written by one at a time.
+///
+/// # Example
+/// ```
+/// use speedy_refs::ArcCell;
+/// use std::thread;
+///
+/// let names = ArcCell::new(Vec::new());
+///
+/// let handle = {
+///     let names = names.clone();
+///     thread::spawn(move || names.write().push("speedy"))
+/// };
+/// handle.join().unwrap();
+///
+/// names.with_mut(|names| names.push("refs"));
+///
+/// assert_eq!(names.with(|names| names.join("_")), "speedy_refs");
+/// ```
+pub struct ArcCell<T> {
+    inner: crate::Arc<SyncRefCell<T>>,
+}
+
+impl<T> ArcCell<T> {
+    /// Creates a new `ArcCell<T>` instance containing the provided value.
+    pub fn new(value: T) -> ArcCell<T> {
+        Self {
+            inner: crate::Arc::new(SyncRefCell::new(value)),
+        }
+    }
+
+    /// Tries to borrow the value immutably without blocking.
+    /// Returns a [`BorrowError`] if the value is currently borrowed mutably.
+    #[inline]
+    pub fn try_read(&self) -> Result<SyncRef<'_, T>, BorrowError> {
+        self.inner.try_read()
+    }
+
+    /// Tries to borrow the value mutably without blocking.
+    /// Returns a [`BorrowMutError`] if the value is currently borrowed (either mutably or immutably).
+    #[inline]
+    pub fn try_write(&self) -> Result<SyncRefMut<'_, T>, BorrowMutError> {
+        self.inner.try_write()
+    }
+
+    /// Borrows the value immutably, waiting for the current writer, if any, to finish.
+    ///
+    /// # Deadlocks
+    /// If the current thread itself holds a `SyncRefMut` to this value.
+    #[inline]
+    pub fn read(&self) -> SyncRef<'_, T> {
+        self.inner.read()
+    }
+
+    /// Borrows the value mutably, waiting for the current readers or writer, if any, to finish.
+    ///
+    /// # Deadlocks
+    /// If the current thread itself holds a `SyncRef` or a `SyncRefMut` to this value.
+    #[inline]
+    pub fn write(&self) -> SyncRefMut<'_, T> {
+        self.inner.write()
+    }
+
+    /// Calls `f` with a shared reference to the value and returns its result.
+    ///
+    /// The value is borrowed, as with [`read`](Self::read), only for the duration of the call.
+    pub fn with<R, F: FnOnce(&T) -> R>(&self, f: F) -> R {
+        f(&self.read())
+    }
+
+    /// Calls `f` with a mutable reference to the value and returns its result.
+    ///
+    /// The value is borrowed, as with [`write`](Self::write), only for the duration of the call.
+    pub fn with_mut<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> R {
+        f(&mut self.write())
+    }
+}
+
+impl<T> Clone for ArcCell<T> {
+    /// Clones the `ArcCell<T>` instance, creating a new instance that shares ownership of the same value.
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T: Default> Default for ArcCell<T> {
+    fn default() -> Self {
+        ArcCell::new(T::default())
+    }
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for ArcCell<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.try_read() {
+            Ok(val) => std::fmt::Debug::fmt(&*val, f),
+            Err(_) => f.write_str("<borrowed>"),
+        }
+    }
+}
+
+impl<T> From<T> for ArcCell<T> {
+    fn from(value: T) -> Self {
+        ArcCell::new(value)
+    }
+}
+
+impl<T: serde::Serialize> serde::Serialize for ArcCell<T> {
+    fn serialize<S: serde::Serializer>(&self, sz: S) -> Result<S::Ok, S::Error> {
+        T::serialize(&self.read(), sz)
+    }
+}
+
+impl<'d, T: serde::Deserialize<'d>> serde::Deserialize<'d> for ArcCell<T> {
+    fn deserialize<D: serde::Deserializer<'d>>(dz: D) -> Result<Self, D::Error> {
+        let value = T::deserialize(dz)?;
+        Ok(ArcCell::new(value))
+    }
+}
+
+/// Spins for a few rounds and then yields to the scheduler while waiting for a borrow.
+struct Backoff {
+    spins: u32,
+}
+
+impl Backoff {
+    const SPIN_LIMIT: u32 = 6;
+
+    fn new() -> Self {
+        Self { spins: 0 }
+    }
+
+    #[inline]
+    fn wait(&mut self) {
+        if self.spins < Self::SPIN_LIMIT {
+            for _ in 0..1 << self.spins {
+                std::hint::spin_loop();
+            }
+            self.spins += 1;
+        } else {
+            std::thread::yield_now();
+        }
+    }
+}
+
+unsafe impl<T: Send> Send for SyncRefCell<T> {}
+unsafe impl<T: Send + Sync> Sync for SyncRefCell<T> {}
+unsafe impl<'a, T: ?Sized + Sync> Send for SyncRef<'a, T> {}
+unsafe impl<'a, T: ?Sized + Sync> Sync for SyncRef<'a, T> {}
+unsafe impl<'a, T: ?Sized + Send> Send for SyncRefMut<'a, T> {}
+unsafe impl<'a, T: ?Sized + Sync> Sync for SyncRefMut<'a, T> {}
+
+mod test {
+    #[test]
+    fn test_readers_and_writer() {
+        let cell = super::SyncRefCell::new(vec![1]);
+        let first = cell.try_read().unwrap();
+        let second = cell.read();
+        assert!(cell.try_write().is_err());
+        drop(first);
+        drop(second);
+
+        cell.write().push(2);
+        assert_eq!(*cell.read(), vec![1, 2]);
+        assert_eq!(cell.replace(vec![]), vec![1, 2]);
+        assert!(cell.take().is_empty());
+    }
+
+    #[test]
+    fn test_concurrent_writes() {
+        let cell = std::sync::Arc::new(super::SyncRefCell::new((0usize, 0usize)));
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let cell = cell.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..1000 {
+                        let mut pair = cell.write();
+                        pair.0 += 1;
+                        pair.1 += 1;
+                        drop(pair);
+
+                        // Readers always see a consistent pair
+                        let pair = cell.read();
+                        assert_eq!(pair.0, pair.1);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(*cell.read(), (4000, 4000));
+    }
+
+    #[test]
+    fn test_arc_cell() {
+        let cell = super::ArcCell::new(1);
+        let clone = cell.clone();
+        let handle = std::thread::spawn(move || clone.with_mut(|val| *val += 1));
+        handle.join().unwrap();
+
+        let reader = cell.read();
+        assert!(cell.try_write().is_err());
+        assert_eq!(format!("{:?}", cell), "2");
+        drop(reader);
+
+        let writer = cell.write();
+        assert!(cell.try_read().is_err());
+        assert_eq!(format!("{:?}", cell), "<borrowed>");
+        drop(writer);
+        assert_eq!(cell.with(|val| *val * 10), 20);
+    }
+}
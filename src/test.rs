@@ -1,4 +1,7 @@
 use crate::Borrow;
+use std::alloc::{AllocError, Allocator, Global, Layout};
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 #[derive(Debug, PartialEq, Eq)]
 struct Data(String, usize, bool, Vec<Self>);
@@ -51,3 +54,135 @@ fn print<T: std::fmt::Debug>(data: &T) {
     // do something
     println!("{:?}", data);
 }
+
+#[test]
+fn test_pointer_sizes() {
+    use std::mem::size_of;
+    const WORD: usize = size_of::<usize>();
+
+    assert_eq!(size_of::<Option<crate::Rc<u8>>>(), WORD);
+    assert_eq!(size_of::<Option<crate::Weak<u8>>>(), WORD);
+    assert_eq!(size_of::<Option<crate::Arc<u8>>>(), WORD);
+    assert_eq!(size_of::<Option<crate::arc::Weak<u8>>>(), WORD);
+    assert_eq!(size_of::<Option<crate::HeapCell<u8>>>(), WORD);
+    assert_eq!(
+        size_of::<Option<crate::Rc<u8, &std::alloc::System>>>(),
+        2 * WORD
+    );
+    assert_eq!(
+        size_of::<Option<crate::Arc<u8, std::alloc::System>>>(),
+        WORD
+    );
+    assert_eq!(size_of::<Option<crate::Rc<str>>>(), 2 * WORD);
+    assert_eq!(size_of::<Option<crate::Arc<[u8]>>>(), 2 * WORD);
+}
+
+#[test]
+fn test_pointers_are_covariant() {
+    fn rc<'a>(rc: crate::Rc<&'static str>) -> crate::Rc<&'a str> {
+        rc
+    }
+    fn weak<'a>(weak: crate::Weak<&'static str>) -> crate::Weak<&'a str> {
+        weak
+    }
+    fn arc<'a>(arc: crate::Arc<&'static str>) -> crate::Arc<&'a str> {
+        arc
+    }
+    fn arc_weak<'a>(weak: crate::arc::Weak<&'static str>) -> crate::arc::Weak<&'a str> {
+        weak
+    }
+    fn heap_cell<'a>(cell: crate::HeapCell<&'static str>) -> crate::HeapCell<&'a str> {
+        cell
+    }
+
+    assert_eq!(*rc(crate::Rc::new("rc")), "rc");
+    assert!(weak(crate::Weak::new()).upgrade().is_none());
+    assert_eq!(*arc(crate::Arc::new("arc")), "arc");
+    assert!(arc_weak(crate::arc::Weak::new()).upgrade().is_none());
+    let cell = heap_cell(crate::HeapCell::new("cell"));
+    assert_eq!(unsafe { *cell.as_ref() }, "cell");
+    unsafe { cell.drop_n_dealloc() };
+}
+
+#[test]
+fn test_may_dangle() {
+    // Dropping a reference never reads through it, so the pointers may outlive what they point to
+    let (rc, weak, arc);
+    let value = String::from("dangling");
+    rc = crate::Rc::new(&value);
+    weak = crate::Rc::downgrade(&rc);
+    arc = crate::Arc::new(&value);
+    assert_eq!(**rc, **arc);
+    assert!(weak.upgrade().is_some());
+}
+
+/// An allocator that counts the live allocations made through it and its clones.
+#[derive(Clone, Default)]
+pub(crate) struct Counting(std::sync::Arc<AtomicUsize>);
+
+impl Counting {
+    pub(crate) fn live(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+unsafe impl Allocator for Counting {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        Global.allocate(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+        Global.deallocate(ptr, layout)
+    }
+}
+
+/// An allocator that never has memory to give.
+pub(crate) struct Exhausted;
+
+unsafe impl Allocator for Exhausted {
+    fn allocate(&self, _: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        Err(AllocError)
+    }
+
+    unsafe fn deallocate(&self, _: NonNull<u8>, _: Layout) {
+        unreachable!("nothing was allocated")
+    }
+}
+
+#[test]
+fn test_heap_cell_allocator() {
+    let alloc = Counting::default();
+    let cell = crate::HeapCell::new_in(String::from("cell"), alloc.clone());
+    let clone = cell.clone_inner();
+    assert_eq!(alloc.live(), 2);
+    unsafe {
+        assert_eq!(clone.take(), "cell");
+        clone.dealloc();
+        cell.drop_n_dealloc();
+    }
+    assert_eq!(alloc.live(), 0);
+
+    let zst = crate::HeapCell::new(());
+    unsafe { zst.drop_n_dealloc() };
+    assert!(crate::HeapCell::try_new_in(1u64, Exhausted).is_err());
+}
+
+#[test]
+fn test_try_new() {
+    let cell = crate::HeapCell::<[u32; 64]>::try_new_zeroed().unwrap();
+    unsafe {
+        let cell = cell.assume_init();
+        assert_eq!(*cell.as_ref(), [0; 64]);
+        cell.drop_n_dealloc();
+    }
+
+    let borrow = crate::Borrow::try_new(vec![1]).unwrap();
+    borrow.clone().push(2);
+    assert_eq!(*borrow, [1, 2]);
+    let cell = crate::RcCell::try_new(1).unwrap();
+    *cell.clone().borrow_mut() += 1;
+    assert_eq!(*cell.borrow(), 2);
+    assert_eq!(*crate::Reon::try_new("static").unwrap(), "static");
+}
@@ -0,0 +1,490 @@
+use std::alloc::Layout;
+use std::marker::PhantomData;
+use std::ptr::NonNull;
+
+use crate::{arc, rc, Arc, Rc};
+
+/// # speedy_refs::HeaderSlice
+/// A header followed by a slice, stored inline in one allocation.
+///
+/// It is the value behind [`ThinArc`] and [`ThinRc`], which also store the length of the slice
+/// in it so that they only need a thin pointer to find the whole value.
+#[repr(C)]
+pub struct HeaderSlice<H, T: ?Sized> {
+    /// The header in front of the slice.
+    pub header: H,
+    len: usize,
+    /// The elements of the slice.
+    pub slice: T,
+}
+
+impl<H, T> HeaderSlice<H, [T]> {
+    /// Returns the layout of a `HeaderSlice<H, [T]>` holding `len` elements.
+    ///
+    /// Built from the `repr(C)` fields one by one, so that it matches `Layout::for_value`.
+    /// `HeaderSlice<H, [T; 0]>` would add its trailing padding before the elements.
+    fn layout(len: usize) -> Layout {
+        Layout::new::<H>()
+            .extend(Layout::new::<usize>())
+            .and_then(|(layout, _)| layout.extend(Layout::array::<T>(len)?))
+            .expect("slice is too large")
+            .0
+            .pad_to_align()
+    }
+
+    /// Writes the header, the length and the items into the uninitialised `HeaderSlice` at `this`.
+    ///
+    /// # Panics
+    /// If `items` does not yield exactly `len` items, or panics itself. The items written so far
+    /// are dropped and `dealloc` is called to free the allocation.
+    ///
+    /// # Safety
+    /// `this` must be valid for writes of a `HeaderSlice` with `len` elements, and `dealloc` must
+    /// free the allocation holding it.
+    unsafe fn init(
+        this: *mut Self,
+        header: H,
+        len: usize,
+        mut items: impl Iterator<Item = T>,
+        dealloc: impl FnMut(),
+    ) {
+        let mut guard = InitGuard {
+            elements: std::ptr::addr_of_mut!((*this).slice).cast::<T>(),
+            written: 0,
+            dealloc,
+        };
+        while guard.written < len {
+            let item = items
+                .next()
+                .expect("ExactSizeIterator yielded fewer items than its length");
+            guard.elements.add(guard.written).write(item);
+            guard.written += 1;
+        }
+        assert!(
+            items.next().is_none(),
+            "ExactSizeIterator yielded more items than its length"
+        );
+        std::mem::forget(guard);
+
+        std::ptr::addr_of_mut!((*this).len).write(len);
+        std::ptr::addr_of_mut!((*this).header).write(header);
+        // The allocation is freed with the layout of the value, it must be the one it was allocated with
+        debug_assert_eq!(Layout::for_value(&*this), Self::layout(len));
+    }
+}
+
+/// Drops the elements written by `HeaderSlice::init` and frees their allocation if it unwinds.
+struct InitGuard<T, F: FnMut()> {
+    elements: *mut T,
+    written: usize,
+    dealloc: F,
+}
+
+impl<T, F: FnMut()> Drop for InitGuard<T, F> {
+    fn drop(&mut self) {
+        unsafe {
+            std::ptr::drop_in_place(std::ptr::slice_from_raw_parts_mut(
+                self.elements,
+                self.written,
+            ))
+        };
+        (self.dealloc)();
+    }
+}
+
+impl<H: std::fmt::Debug, T: std::fmt::Debug> std::fmt::Debug for HeaderSlice<H, [T]> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HeaderSlice")
+            .field("header", &self.header)
+            .field("slice", &&self.slice)
+            .finish()
+    }
+}
+
+/// # speedy_refs::ThinArc
+/// An [`Arc`] to a [`HeaderSlice`] that is only one machine word wide.
+///
+/// An `Arc<[T]>` is a fat pointer holding the length of the slice next to the address.
+/// `ThinArc` keeps the header, both counts, the length and the elements in one allocation
+/// and only points to it, which halves the size of the pointer. `Option<ThinArc<H, T>>` is one word wide as well.
+///
+/// # Examples
+///
+/// ```
+/// use speedy_refs::ThinArc;
+///
+/// let node = ThinArc::from_header_and_iter("call", vec![1, 2, 3]);
+/// let clone = ThinArc::clone(&node);
+///
+/// assert_eq!(clone.header, "call");
+/// assert_eq!(clone.slice, [1, 2, 3]);
+/// assert_eq!(std::mem::size_of::<ThinArc<&str, i32>>(), std::mem::size_of::<usize>());
+/// ```
+pub struct ThinArc<H, T> {
+    ptr: NonNull<arc::Inner<HeaderSlice<H, [T; 0]>>>,
+    _marker: PhantomData<Arc<HeaderSlice<H, [T]>>>,
+}
+
+impl<H, T> ThinArc<H, T> {
+    /// Creates a new `ThinArc` holding `header` followed by the items of `items`.
+    ///
+    /// # Panics
+    /// If the iterator does not yield exactly as many items as its `len` reports.
+    pub fn from_header_and_iter<I>(header: H, items: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let items = items.into_iter();
+        let len = items.len();
+        unsafe {
+            let layout = HeaderSlice::<H, [T]>::layout(len);
+            let inner = arc::Inner::alloc_for(layout, |mem| {
+                std::ptr::slice_from_raw_parts_mut(mem.cast::<T>(), len) as *mut _
+            });
+            let value = arc::Inner::value_ptr(inner.as_ptr());
+            HeaderSlice::init(value, header, len, items, || {
+                arc::Inner::dealloc_for(inner, layout)
+            });
+            Self::from_arc(Arc::from_inner_ptr(inner.as_ptr()))
+        }
+    }
+
+    /// Turns an `Arc` to a `HeaderSlice` into a `ThinArc` without touching the counts.
+    pub fn from_arc(arc: Arc<HeaderSlice<H, [T]>>) -> Self {
+        let inner = Arc::into_inner_ptr(arc);
+        Self {
+            // Dropping the length, it is stored in the `HeaderSlice`
+            ptr: unsafe { NonNull::new_unchecked(inner as *mut _) },
+            _marker: PhantomData,
+        }
+    }
+
+    /// Turns a `ThinArc` into a regular, fat, `Arc` without touching the counts.
+    pub fn into_arc(this: Self) -> Arc<HeaderSlice<H, [T]>> {
+        let this = std::mem::ManuallyDrop::new(this);
+        unsafe { Arc::from_inner_ptr(this.fat_ptr()) }
+    }
+
+    /// Calls `f` with the `ThinArc` seen as a regular `Arc`.
+    pub fn with_arc<R>(&self, f: impl FnOnce(&Arc<HeaderSlice<H, [T]>>) -> R) -> R {
+        // The `Arc` borrows the strong reference of `self`, so it must not be dropped
+        let arc = std::mem::ManuallyDrop::new(unsafe { Arc::from_inner_ptr(self.fat_ptr()) });
+        f(&arc)
+    }
+
+    /// Returns the number of `Arc` and `ThinArc` pointers to this value.
+    pub fn strong_count(this: &Self) -> usize {
+        this.with_arc(Arc::strong_count)
+    }
+
+    /// Returns `true` if both `ThinArc`s point to the same allocation.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        this.ptr == other.ptr
+    }
+
+    /// Restores the length of the slice into the pointer.
+    #[inline(always)]
+    fn fat_ptr(&self) -> *mut arc::Inner<HeaderSlice<H, [T]>> {
+        let thin = self.ptr.as_ptr();
+        // The length is initialised before a `ThinArc` is created and never changes
+        let len = unsafe { (*arc::Inner::value_ptr(thin)).len };
+        std::ptr::slice_from_raw_parts_mut(thin.cast::<T>(), len) as *mut _
+    }
+}
+
+impl<H, T> Clone for ThinArc<H, T> {
+    fn clone(&self) -> Self {
+        self.with_arc(|arc| std::mem::forget(Arc::clone(arc)));
+        Self {
+            ptr: self.ptr,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<H, T> std::ops::Deref for ThinArc<H, T> {
+    type Target = HeaderSlice<H, [T]>;
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        // The value stays alive as long as this strong reference
+        unsafe { &*arc::Inner::value_ptr(self.fat_ptr()) }
+    }
+}
+
+impl<H, T> Drop for ThinArc<H, T> {
+    fn drop(&mut self) {
+        drop(unsafe { Arc::from_inner_ptr(self.fat_ptr()) });
+    }
+}
+
+impl<H: std::fmt::Debug, T: std::fmt::Debug> std::fmt::Debug for ThinArc<H, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<H, T> From<Arc<HeaderSlice<H, [T]>>> for ThinArc<H, T> {
+    fn from(arc: Arc<HeaderSlice<H, [T]>>) -> Self {
+        Self::from_arc(arc)
+    }
+}
+
+impl<H, T> From<ThinArc<H, T>> for Arc<HeaderSlice<H, [T]>> {
+    fn from(thin: ThinArc<H, T>) -> Self {
+        ThinArc::into_arc(thin)
+    }
+}
+
+unsafe impl<H: Sync + Send, T: Sync + Send> Sync for ThinArc<H, T> {}
+unsafe impl<H: Sync + Send, T: Sync + Send> Send for ThinArc<H, T> {}
+
+/// # speedy_refs::ThinRc
+/// An [`Rc`] to a [`HeaderSlice`] that is only one machine word wide.
+///
+/// The single-threaded counterpart of [`ThinArc`]: the header, both counts, the length and the
+/// elements live in one allocation and the pointer is thin.
+///
+/// # Examples
+///
+/// ```
+/// use speedy_refs::ThinRc;
+///
+/// let names = ThinRc::from_header_and_iter(2u32, ["speedy", "refs"]);
+///
+/// assert_eq!(names.header, 2);
+/// assert_eq!(names.slice.join("_"), "speedy_refs");
+/// assert_eq!(std::mem::size_of::<Option<ThinRc<u32, &str>>>(), std::mem::size_of::<usize>());
+/// ```
+pub struct ThinRc<H, T> {
+    ptr: NonNull<rc::Inner<HeaderSlice<H, [T; 0]>>>,
+    _marker: PhantomData<Rc<HeaderSlice<H, [T]>>>,
+}
+
+impl<H, T> ThinRc<H, T> {
+    /// Creates a new `ThinRc` holding `header` followed by the items of `items`.
+    ///
+    /// # Panics
+    /// If the iterator does not yield exactly as many items as its `len` reports.
+    pub fn from_header_and_iter<I>(header: H, items: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let items = items.into_iter();
+        let len = items.len();
+        unsafe {
+            let layout = HeaderSlice::<H, [T]>::layout(len);
+            let inner = rc::Inner::alloc_for(layout, |mem| {
+                std::ptr::slice_from_raw_parts_mut(mem.cast::<T>(), len) as *mut _
+            });
+            let value = rc::Inner::value_ptr(inner.as_ptr());
+            HeaderSlice::init(value, header, len, items, || {
+                rc::Inner::dealloc_for(inner, layout)
+            });
+            Self::from_rc(Rc::from_inner_ptr(inner.as_ptr()))
+        }
+    }
+
+    /// Turns an `Rc` to a `HeaderSlice` into a `ThinRc` without touching the counts.
+    pub fn from_rc(rc: Rc<HeaderSlice<H, [T]>>) -> Self {
+        let inner = Rc::into_inner_ptr(rc);
+        Self {
+            // Dropping the length, it is stored in the `HeaderSlice`
+            ptr: unsafe { NonNull::new_unchecked(inner as *mut _) },
+            _marker: PhantomData,
+        }
+    }
+
+    /// Turns a `ThinRc` into a regular, fat, `Rc` without touching the counts.
+    pub fn into_rc(this: Self) -> Rc<HeaderSlice<H, [T]>> {
+        let this = std::mem::ManuallyDrop::new(this);
+        unsafe { Rc::from_inner_ptr(this.fat_ptr()) }
+    }
+
+    /// Calls `f` with the `ThinRc` seen as a regular `Rc`.
+    pub fn with_rc<R>(&self, f: impl FnOnce(&Rc<HeaderSlice<H, [T]>>) -> R) -> R {
+        // The `Rc` borrows the strong reference of `self`, so it must not be dropped
+        let rc = std::mem::ManuallyDrop::new(unsafe { Rc::from_inner_ptr(self.fat_ptr()) });
+        f(&rc)
+    }
+
+    /// Returns the number of `Rc` and `ThinRc` pointers to this value.
+    pub fn strong_count(this: &Self) -> usize {
+        this.with_rc(Rc::strong_count)
+    }
+
+    /// Returns `true` if both `ThinRc`s point to the same allocation.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        this.ptr == other.ptr
+    }
+
+    /// Restores the length of the slice into the pointer.
+    #[inline]
+    fn fat_ptr(&self) -> *mut rc::Inner<HeaderSlice<H, [T]>> {
+        let thin = self.ptr.as_ptr();
+        // The length is initialised before a `ThinRc` is created and never changes
+        let len = unsafe { (*rc::Inner::value_ptr(thin)).len };
+        std::ptr::slice_from_raw_parts_mut(thin.cast::<T>(), len) as *mut _
+    }
+}
+
+impl<H, T> Clone for ThinRc<H, T> {
+    fn clone(&self) -> Self {
+        self.with_rc(|rc| std::mem::forget(Rc::clone(rc)));
+        Self {
+            ptr: self.ptr,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<H, T> std::ops::Deref for ThinRc<H, T> {
+    type Target = HeaderSlice<H, [T]>;
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        // The value stays alive as long as this strong reference
+        unsafe { &*rc::Inner::value_ptr(self.fat_ptr()) }
+    }
+}
+
+impl<H, T> Drop for ThinRc<H, T> {
+    fn drop(&mut self) {
+        drop(unsafe { Rc::from_inner_ptr(self.fat_ptr()) });
+    }
+}
+
+impl<H: std::fmt::Debug, T: std::fmt::Debug> std::fmt::Debug for ThinRc<H, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<H, T> From<Rc<HeaderSlice<H, [T]>>> for ThinRc<H, T> {
+    fn from(rc: Rc<HeaderSlice<H, [T]>>) -> Self {
+        Self::from_rc(rc)
+    }
+}
+
+impl<H, T> From<ThinRc<H, T>> for Rc<HeaderSlice<H, [T]>> {
+    fn from(thin: ThinRc<H, T>) -> Self {
+        ThinRc::into_rc(thin)
+    }
+}
+
+mod test {
+    #[test]
+    fn test_thin_arc() {
+        let thin = super::ThinArc::from_header_and_iter(String::from("header"), 0..4u32);
+        let clone = thin.clone();
+        assert_eq!(super::ThinArc::strong_count(&thin), 2);
+        assert!(super::ThinArc::ptr_eq(&thin, &clone));
+
+        let handle = std::thread::spawn(move || clone.slice.iter().sum::<u32>());
+        assert_eq!(handle.join().unwrap(), 6);
+        assert_eq!(super::ThinArc::strong_count(&thin), 1);
+
+        let fat = super::ThinArc::into_arc(thin);
+        assert_eq!(fat.header, "header");
+        let thin = super::ThinArc::from_arc(fat);
+        assert_eq!(
+            format!("{:?}", thin),
+            r#"HeaderSlice { header: "header", slice: [0, 1, 2, 3] }"#
+        );
+    }
+
+    #[test]
+    fn test_thin_rc_drops() {
+        use std::cell::Cell;
+        struct Counted<'a>(&'a Cell<usize>);
+        impl Drop for Counted<'_> {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let drops = Cell::new(0);
+        let thin =
+            super::ThinRc::from_header_and_iter(Counted(&drops), (0..3).map(|_| Counted(&drops)));
+        let clone = thin.clone();
+        assert_eq!(clone.slice.len(), 3);
+        drop(thin);
+        assert_eq!(drops.get(), 0);
+        drop(clone);
+        assert_eq!(drops.get(), 4);
+
+        let empty = super::ThinRc::<u8, [u64; 4]>::from_header_and_iter(1, std::iter::empty());
+        assert!(empty.slice.is_empty());
+        assert_eq!(super::ThinRc::strong_count(&empty), 1);
+    }
+
+    #[test]
+    fn test_over_aligned_header() {
+        use std::alloc::Layout;
+
+        #[derive(Debug, PartialEq)]
+        #[repr(align(16))]
+        struct Header(u8);
+
+        let layout = super::HeaderSlice::<Header, [u8]>::layout(8);
+        let arc = super::ThinArc::from_header_and_iter(Header(1), 0..8u8);
+        let rc = super::ThinRc::from_header_and_iter(Header(2), 0..8u8);
+        // The allocation is freed with the layout of the value, so it must match the one allocated
+        assert_eq!(Layout::for_value(&*arc), layout);
+        assert_eq!(Layout::for_value(&*rc), layout);
+        assert_eq!(layout.size(), 32);
+
+        assert_eq!(arc.header, Header(1));
+        assert_eq!(rc.slice, [0, 1, 2, 3, 4, 5, 6, 7]);
+        let weak = arc.with_arc(super::Arc::downgrade);
+        drop(arc);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_init_panic_drops_items() {
+        use std::cell::Cell;
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+        struct Counted<'a>(&'a Cell<usize>);
+        impl Drop for Counted<'_> {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let drops = Cell::new(0);
+        let items = (0..4).map(|i| {
+            assert!(i < 2, "iterator panicked");
+            Counted(&drops)
+        });
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            super::ThinRc::from_header_and_iter(Counted(&drops), items)
+        }));
+        assert!(result.is_err());
+        assert_eq!(drops.get(), 3);
+
+        // An iterator yielding fewer items than its length
+        struct Short<'a>(usize, &'a Cell<usize>);
+        impl<'a> Iterator for Short<'a> {
+            type Item = Counted<'a>;
+            fn next(&mut self) -> Option<Self::Item> {
+                (self.0 > 1).then(|| {
+                    self.0 -= 1;
+                    Counted(self.1)
+                })
+            }
+        }
+        impl ExactSizeIterator for Short<'_> {
+            fn len(&self) -> usize {
+                self.0
+            }
+        }
+
+        drops.set(0);
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            super::ThinArc::from_header_and_iter(Counted(&drops), Short(3, &drops))
+        }));
+        assert!(result.is_err());
+        assert_eq!(drops.get(), 3);
+    }
+}
@@ -0,0 +1,304 @@
+//! Concurrency tests for `speedy_refs::Arc`, and for `speedy_refs::AtomicPtr` and
+//! `speedy_refs::AtomicArc` whose reader registration runs on the same atomics.
+//!
+//! Every test runs the same scenario in three ways:
+//! - `cargo test --test arc` runs it once on real threads,
+//! - `cargo miri test --test arc` also checks it for data races, leaks and use after free,
+//! - `cargo test --release --features loom --test arc` explores every interleaving of its threads with `loom`,
+//!   up to 4 preemptions for the `AtomicPtr` and `AtomicArc` ones.
+
+use speedy_refs::arc::{UniqueArc, Weak};
+use speedy_refs::{Arc, AtomicArc, AtomicPtr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(feature = "loom")]
+use loom::{cell::UnsafeCell, model, thread};
+#[cfg(not(feature = "loom"))]
+use std::thread;
+
+#[cfg(not(feature = "loom"))]
+fn model<F: Fn()>(f: F) {
+    f()
+}
+
+/// `model` with at most 4 preemptions per interleaving, unless `LOOM_MAX_PREEMPTIONS` says otherwise.
+///
+/// The retire lists of `AtomicPtr` and `AtomicArc` writers make an unbounded search run for hours.
+#[cfg(feature = "loom")]
+fn bounded_model<F: Fn() + Sync + Send + 'static>(f: F) {
+    let mut builder = loom::model::Builder::new();
+    builder.preemption_bound.get_or_insert(4);
+    builder.check(f)
+}
+
+#[cfg(not(feature = "loom"))]
+use model as bounded_model;
+
+/// `std::cell::UnsafeCell` with the access API of `loom::cell::UnsafeCell`.
+#[cfg(not(feature = "loom"))]
+struct UnsafeCell<T>(std::cell::UnsafeCell<T>);
+
+#[cfg(not(feature = "loom"))]
+impl<T> UnsafeCell<T> {
+    fn new(val: T) -> Self {
+        Self(std::cell::UnsafeCell::new(val))
+    }
+
+    fn with<R>(&self, f: impl FnOnce(*const T) -> R) -> R {
+        f(self.0.get())
+    }
+
+    fn with_mut<R>(&self, f: impl FnOnce(*mut T) -> R) -> R {
+        f(self.0.get())
+    }
+}
+
+/// A value whose reads and final write are tracked for races, and whose drops are counted.
+///
+/// `drop` writes to the value, so it races with any read that does not happen before it.
+struct Tracked {
+    val: UnsafeCell<usize>,
+    drops: std::sync::Arc<AtomicUsize>,
+}
+
+impl Tracked {
+    fn new(drops: &std::sync::Arc<AtomicUsize>) -> Self {
+        Self {
+            val: UnsafeCell::new(7),
+            drops: drops.clone(),
+        }
+    }
+
+    fn get(&self) -> usize {
+        self.val.with(|val| unsafe { *val })
+    }
+
+    fn set(&mut self, new: usize) {
+        self.val.with_mut(|val| unsafe { *val = new })
+    }
+}
+
+impl Clone for Tracked {
+    fn clone(&self) -> Self {
+        Self {
+            val: UnsafeCell::new(self.get()),
+            drops: self.drops.clone(),
+        }
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        self.set(0);
+        self.drops.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+unsafe impl Send for Tracked {}
+unsafe impl Sync for Tracked {}
+
+#[test]
+fn clones_dropped_on_other_threads() {
+    model(|| {
+        let drops = std::sync::Arc::new(AtomicUsize::new(0));
+        let arc = Arc::new(Tracked::new(&drops));
+
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let arc = arc.clone();
+                thread::spawn(move || assert_eq!(arc.get(), 7))
+            })
+            .collect();
+
+        assert_eq!(arc.get(), 7);
+        drop(arc);
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+    });
+}
+
+#[test]
+fn weak_upgrade_races_last_drop() {
+    model(|| {
+        let drops = std::sync::Arc::new(AtomicUsize::new(0));
+        let arc = Arc::new(Tracked::new(&drops));
+        let weak = Arc::downgrade(&arc);
+
+        let handle = thread::spawn(move || {
+            if let Some(arc) = weak.upgrade() {
+                assert_eq!(arc.get(), 7);
+            }
+        });
+
+        drop(arc);
+        handle.join().unwrap();
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+    });
+}
+
+#[test]
+fn weak_dropped_on_other_thread() {
+    model(|| {
+        let drops = std::sync::Arc::new(AtomicUsize::new(0));
+        let arc = Arc::new(Tracked::new(&drops));
+        let weak = Arc::downgrade(&arc);
+        let handle = thread::spawn(move || drop(weak));
+
+        drop(arc);
+        handle.join().unwrap();
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+    });
+}
+
+#[test]
+fn get_mut_races_upgrade() {
+    model(|| {
+        let drops = std::sync::Arc::new(AtomicUsize::new(0));
+        let mut arc = Arc::new(Tracked::new(&drops));
+        let weak = Arc::downgrade(&arc);
+
+        let handle = thread::spawn(move || {
+            let arc = weak.upgrade().unwrap();
+            assert_eq!(arc.get(), 7);
+        });
+
+        // Never unique while the `Weak` or its upgrade is alive
+        if let Some(val) = Arc::get_mut(&mut arc) {
+            val.set(7);
+        }
+
+        handle.join().unwrap();
+        Arc::get_mut(&mut arc).unwrap().set(8);
+        assert_eq!(arc.get(), 8);
+    });
+}
+
+#[test]
+fn get_mut_races_downgrade() {
+    model(|| {
+        let drops = std::sync::Arc::new(AtomicUsize::new(0));
+        let mut arc = Arc::new(Tracked::new(&drops));
+        let other = arc.clone();
+
+        let handle = thread::spawn(move || {
+            let weak: Weak<Tracked> = Arc::downgrade(&other);
+            drop(other);
+            weak.upgrade().map(|arc| arc.get())
+        });
+
+        // Unique only once the other thread has released both of its pointers
+        if let Some(val) = Arc::get_mut(&mut arc) {
+            val.set(9);
+        }
+
+        assert_eq!(handle.join().unwrap(), Some(7));
+        drop(arc);
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+    });
+}
+
+#[test]
+fn make_mut_races_reader() {
+    model(|| {
+        let drops = std::sync::Arc::new(AtomicUsize::new(0));
+        let mut arc = Arc::new(Tracked::new(&drops));
+        let other = arc.clone();
+
+        let handle = thread::spawn(move || other.get());
+
+        // Writes to a copy while `other` may still be reading the original
+        Arc::make_mut(&mut arc).set(2);
+        assert_eq!(arc.get(), 2);
+
+        assert_eq!(handle.join().unwrap(), 7);
+    });
+}
+
+#[test]
+fn into_inner_exactly_once() {
+    model(|| {
+        let drops = std::sync::Arc::new(AtomicUsize::new(0));
+        let arc = Arc::new(Tracked::new(&drops));
+        let other = arc.clone();
+
+        let handle = thread::spawn(move || Arc::into_inner(other).map(|val| val.get()));
+        let here = Arc::into_inner(arc).map(|val| val.get());
+        let there = handle.join().unwrap();
+
+        assert!(here.is_some() != there.is_some());
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+    });
+}
+
+#[test]
+fn unique_shared_while_weak_upgrades() {
+    model(|| {
+        let drops = std::sync::Arc::new(AtomicUsize::new(0));
+        let mut unique = UniqueArc::new(Tracked::new(&drops));
+        let weak = UniqueArc::downgrade(&unique);
+
+        let handle = thread::spawn(move || weak.upgrade().map(|arc| arc.get()));
+
+        // Upgrading before `into_shared` fails, after it sees the write
+        unique.set(3);
+        let arc = UniqueArc::into_shared(unique);
+
+        assert!(matches!(handle.join().unwrap(), None | Some(3)));
+        drop(arc);
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+    });
+}
+
+#[test]
+fn atomic_ptr_two_writers_one_reader() {
+    bounded_model(|| {
+        let drops = std::sync::Arc::new(AtomicUsize::new(0));
+        let cell = std::sync::Arc::new(AtomicPtr::new(Tracked::new(&drops)));
+
+        let reader = {
+            let cell = cell.clone();
+            thread::spawn(move || assert_eq!(cell.load().get(), 7))
+        };
+        let writer = {
+            let (cell, drops) = (cell.clone(), drops.clone());
+            thread::spawn(move || cell.store(Box::new(Tracked::new(&drops))))
+        };
+
+        // A reader that registered between the two writers' flips must still be counted
+        cell.store(Box::new(Tracked::new(&drops)));
+
+        reader.join().unwrap();
+        writer.join().unwrap();
+        // The replaced values are freed once their readers are gone, at the latest with the cell
+        drop(cell);
+        assert_eq!(drops.load(Ordering::Relaxed), 3);
+    });
+}
+
+#[test]
+fn atomic_arc_two_writers_one_reader() {
+    bounded_model(|| {
+        let drops = std::sync::Arc::new(AtomicUsize::new(0));
+        let cell = std::sync::Arc::new(AtomicArc::new(Tracked::new(&drops)));
+
+        // The guard's allocation must still be counted when it is turned into an `Arc`
+        let reader = {
+            let cell = cell.clone();
+            thread::spawn(move || cell.load().to_arc().get())
+        };
+        let writer = {
+            let (cell, drops) = (cell.clone(), drops.clone());
+            thread::spawn(move || cell.store(Arc::new(Tracked::new(&drops))))
+        };
+
+        cell.store(Arc::new(Tracked::new(&drops)));
+
+        writer.join().unwrap();
+        assert_eq!(reader.join().unwrap(), 7);
+        drop(cell);
+        assert_eq!(drops.load(Ordering::Relaxed), 3);
+    });
+}